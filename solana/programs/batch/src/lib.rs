@@ -1,5 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use factory_stub::{PairRecord, PAIR_RECORD_SEED};
+use pair_stub::{program::PairStub, PairState};
 
 declare_id!("2uDexdyb8hj7R1nrR9ESEci831Urbag5Rq12TzgZEAZq");
 
@@ -12,26 +14,76 @@ pub const DENOM_XL: u64 = 2_000_000_000;      // 200 units
 
 pub const POOL_SEED: &[u8] = b"pool";
 pub const CONFIG_SEED: &[u8] = b"config";
+pub const TICKET_SEED: &[u8] = b"ticket";
+pub const FEE_VAULT_SEED: &[u8] = b"fee_vault";
+
+/// How long a deposit waits for mixing before its depositor may cancel it (48 hours),
+/// matching the Soroban contract's default.
+pub const DEPOSIT_EXPIRY_SECONDS: i64 = 48 * 60 * 60;
+
+/// Upper bound for a pool's fee (10%), matching the Soroban contract.
+pub const MAX_FEE_BPS: u16 = 1_000;
+const BPS_DENOMINATOR: u64 = 10_000;
 
 #[program]
 pub mod batch {
     use super::*;
 
     /// Initialize global config (owner, factory, router). One-time.
+    /// The payer becomes the owner and initially holds every role.
     pub fn initialize_config(
         ctx: Context<InitializeConfig>,
         factory: Pubkey,
         router: Pubkey,
     ) -> Result<()> {
         let config = &mut ctx.accounts.config;
-        config.owner = ctx.accounts.payer.key();
+        let owner = ctx.accounts.payer.key();
+        config.owner = owner;
+        config.pending_owner = Pubkey::default();
+        config.admin = owner;
+        config.keeper = owner;
+        config.fee_manager = owner;
+        config.pauser = owner;
+        config.paused = false;
         config.factory = factory;
         config.router = router;
         config.bump = ctx.bumps.config;
         Ok(())
     }
 
-    /// Create a CoinJoin pool for a specific denomination and mint.
+    /// Nominate a new owner; ownership moves once they call `accept_owner`. Owner only.
+    pub fn propose_owner(ctx: Context<OwnerOnly>, new_owner: Pubkey) -> Result<()> {
+        ctx.accounts.config.pending_owner = new_owner;
+        Ok(())
+    }
+
+    /// Complete a transfer started by `propose_owner`. Pending owner only.
+    pub fn accept_owner(ctx: Context<AcceptOwner>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.owner = ctx.accounts.new_owner.key();
+        config.pending_owner = Pubkey::default();
+        Ok(())
+    }
+
+    /// Assign a role to an account, replacing its previous holder. Owner only.
+    pub fn set_role(ctx: Context<OwnerOnly>, role: Role, account: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        match role {
+            Role::Admin => config.admin = account,
+            Role::Keeper => config.keeper = account,
+            Role::FeeManager => config.fee_manager = account,
+            Role::Pauser => config.pauser = account,
+        }
+        Ok(())
+    }
+
+    /// Stop or resume deposits and mixing. Pauser only.
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+        Ok(())
+    }
+
+    /// Create a CoinJoin pool for a specific denomination and mint, with its vaults and fee vault. Admin only.
     pub fn init_pool(
         ctx: Context<InitPool>,
         denomination: u64,
@@ -42,11 +94,16 @@ pub mod batch {
         require!(is_supported_denom(denomination), BatchError::UnsupportedDenomination);
         require!(min_pool_size >= 2, BatchError::InvalidConfig);
         require!(max_pool_size >= min_pool_size, BatchError::InvalidConfig);
+        require!(fee_bps <= MAX_FEE_BPS, BatchError::InvalidConfig);
+        require_keys_neq!(ctx.accounts.mint.key(), ctx.accounts.output_mint.key(), BatchError::InvalidConfig);
 
         let pool = &mut ctx.accounts.pool;
         pool.config = ctx.accounts.config.key();
         pool.mint = ctx.accounts.mint.key();
         pool.vault = ctx.accounts.vault.key();
+        pool.output_mint = ctx.accounts.output_mint.key();
+        pool.output_vault = ctx.accounts.output_vault.key();
+        pool.fee_vault = ctx.accounts.fee_vault.key();
         pool.denomination = denomination;
         pool.fee_bps = fee_bps;
         pool.min_pool_size = min_pool_size;
@@ -54,12 +111,58 @@ pub mod batch {
         pool.current_pool_size = 0;
         pool.total_deposits = 0;
         pool.total_withdrawals = 0;
+        pool.total_fees_collected = 0;
+        pool.total_fees_withdrawn = 0;
         pool.bump = ctx.bumps.pool;
         Ok(())
     }
 
-    /// Deposit funds into the pool vault; records participant count.
-    pub fn deposit(ctx: Context<Deposit>) -> Result<()> {
+    /// Update a pool's fee. Fee manager only.
+    pub fn set_pool_fee(ctx: Context<SetPoolFee>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, BatchError::InvalidConfig);
+        ctx.accounts.pool.fee_bps = fee_bps;
+        Ok(())
+    }
+
+    /// Move `amount` of a pool's collected fees to `destination`. Owner only; allowed while
+    /// paused so collected fees can always be reclaimed.
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        require!(
+            amount > 0 && amount <= ctx.accounts.fee_vault.amount,
+            BatchError::InsufficientFees
+        );
+
+        let pool = &ctx.accounts.pool;
+        let seeds = &[POOL_SEED, &pool.denomination.to_le_bytes(), &[pool.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.fee_vault.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, amount)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.total_fees_withdrawn = pool
+            .total_fees_withdrawn
+            .checked_add(amount)
+            .ok_or(BatchError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Deposit funds into the pool vault and record a ticket binding the deposit's
+    /// commitment to the token account that will receive its payout. `min_out` is the
+    /// smallest payout, in the output mint, the depositor accepts; the pool fee in force
+    /// now is snapshotted so a later `set_pool_fee` does not apply to this deposit.
+    pub fn deposit(ctx: Context<Deposit>, commitment: [u8; 32], min_out: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, BatchError::Paused);
+        require!(commitment != [0u8; 32], BatchError::InvalidCommitment);
         let pool = &mut ctx.accounts.pool;
         require!(ctx.accounts.mint.key() == pool.mint, BatchError::MintMismatch);
         require!(ctx.accounts.vault.key() == pool.vault, BatchError::VaultMismatch);
@@ -82,17 +185,74 @@ pub mod batch {
             .checked_add(1)
             .ok_or(BatchError::MathOverflow)?;
 
+        let timestamp = Clock::get()?.unix_timestamp;
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.pool = pool.key();
+        ticket.commitment = commitment;
+        ticket.depositor = ctx.accounts.depositor.key();
+        ticket.recipient = ctx.accounts.recipient_token.key();
+        ticket.min_out = min_out;
+        ticket.fee_bps = pool.fee_bps;
+        ticket.timestamp = timestamp;
+        ticket.expiry = timestamp
+            .checked_add(DEPOSIT_EXPIRY_SECONDS)
+            .ok_or(BatchError::MathOverflow)?;
+        ticket.bump = ctx.bumps.ticket;
+
         Ok(())
     }
 
-    /// Execute mixing: transfer one denomination to each recipient token account provided in remaining accounts.
-    /// Remaining accounts must be SPL token accounts with mint == pool.mint.
+    /// Return an expired, unmixed deposit to its depositor and close its ticket.
+    /// Depositor only; allowed while paused so funds can always be reclaimed.
+    pub fn cancel_deposit(ctx: Context<CancelDeposit>) -> Result<()> {
+        require!(
+            Clock::get()?.unix_timestamp >= ctx.accounts.ticket.expiry,
+            BatchError::TicketNotExpired
+        );
+
+        let pool = &ctx.accounts.pool;
+        let denom = pool.denomination;
+        let seeds = &[POOL_SEED, &denom.to_le_bytes(), &[pool.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.depositor_token.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, denom)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.current_pool_size = pool
+            .current_pool_size
+            .checked_sub(1)
+            .ok_or(BatchError::MathOverflow)?;
+
+        Ok(())
+    }
+
+    /// Execute mixing: skim each ticket's fee, at the rate snapshotted when it was deposited, into the
+    /// fee vault, swap the rest in one aggregated CPI into `pair_stub::swap`, then pay an equal share of
+    /// the output mint to the recipient bound to each ticket and close it.
+    /// Remaining accounts are `(ticket, recipient token account, depositor)` triples; each ticket must
+    /// belong to this pool and be unexpired, and its rent is returned to its depositor. The swap output
+    /// is quoted off the pair's reserves and fee; every recipient's share must reach the largest `min_out`
+    /// among the tickets. The pair must be the one `factory_stub` created for the pool's mints. Keeper only.
     pub fn execute_mixing(ctx: Context<ExecuteMixing>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, BatchError::Paused);
+
         // Coerce the context lifetimes so the typed accounts and remaining accounts share one scope.
         let ctx: anchor_lang::context::Context<'_, '_, '_, '_, ExecuteMixing<'_>> =
             unsafe { std::mem::transmute(ctx) };
 
-        let recipient_count = ctx.remaining_accounts.len() as u32;
+        let participants = ctx.remaining_accounts.chunks_exact(3);
+        require!(participants.remainder().is_empty(), BatchError::ParticipantMismatch);
+        let recipient_count = participants.len() as u32;
 
         let pool_values = {
             let pool = &ctx.accounts.pool;
@@ -103,34 +263,122 @@ pub mod batch {
                 pool.max_pool_size,
                 pool.current_pool_size,
                 pool.mint,
+                pool.output_mint,
             )
         };
 
-        let (denom, pool_bump, min_pool_size, max_pool_size, current_pool_size, pool_mint) =
+        let (denom, pool_bump, min_pool_size, max_pool_size, current_pool_size, pool_mint, output_mint) =
             pool_values;
 
         require!(recipient_count >= min_pool_size, BatchError::NotEnoughParticipants);
         require!(recipient_count <= max_pool_size, BatchError::TooManyParticipants);
-        require!(recipient_count == current_pool_size, BatchError::ParticipantMismatch);
-
-        let seeds = &[POOL_SEED, &denom.to_le_bytes(), &[pool_bump]];
-        let signer_seeds = &[&seeds[..]];
+        require!(recipient_count <= current_pool_size, BatchError::ParticipantMismatch);
 
-        let vault_info = ctx.accounts.vault.to_account_info();
         let pool_info = ctx.accounts.pool.to_account_info();
-        let token_program_info = ctx.accounts.token_program.to_account_info();
-
-        for recipient_info in ctx.remaining_accounts.iter() {
-            // Validate each recipient is an SPL token account for the same mint.
+        let pool_key = pool_info.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        // Consume every ticket before moving funds. A ticket listed twice fails to load
+        // the second time because it was closed the first time.
+        let mut recipients = Vec::with_capacity(recipient_count as usize);
+        let mut fee = 0u64;
+        let mut min_payout = 0u64;
+        for participant in participants {
+            let (ticket_info, recipient_info, depositor_info) =
+                (&participant[0], &participant[1], &participant[2]);
+
+            // Only pay recipients recorded by a live ticket of this pool.
+            let ticket = Account::<DepositTicket>::try_from(ticket_info)
+                .map_err(|_| BatchError::InvalidTicket)?;
+            require_keys_eq!(ticket.pool, pool_key, BatchError::InvalidTicket);
+            require!(now < ticket.expiry, BatchError::TicketExpired);
+            require_keys_eq!(recipient_info.key(), ticket.recipient, BatchError::InvalidRecipient);
+            require_keys_eq!(depositor_info.key(), ticket.depositor, BatchError::InvalidTicket);
+
+            // The recipient must still be an SPL token account for the output mint.
             let recipient_token = Account::<TokenAccount>::try_from(recipient_info)
                 .map_err(|_| BatchError::InvalidRecipient)?;
             require!(
-                recipient_token.mint == pool_mint,
+                recipient_token.mint == output_mint,
                 BatchError::InvalidRecipient
             );
 
+            // Equal shares must satisfy the strictest depositor.
+            min_payout = min_payout.max(ticket.min_out);
+            let ticket_fee = denom
+                .checked_mul(ticket.fee_bps.into())
+                .ok_or(BatchError::MathOverflow)?
+                / BPS_DENOMINATOR;
+            fee = fee.checked_add(ticket_fee).ok_or(BatchError::MathOverflow)?;
+
+            ticket.close(depositor_info.clone())?;
+            recipients.push(recipient_info);
+        }
+
+        let seeds = &[POOL_SEED, &denom.to_le_bytes(), &[pool_bump]];
+        let signer_seeds = &[&seeds[..]];
+        let token_program_info = ctx.accounts.token_program.to_account_info();
+
+        let total_input = denom
+            .checked_mul(recipient_count.into())
+            .ok_or(BatchError::MathOverflow)?;
+        let swap_input = total_input - fee;
+
+        if fee > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.vault.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
+                authority: pool_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                token_program_info.clone(),
+                cpi_accounts,
+                signer_seeds,
+            );
+            token::transfer(cpi_ctx, fee)?;
+        }
+
+        // Quote the aggregated swap off the pair's reserves and fee; the pair pulls the
+        // pooled input from the vault and pays the output into the output vault.
+        let zero_for_one = ctx.accounts.pair.token_0 == pool_mint;
+        let quoted_output = ctx.accounts.pair.get_amount_out(swap_input, zero_for_one)?;
+        require!(quoted_output > 0, BatchError::InsufficientLiquidity);
+
+        let output_before = ctx.accounts.output_vault.amount;
+        let swap_accounts = pair_stub::cpi::accounts::Swap {
+            pair: ctx.accounts.pair.to_account_info(),
+            vault_0: ctx.accounts.pair_vault_0.to_account_info(),
+            vault_1: ctx.accounts.pair_vault_1.to_account_info(),
+            user_token_in: ctx.accounts.vault.to_account_info(),
+            user_token_out: ctx.accounts.output_vault.to_account_info(),
+            user: pool_info.clone(),
+            token_program: token_program_info.clone(),
+        };
+        pair_stub::cpi::swap(
+            CpiContext::new_with_signer(
+                ctx.accounts.pair_program.to_account_info(),
+                swap_accounts,
+                signer_seeds,
+            ),
+            swap_input,
+            quoted_output,
+        )?;
+
+        // Split what actually arrived; rounding dust stays in the output vault.
+        ctx.accounts.output_vault.reload()?;
+        let received = ctx
+            .accounts
+            .output_vault
+            .amount
+            .checked_sub(output_before)
+            .ok_or(BatchError::MathOverflow)?;
+        let payout = received / u64::from(recipient_count);
+        require!(payout > 0 && payout >= min_payout, BatchError::SlippageExceeded);
+
+        let output_vault_info = ctx.accounts.output_vault.to_account_info();
+        for recipient_info in recipients {
             let cpi_accounts = Transfer {
-                from: vault_info.clone(),
+                from: output_vault_info.clone(),
                 to: recipient_info.clone(),
                 authority: pool_info.clone(),
             };
@@ -139,7 +387,7 @@ pub mod batch {
                 cpi_accounts,
                 signer_seeds,
             );
-            token::transfer(cpi_ctx, denom)?;
+            token::transfer(cpi_ctx, payout)?;
         }
 
         let pool = &mut ctx.accounts.pool;
@@ -147,7 +395,11 @@ pub mod batch {
             .total_withdrawals
             .checked_add(recipient_count.into())
             .ok_or(BatchError::MathOverflow)?;
-        pool.current_pool_size = 0;
+        pool.current_pool_size = current_pool_size - recipient_count;
+        pool.total_fees_collected = pool
+            .total_fees_collected
+            .checked_add(fee)
+            .ok_or(BatchError::MathOverflow)?;
 
         Ok(())
     }
@@ -168,6 +420,42 @@ pub struct InitializeConfig<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct OwnerOnly<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = owner @ BatchError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOwner<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = config.pending_owner == new_owner.key() @ BatchError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    pub new_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = pauser @ BatchError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    pub pauser: Signer<'info>,
+}
+
 #[derive(Accounts)]
 #[instruction(denomination: u64)]
 pub struct InitPool<'info> {
@@ -175,7 +463,8 @@ pub struct InitPool<'info> {
     pub payer: Signer<'info>,
     #[account(
         seeds = [CONFIG_SEED],
-        bump = config.bump
+        bump = config.bump,
+        constraint = config.admin == payer.key() @ BatchError::Unauthorized
     )]
     pub config: Account<'info, Config>,
     #[account(
@@ -194,6 +483,25 @@ pub struct InitPool<'info> {
         associated_token::authority = pool
     )]
     pub vault: Account<'info, TokenAccount>,
+    /// Mint that deposits are swapped into and paid out in.
+    pub output_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = output_mint,
+        associated_token::authority = pool
+    )]
+    pub output_vault: Account<'info, TokenAccount>,
+    /// Pool-owned token account collecting the pool fee, in the input mint.
+    #[account(
+        init,
+        payer = payer,
+        seeds = [FEE_VAULT_SEED, pool.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = pool
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, anchor_spl::associated_token::AssociatedToken>,
@@ -201,9 +509,54 @@ pub struct InitPool<'info> {
 }
 
 #[derive(Accounts)]
-pub struct Deposit<'info> {
+pub struct SetPoolFee<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = fee_manager @ BatchError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    #[account(mut, has_one = config)]
+    pub pool: Account<'info, Pool>,
+    pub fee_manager: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = owner @ BatchError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    pub owner: Signer<'info>,
+    #[account(mut, has_one = config, has_one = fee_vault @ BatchError::VaultMismatch)]
+    pub pool: Account<'info, Pool>,
     #[account(mut)]
+    pub fee_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = destination.mint == pool.mint @ BatchError::MintMismatch
+    )]
+    pub destination: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(commitment: [u8; 32])]
+pub struct Deposit<'info> {
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, has_one = config)]
     pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = depositor,
+        space = 8 + DepositTicket::LEN,
+        seeds = [TICKET_SEED, pool.key().as_ref(), commitment.as_ref()],
+        bump
+    )]
+    pub ticket: Account<'info, DepositTicket>,
     pub mint: Account<'info, Mint>,
     #[account(
         mut,
@@ -219,34 +572,118 @@ pub struct Deposit<'info> {
         constraint = depositor_token.owner == depositor.key()
     )]
     pub depositor_token: Account<'info, TokenAccount>,
+    /// Token account that receives this deposit's payout, in the pool's output mint.
+    #[account(constraint = recipient_token.mint == pool.output_mint @ BatchError::InvalidRecipient)]
+    pub recipient_token: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ExecuteMixing<'info> {
+pub struct CancelDeposit<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        seeds = [TICKET_SEED, pool.key().as_ref(), ticket.commitment.as_ref()],
+        bump = ticket.bump,
+        has_one = pool @ BatchError::InvalidTicket,
+        has_one = depositor @ BatchError::Unauthorized,
+        close = depositor
+    )]
+    pub ticket: Account<'info, DepositTicket>,
+    #[account(
+        mut,
+        constraint = vault.key() == pool.vault @ BatchError::VaultMismatch
+    )]
+    pub vault: Account<'info, TokenAccount>,
     #[account(mut)]
+    pub depositor: Signer<'info>,
+    #[account(
+        mut,
+        constraint = depositor_token.mint == pool.mint @ BatchError::MintMismatch,
+        constraint = depositor_token.owner == depositor.key()
+    )]
+    pub depositor_token: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteMixing<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = keeper @ BatchError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    pub keeper: Signer<'info>,
+    #[account(mut, has_one = config)]
     pub pool: Account<'info, Pool>,
     #[account(
         mut,
         constraint = vault.key() == pool.vault,
         constraint = vault.mint == pool.mint
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.output_vault @ BatchError::VaultMismatch)]
+    pub output_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.fee_vault @ BatchError::VaultMismatch)]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
     #[account(address = pool.mint)]
     pub mint: Account<'info, Mint>,
+    /// Pair trading the pool's input mint against its output mint.
+    #[account(
+        mut,
+        constraint = (pair.token_0 == pool.mint && pair.token_1 == pool.output_mint)
+            || (pair.token_1 == pool.mint && pair.token_0 == pool.output_mint) @ BatchError::PairMismatch
+    )]
+    pub pair: Box<Account<'info, PairState>>,
+    /// The factory's record for the pair's mints; the pair must be the one it created.
+    #[account(
+        seeds = [PAIR_RECORD_SEED, pair.token_0.as_ref(), pair.token_1.as_ref()],
+        bump = pair_record.bump,
+        seeds::program = factory_stub::ID,
+        constraint = pair_record.pair == pair.key() @ BatchError::PairMismatch
+    )]
+    pub pair_record: Box<Account<'info, PairRecord>>,
+    #[account(mut, address = pair.vault_0 @ BatchError::VaultMismatch)]
+    pub pair_vault_0: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pair.vault_1 @ BatchError::VaultMismatch)]
+    pub pair_vault_1: Box<Account<'info, TokenAccount>>,
+    pub pair_program: Program<'info, PairStub>,
     pub token_program: Program<'info, Token>,
 }
 
 #[account]
 pub struct Config {
     pub owner: Pubkey,
+    /// Nominated by `propose_owner`; `Pubkey::default()` when no transfer is pending.
+    pub pending_owner: Pubkey,
+    /// Creates pools.
+    pub admin: Pubkey,
+    /// Executes mixing.
+    pub keeper: Pubkey,
+    /// Sets pool fees.
+    pub fee_manager: Pubkey,
+    /// Pauses deposits and mixing.
+    pub pauser: Pubkey,
+    pub paused: bool,
     pub factory: Pubkey,
     pub router: Pubkey,
     pub bump: u8,
 }
 
 impl Config {
-    pub const LEN: usize = 32 + 32 + 32 + 1;
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 32 + 32 + 1 + 32 + 32 + 1;
+}
+
+/// Permissions the owner can assign to separate accounts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Keeper,
+    FeeManager,
+    Pauser,
 }
 
 #[account]
@@ -254,6 +691,12 @@ pub struct Pool {
     pub config: Pubkey,
     pub mint: Pubkey,
     pub vault: Pubkey,
+    /// Mint that deposits are swapped into and paid out in.
+    pub output_mint: Pubkey,
+    /// Pool-owned token account receiving the swap output.
+    pub output_vault: Pubkey,
+    /// Pool-owned token account holding collected fees, in the input mint.
+    pub fee_vault: Pubkey,
     pub denomination: u64,
     pub fee_bps: u16,
     pub min_pool_size: u32,
@@ -261,11 +704,37 @@ pub struct Pool {
     pub current_pool_size: u32,
     pub total_deposits: u64,
     pub total_withdrawals: u64,
+    /// Fees skimmed into the fee vault by `execute_mixing`, over the pool's lifetime.
+    pub total_fees_collected: u64,
+    /// Fees moved out of the fee vault by `withdraw_fees`, over the pool's lifetime.
+    pub total_fees_withdrawn: u64,
     pub bump: u8,
 }
 
 impl Pool {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 2 + 4 + 4 + 4 + 8 + 8 + 1;
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 32 + 32 + 8 + 2 + 4 + 4 + 4 + 8 + 8 + 8 + 8 + 1;
+}
+
+/// One pending deposit, created by `deposit` and closed when it is mixed or cancelled.
+#[account]
+pub struct DepositTicket {
+    pub pool: Pubkey,
+    pub commitment: [u8; 32],
+    pub depositor: Pubkey,
+    /// Token account that receives the payout.
+    pub recipient: Pubkey,
+    /// Smallest payout, in the output mint, the depositor accepts.
+    pub min_out: u64,
+    /// Pool fee when the deposit was made; mixing skims at this rate.
+    pub fee_bps: u16,
+    pub timestamp: i64,
+    /// Mixing rejects the ticket from this time on; the depositor may cancel instead.
+    pub expiry: i64,
+    pub bump: u8,
+}
+
+impl DepositTicket {
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 8 + 2 + 8 + 8 + 1;
 }
 
 #[error_code]
@@ -288,6 +757,26 @@ pub enum BatchError {
     VaultMismatch,
     #[msg("Invalid recipient account")]
     InvalidRecipient,
+    #[msg("Signer does not hold the required role")]
+    Unauthorized,
+    #[msg("Program is paused")]
+    Paused,
+    #[msg("Commitment must be non-zero")]
+    InvalidCommitment,
+    #[msg("Deposit ticket does not belong to this pool or depositor")]
+    InvalidTicket,
+    #[msg("Deposit ticket has expired")]
+    TicketExpired,
+    #[msg("Deposit ticket has not expired yet")]
+    TicketNotExpired,
+    #[msg("Pair does not trade the pool's mints")]
+    PairMismatch,
+    #[msg("Pair reserves cannot fill the swap")]
+    InsufficientLiquidity,
+    #[msg("Payout below the requested minimum")]
+    SlippageExceeded,
+    #[msg("Fee vault holds less than the requested amount")]
+    InsufficientFees,
 }
 
 fn is_supported_denom(amount: u64) -> bool {
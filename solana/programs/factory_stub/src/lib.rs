@@ -1,50 +1,261 @@
 use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{Mint, Token},
+};
+use pair_stub::program::PairStub;
+
+use crate::program::FactoryStub;
 
 declare_id!("uY7scRK6DgtK7Ww9udtDiny7fpyEF324C78PXHnemKP");
 
+pub const ALL_PAIRS_SEED: &[u8] = b"all_pairs";
+pub const PAIR_RECORD_SEED: &[u8] = b"pair";
+pub use pair_stub::FACTORY_AUTHORITY_SEED;
+
+/// Capacity of the `all_pairs` registry.
+pub const MAX_PAIRS: usize = 64;
+
+// `pair_stub` only accepts `init_pair` signed by this program's authority PDA, so its
+// copy of the factory program id must be the one declared above.
+const _: () = {
+    let (ours, theirs) = (ID.to_bytes(), pair_stub::FACTORY_PROGRAM_ID.to_bytes());
+    let mut i = 0;
+    while i < 32 {
+        assert!(ours[i] == theirs[i], "pair_stub::FACTORY_PROGRAM_ID does not match the factory program id");
+        i += 1;
+    }
+};
+
 #[program]
 pub mod factory_stub {
     use super::*;
 
-    /// Registers a pair address for the two token mints. Idempotent.
-    pub fn set_pair(ctx: Context<SetPair>, pair_address: Pubkey) -> Result<()> {
+    /// Create the `all_pairs` registry. One-time and restricted to the program's upgrade
+    /// authority, which pays and becomes the factory admin.
+    pub fn init_factory(ctx: Context<InitFactory>) -> Result<()> {
+        let all_pairs = &mut ctx.accounts.all_pairs;
+        all_pairs.admin = ctx.accounts.payer.key();
+        all_pairs.pairs = Vec::new();
+        all_pairs.bump = ctx.bumps.all_pairs;
+        Ok(())
+    }
+
+    /// Create a `pair_stub` pair for two mints and register it.
+    /// Mints must be passed sorted (`mint_0 < mint_1`) so each pair of tokens has a single
+    /// canonical pair; creating the same pair twice fails. The payer funds the accounts; the
+    /// factory authority PDA signs the CPI and becomes the pair's fee authority.
+    pub fn create_pair(ctx: Context<CreatePair>, fee_bps: u16) -> Result<()> {
+        let mint_0 = ctx.accounts.mint_0.key();
+        let mint_1 = ctx.accounts.mint_1.key();
+        require_keys_neq!(mint_0, mint_1, FactoryError::IdenticalMints);
+        require!(mint_0 < mint_1, FactoryError::UnsortedMints);
+        require!(
+            ctx.accounts.all_pairs.pairs.len() < MAX_PAIRS,
+            FactoryError::RegistryFull
+        );
+
+        let seeds = &[FACTORY_AUTHORITY_SEED, &[ctx.bumps.factory_authority]];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = pair_stub::cpi::accounts::InitPair {
+            factory_authority: ctx.accounts.factory_authority.to_account_info(),
+            payer: ctx.accounts.payer.to_account_info(),
+            mint_0: ctx.accounts.mint_0.to_account_info(),
+            mint_1: ctx.accounts.mint_1.to_account_info(),
+            pair: ctx.accounts.pair.to_account_info(),
+            vault_0: ctx.accounts.vault_0.to_account_info(),
+            vault_1: ctx.accounts.vault_1.to_account_info(),
+            lp_mint: ctx.accounts.lp_mint.to_account_info(),
+            locked_lp: ctx.accounts.locked_lp.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+            rent: ctx.accounts.rent.to_account_info(),
+        };
+        pair_stub::cpi::init_pair(
+            CpiContext::new_with_signer(ctx.accounts.pair_program.to_account_info(), cpi_accounts, signer),
+            fee_bps,
+        )?;
+
+        let pair = ctx.accounts.pair.key();
         let record = &mut ctx.accounts.pair_record;
-        record.token_a = ctx.accounts.token_a.key();
-        record.token_b = ctx.accounts.token_b.key();
-        record.pair = pair_address;
+        record.token_0 = mint_0;
+        record.token_1 = mint_1;
+        record.pair = pair;
+        record.index = ctx.accounts.all_pairs.pairs.len() as u32;
         record.bump = ctx.bumps.pair_record;
+
+        ctx.accounts.all_pairs.pairs.push(pair);
         Ok(())
     }
+
+    /// Update the swap fee of a pair created by this factory. Factory admin only.
+    pub fn set_pair_fee(ctx: Context<SetPairFee>, fee_bps: u16) -> Result<()> {
+        let seeds = &[FACTORY_AUTHORITY_SEED, &[ctx.bumps.factory_authority]];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = pair_stub::cpi::accounts::SetFee {
+            pair: ctx.accounts.pair.to_account_info(),
+            authority: ctx.accounts.factory_authority.to_account_info(),
+        };
+        pair_stub::cpi::set_fee(
+            CpiContext::new_with_signer(ctx.accounts.pair_program.to_account_info(), cpi_accounts, signer),
+            fee_bps,
+        )
+    }
+
+    /// Return the registered pair for two mints, in either order.
+    pub fn get_pair(ctx: Context<GetPair>) -> Result<Pubkey> {
+        Ok(ctx.accounts.pair_record.pair)
+    }
+}
+
+/// Order two mints the way `create_pair` expects them.
+pub fn sort_mints(token_a: Pubkey, token_b: Pubkey) -> (Pubkey, Pubkey) {
+    if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    }
+}
+
+/// Address of the `PairRecord` for two mints, in either order.
+pub fn pair_record_address(token_a: Pubkey, token_b: Pubkey) -> (Pubkey, u8) {
+    let (token_0, token_1) = sort_mints(token_a, token_b);
+    Pubkey::find_program_address(
+        &[PAIR_RECORD_SEED, token_0.as_ref(), token_1.as_ref()],
+        &ID,
+    )
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct SetPair<'info> {
-    /// CHECK: token mint pubkey
-    pub token_a: AccountInfo<'info>,
-    /// CHECK: token mint pubkey
-    pub token_b: AccountInfo<'info>,
+pub struct InitFactory<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + AllPairs::LEN,
+        seeds = [ALL_PAIRS_SEED],
+        bump
+    )]
+    pub all_pairs: Account<'info, AllPairs>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ FactoryError::NotUpgradeAuthority)]
+    pub program: Program<'info, FactoryStub>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(payer.key()) @ FactoryError::NotUpgradeAuthority)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePair<'info> {
+    #[account(mut, seeds = [ALL_PAIRS_SEED], bump = all_pairs.bump)]
+    pub all_pairs: Account<'info, AllPairs>,
+    pub mint_0: Account<'info, Mint>,
+    pub mint_1: Account<'info, Mint>,
     #[account(
         init,
         payer = payer,
         space = 8 + PairRecord::LEN,
-        seeds = [b"pair", token_a.key().as_ref(), token_b.key().as_ref()],
+        seeds = [PAIR_RECORD_SEED, mint_0.key().as_ref(), mint_1.key().as_ref()],
         bump,
     )]
     pub pair_record: Account<'info, PairRecord>,
+    /// CHECK: signer-only PDA; owns no data
+    #[account(seeds = [FACTORY_AUTHORITY_SEED], bump)]
+    pub factory_authority: UncheckedAccount<'info>,
+    /// CHECK: created and validated by `pair_stub::init_pair`
+    #[account(mut)]
+    pub pair: UncheckedAccount<'info>,
+    /// CHECK: created and validated by `pair_stub::init_pair`
+    #[account(mut)]
+    pub vault_0: UncheckedAccount<'info>,
+    /// CHECK: created and validated by `pair_stub::init_pair`
+    #[account(mut)]
+    pub vault_1: UncheckedAccount<'info>,
+    /// CHECK: created and validated by `pair_stub::init_pair`
+    #[account(mut)]
+    pub lp_mint: UncheckedAccount<'info>,
+    /// CHECK: created and validated by `pair_stub::init_pair`
+    #[account(mut)]
+    pub locked_lp: UncheckedAccount<'info>,
     #[account(mut)]
     pub payer: Signer<'info>,
+    pub pair_program: Program<'info, PairStub>,
     pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+pub struct SetPairFee<'info> {
+    #[account(seeds = [ALL_PAIRS_SEED], bump = all_pairs.bump, has_one = admin @ FactoryError::Unauthorized)]
+    pub all_pairs: Account<'info, AllPairs>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [PAIR_RECORD_SEED, pair_record.token_0.as_ref(), pair_record.token_1.as_ref()],
+        bump = pair_record.bump,
+        has_one = pair @ FactoryError::PairNotFound
+    )]
+    pub pair_record: Account<'info, PairRecord>,
+    /// CHECK: validated against `pair_record` here and by `pair_stub::set_fee`
+    #[account(mut)]
+    pub pair: UncheckedAccount<'info>,
+    /// CHECK: signer-only PDA; owns no data
+    #[account(seeds = [FACTORY_AUTHORITY_SEED], bump)]
+    pub factory_authority: UncheckedAccount<'info>,
+    pub pair_program: Program<'info, PairStub>,
+}
+
+#[derive(Accounts)]
+pub struct GetPair<'info> {
+    /// CHECK: token mint pubkey
+    pub token_a: AccountInfo<'info>,
+    /// CHECK: token mint pubkey
+    pub token_b: AccountInfo<'info>,
+    #[account(address = pair_record_address(token_a.key(), token_b.key()).0 @ FactoryError::PairNotFound)]
+    pub pair_record: Account<'info, PairRecord>,
+}
+
+/// Every pair created through the factory, in creation order.
+#[account]
+pub struct AllPairs {
+    /// Sets the fees of created pairs.
+    pub admin: Pubkey,
+    pub pairs: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl AllPairs {
+    pub const LEN: usize = 32 + 4 + 32 * MAX_PAIRS + 1;
+}
+
+/// Pair created for two sorted mints; the batch program resolves pairs through it.
 #[account]
 pub struct PairRecord {
-    pub token_a: Pubkey,
-    pub token_b: Pubkey,
+    pub token_0: Pubkey,
+    pub token_1: Pubkey,
     pub pair: Pubkey,
+    pub index: u32,
     pub bump: u8,
 }
 
 impl PairRecord {
-    pub const LEN: usize = 32 + 32 + 32 + 1;
+    pub const LEN: usize = 32 + 32 + 32 + 4 + 1;
+}
+
+#[error_code]
+pub enum FactoryError {
+    #[msg("Pair mints must differ")]
+    IdenticalMints,
+    #[msg("Pair mints must be sorted (mint_0 < mint_1)")]
+    UnsortedMints,
+    #[msg("Pair registry is full")]
+    RegistryFull,
+    #[msg("No pair registered for these mints")]
+    PairNotFound,
+    #[msg("Signer is not the factory admin")]
+    Unauthorized,
+    #[msg("Signer is not the program's upgrade authority")]
+    NotUpgradeAuthority,
 }
@@ -1,106 +1,278 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{self, Mint, Token, TokenAccount, Transfer},
+    token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer},
 };
 
 declare_id!("6Wq5RBNnszrhQiR5QBbgZGgHPthLAhot2miZ1qDddKci");
 
+pub const PAIR_SEED: &[u8] = b"pair";
+pub const LP_MINT_SEED: &[u8] = b"lp";
+
+/// `factory_stub` program; only its authority PDA may create pairs.
+/// Kept here because `factory_stub` depends on this crate, not the other way round.
+pub const FACTORY_PROGRAM_ID: Pubkey = pubkey!("uY7scRK6DgtK7Ww9udtDiny7fpyEF324C78PXHnemKP");
+/// Seed of the `factory_stub` PDA that signs `init_pair` and holds every pair's fee authority.
+pub const FACTORY_AUTHORITY_SEED: &[u8] = b"authority";
+
+/// Upper bound for the swap fee (10%).
+pub const MAX_FEE_BPS: u16 = 1_000;
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// LP tokens minted to the pair's own locked LP account on the first deposit, so the
+/// pool can never be fully drained.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
 #[program]
 pub mod pair_stub {
     use super::*;
 
-    /// Initialize a stub pair with fixed reserves and vault.
-    pub fn init_pair(
-        ctx: Context<InitPair>,
-        reserve_0: u64,
-        reserve_1: u64,
-    ) -> Result<()> {
+    /// Initialize an empty constant-product pair with its vaults, LP mint and the locked
+    /// LP account that holds `MINIMUM_LIQUIDITY`.
+    /// Must be signed by the factory authority PDA, which becomes the pair's fee authority.
+    pub fn init_pair(ctx: Context<InitPair>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, PairError::InvalidFee);
+
         let pair = &mut ctx.accounts.pair;
-        pair.authority = ctx.accounts.authority.key();
+        pair.authority = ctx.accounts.factory_authority.key();
         pair.token_0 = ctx.accounts.mint_0.key();
         pair.token_1 = ctx.accounts.mint_1.key();
-        pair.reserve_0 = reserve_0;
-        pair.reserve_1 = reserve_1;
+        pair.reserve_0 = 0;
+        pair.reserve_1 = 0;
         pair.vault_0 = ctx.accounts.vault_0.key();
         pair.vault_1 = ctx.accounts.vault_1.key();
+        pair.lp_mint = ctx.accounts.lp_mint.key();
+        pair.fee_bps = fee_bps;
         pair.bump = ctx.bumps.pair;
         Ok(())
     }
 
-    /// Update reserves (for testing scenarios).
-    pub fn set_reserves(ctx: Context<SetReserves>, reserve_0: u64, reserve_1: u64) -> Result<()> {
-        let pair = &mut ctx.accounts.pair;
-        require_keys_eq!(pair.authority, ctx.accounts.authority.key(), PairError::Unauthorized);
-        pair.reserve_0 = reserve_0;
-        pair.reserve_1 = reserve_1;
+    /// Update the swap fee. Authority only, i.e. through `factory_stub::set_pair_fee`.
+    pub fn set_fee(ctx: Context<SetFee>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, PairError::InvalidFee);
+        ctx.accounts.pair.fee_bps = fee_bps;
         Ok(())
     }
 
-    /// Stub swap: transfers provided outputs from vaults to the recipient.
-    pub fn swap(
-        ctx: Context<Swap>,
-        amount_0_out: u64,
-        amount_1_out: u64,
+    /// Deposit both tokens at the current reserve ratio and mint LP tokens.
+    /// The first deposit sets the price and mints `MINIMUM_LIQUIDITY` to the locked LP
+    /// account, which only the pair can sign for and never burns; later deposits use as
+    /// much of the desired amounts as the ratio allows.
+    pub fn add_liquidity(
+        ctx: Context<AddLiquidity>,
+        amount_0_desired: u64,
+        amount_1_desired: u64,
+        min_liquidity: u64,
     ) -> Result<()> {
         let pair = &ctx.accounts.pair;
-        // Pay out from vaults; this is a stub and does not update reserves.
-        if amount_0_out > 0 {
-            let seeds = &[b"pair", pair.token_0.as_ref(), pair.token_1.as_ref(), &[pair.bump]];
-            let signer = &[&seeds[..]];
+        let supply = ctx.accounts.lp_mint.supply;
+
+        let (amount_0, amount_1, liquidity) = if supply == 0 {
+            let root = isqrt(u128::from(amount_0_desired) * u128::from(amount_1_desired));
+            let liquidity = u64::try_from(root)
+                .map_err(|_| PairError::MathOverflow)?
+                .checked_sub(MINIMUM_LIQUIDITY)
+                .ok_or(PairError::InsufficientLiquidityMinted)?;
+            (amount_0_desired, amount_1_desired, liquidity)
+        } else {
+            let amount_1_optimal = mul_div(amount_0_desired, pair.reserve_1, pair.reserve_0)?;
+            let (amount_0, amount_1) = if amount_1_optimal <= amount_1_desired {
+                (amount_0_desired, amount_1_optimal)
+            } else {
+                (mul_div(amount_1_desired, pair.reserve_0, pair.reserve_1)?, amount_1_desired)
+            };
+            let liquidity = mul_div(amount_0, supply, pair.reserve_0)?
+                .min(mul_div(amount_1, supply, pair.reserve_1)?);
+            (amount_0, amount_1, liquidity)
+        };
+        require!(liquidity > 0, PairError::InsufficientLiquidityMinted);
+        require!(liquidity >= min_liquidity, PairError::SlippageExceeded);
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let user = ctx.accounts.user.to_account_info();
+        for (from, to, amount) in [
+            (&ctx.accounts.user_token_0, &ctx.accounts.vault_0, amount_0),
+            (&ctx.accounts.user_token_1, &ctx.accounts.vault_1, amount_1),
+        ] {
             let cpi_accounts = Transfer {
-                from: ctx.accounts.vault_0.to_account_info(),
-                to: ctx.accounts.to_0.to_account_info(),
+                from: from.to_account_info(),
+                to: to.to_account_info(),
+                authority: user.clone(),
+            };
+            token::transfer(CpiContext::new(token_program.clone(), cpi_accounts), amount)?;
+        }
+
+        let (token_0, token_1, bump) = (pair.token_0, pair.token_1, pair.bump);
+        let seeds = &[PAIR_SEED, token_0.as_ref(), token_1.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+        let mut mints = vec![(&ctx.accounts.user_lp, liquidity)];
+        if supply == 0 {
+            mints.push((&ctx.accounts.locked_lp, MINIMUM_LIQUIDITY));
+        }
+        for (to, amount) in mints {
+            let cpi_accounts = MintTo {
+                mint: ctx.accounts.lp_mint.to_account_info(),
+                to: to.to_account_info(),
                 authority: ctx.accounts.pair.to_account_info(),
             };
-            let cpi_ctx =
-                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
-            token::transfer(cpi_ctx, amount_0_out)?;
+            token::mint_to(
+                CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer),
+                amount,
+            )?;
         }
-        if amount_1_out > 0 {
-            let seeds = &[b"pair", pair.token_0.as_ref(), pair.token_1.as_ref(), &[pair.bump]];
-            let signer = &[&seeds[..]];
+
+        sync_reserves(&mut ctx.accounts.pair, &mut ctx.accounts.vault_0, &mut ctx.accounts.vault_1)
+    }
+
+    /// Burn LP tokens for a proportional share of both vaults.
+    pub fn remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        liquidity: u64,
+        min_amount_0: u64,
+        min_amount_1: u64,
+    ) -> Result<()> {
+        let supply = ctx.accounts.lp_mint.supply;
+        require!(liquidity > 0 && liquidity <= supply, PairError::InsufficientLiquidityBurned);
+
+        let amount_0 = mul_div(liquidity, ctx.accounts.vault_0.amount, supply)?;
+        let amount_1 = mul_div(liquidity, ctx.accounts.vault_1.amount, supply)?;
+        require!(amount_0 > 0 && amount_1 > 0, PairError::InsufficientLiquidityBurned);
+        require!(
+            amount_0 >= min_amount_0 && amount_1 >= min_amount_1,
+            PairError::SlippageExceeded
+        );
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let cpi_accounts = Burn {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            from: ctx.accounts.user_lp.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        token::burn(CpiContext::new(token_program.clone(), cpi_accounts), liquidity)?;
+
+        let pair = &ctx.accounts.pair;
+        let seeds = &[PAIR_SEED, pair.token_0.as_ref(), pair.token_1.as_ref(), &[pair.bump]];
+        let signer = &[&seeds[..]];
+        for (from, to, amount) in [
+            (&ctx.accounts.vault_0, &ctx.accounts.user_token_0, amount_0),
+            (&ctx.accounts.vault_1, &ctx.accounts.user_token_1, amount_1),
+        ] {
             let cpi_accounts = Transfer {
-                from: ctx.accounts.vault_1.to_account_info(),
-                to: ctx.accounts.to_1.to_account_info(),
+                from: from.to_account_info(),
+                to: to.to_account_info(),
                 authority: ctx.accounts.pair.to_account_info(),
             };
-            let cpi_ctx =
-                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
-            token::transfer(cpi_ctx, amount_1_out)?;
+            token::transfer(
+                CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer),
+                amount,
+            )?;
         }
+
+        sync_reserves(&mut ctx.accounts.pair, &mut ctx.accounts.vault_0, &mut ctx.accounts.vault_1)
+    }
+
+    /// Swap `amount_in` of the mint of `user_token_in` for the other token.
+    /// Pulls the input from the user, pays the constant-product output net of the pair
+    /// fee, and fails if that is below `min_amount_out` or if `x*y` would decrease.
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64) -> Result<()> {
+        let pair = &ctx.accounts.pair;
+        let zero_for_one = ctx.accounts.user_token_in.mint == pair.token_0;
+        let (mint_out, vault_in, vault_out) = if zero_for_one {
+            (pair.token_1, &ctx.accounts.vault_0, &ctx.accounts.vault_1)
+        } else {
+            require_keys_eq!(ctx.accounts.user_token_in.mint, pair.token_1, PairError::MintMismatch);
+            (pair.token_0, &ctx.accounts.vault_1, &ctx.accounts.vault_0)
+        };
+        require_keys_eq!(ctx.accounts.user_token_out.mint, mint_out, PairError::MintMismatch);
+
+        let amount_out = pair.get_amount_out(amount_in, zero_for_one)?;
+        require!(amount_out > 0, PairError::InsufficientOutputAmount);
+        require!(amount_out >= min_amount_out, PairError::SlippageExceeded);
+        let k_before = u128::from(pair.reserve_0) * u128::from(pair.reserve_1);
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_token_in.to_account_info(),
+            to: vault_in.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        token::transfer(CpiContext::new(token_program.clone(), cpi_accounts), amount_in)?;
+
+        let seeds = &[PAIR_SEED, pair.token_0.as_ref(), pair.token_1.as_ref(), &[pair.bump]];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = Transfer {
+            from: vault_out.to_account_info(),
+            to: ctx.accounts.user_token_out.to_account_info(),
+            authority: ctx.accounts.pair.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(token_program, cpi_accounts, signer),
+            amount_out,
+        )?;
+
+        sync_reserves(&mut ctx.accounts.pair, &mut ctx.accounts.vault_0, &mut ctx.accounts.vault_1)?;
+        let pair = &ctx.accounts.pair;
+        require!(
+            u128::from(pair.reserve_0) * u128::from(pair.reserve_1) >= k_before,
+            PairError::InvariantViolated
+        );
         Ok(())
     }
+
+    /// Set the reserves to the vault balances, e.g. after tokens were sent to a vault directly.
+    pub fn sync(ctx: Context<Sync>) -> Result<()> {
+        sync_reserves(&mut ctx.accounts.pair, &mut ctx.accounts.vault_0, &mut ctx.accounts.vault_1)
+    }
 }
 
 #[derive(Accounts)]
 pub struct InitPair<'info> {
+    /// `factory_stub`'s authority PDA, signing through `invoke_signed`.
+    #[account(seeds = [FACTORY_AUTHORITY_SEED], bump, seeds::program = FACTORY_PROGRAM_ID)]
+    pub factory_authority: Signer<'info>,
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub payer: Signer<'info>,
     pub mint_0: Account<'info, Mint>,
     pub mint_1: Account<'info, Mint>,
     #[account(
         init,
-        payer = authority,
+        payer = payer,
         space = 8 + PairState::LEN,
-        seeds = [b"pair", mint_0.key().as_ref(), mint_1.key().as_ref()],
+        seeds = [PAIR_SEED, mint_0.key().as_ref(), mint_1.key().as_ref()],
         bump
     )]
     pub pair: Account<'info, PairState>,
     #[account(
         init,
-        payer = authority,
+        payer = payer,
         associated_token::mint = mint_0,
         associated_token::authority = pair
     )]
     pub vault_0: Account<'info, TokenAccount>,
     #[account(
         init,
-        payer = authority,
+        payer = payer,
         associated_token::mint = mint_1,
         associated_token::authority = pair
     )]
     pub vault_1: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [LP_MINT_SEED, pair.key().as_ref()],
+        bump,
+        mint::decimals = mint_0.decimals,
+        mint::authority = pair
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    /// Holds `MINIMUM_LIQUIDITY`; owned by the pair, which never transfers or burns from it.
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = lp_mint,
+        associated_token::authority = pair
+    )]
+    pub locked_lp: Account<'info, TokenAccount>,
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -108,48 +280,195 @@ pub struct InitPair<'info> {
 }
 
 #[derive(Accounts)]
-pub struct SetReserves<'info> {
-    #[account(mut, has_one = authority)]
+pub struct SetFee<'info> {
+    #[account(mut, has_one = authority @ PairError::Unauthorized)]
     pub pair: Account<'info, PairState>,
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [PAIR_SEED, pair.token_0.as_ref(), pair.token_1.as_ref()],
+        bump = pair.bump,
+        has_one = vault_0,
+        has_one = vault_1,
+        has_one = lp_mint
+    )]
+    pub pair: Account<'info, PairState>,
+    #[account(mut)]
+    pub vault_0: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_1: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, constraint = user_token_0.mint == pair.token_0 @ PairError::MintMismatch)]
+    pub user_token_0: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_token_1.mint == pair.token_1 @ PairError::MintMismatch)]
+    pub user_token_1: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_lp.mint == lp_mint.key() @ PairError::MintMismatch)]
+    pub user_lp: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = lp_mint,
+        associated_token::authority = pair
+    )]
+    pub locked_lp: Account<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [PAIR_SEED, pair.token_0.as_ref(), pair.token_1.as_ref()],
+        bump = pair.bump,
+        has_one = vault_0,
+        has_one = vault_1,
+        has_one = lp_mint
+    )]
+    pub pair: Account<'info, PairState>,
+    #[account(mut)]
+    pub vault_0: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_1: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, constraint = user_token_0.mint == pair.token_0 @ PairError::MintMismatch)]
+    pub user_token_0: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_token_1.mint == pair.token_1 @ PairError::MintMismatch)]
+    pub user_token_1: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_lp.mint == lp_mint.key() @ PairError::MintMismatch)]
+    pub user_lp: Account<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
 #[derive(Accounts)]
 pub struct Swap<'info> {
     #[account(
-        seeds = [b"pair", pair.token_0.as_ref(), pair.token_1.as_ref()],
-        bump = pair.bump
+        mut,
+        seeds = [PAIR_SEED, pair.token_0.as_ref(), pair.token_1.as_ref()],
+        bump = pair.bump,
+        has_one = vault_0,
+        has_one = vault_1
     )]
     pub pair: Account<'info, PairState>,
     #[account(mut)]
     pub vault_0: Account<'info, TokenAccount>,
     #[account(mut)]
     pub vault_1: Account<'info, TokenAccount>,
+    /// Source of the input; its mint selects the swap direction.
     #[account(mut)]
-    pub to_0: Account<'info, TokenAccount>,
+    pub user_token_in: Account<'info, TokenAccount>,
     #[account(mut)]
-    pub to_1: Account<'info, TokenAccount>,
+    pub user_token_out: Account<'info, TokenAccount>,
+    pub user: Signer<'info>,
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct Sync<'info> {
+    #[account(mut, has_one = vault_0, has_one = vault_1)]
+    pub pair: Account<'info, PairState>,
+    pub vault_0: Account<'info, TokenAccount>,
+    pub vault_1: Account<'info, TokenAccount>,
+}
+
 #[account]
 pub struct PairState {
+    /// May change the fee; the factory authority PDA for pairs created by `factory_stub`.
     pub authority: Pubkey,
     pub token_0: Pubkey,
     pub token_1: Pubkey,
+    /// Vault balances as of the last swap, liquidity change or sync.
     pub reserve_0: u64,
     pub reserve_1: u64,
     pub vault_0: Pubkey,
     pub vault_1: Pubkey,
+    pub lp_mint: Pubkey,
+    /// Swap fee kept by the pair for liquidity providers.
+    pub fee_bps: u16,
     pub bump: u8,
 }
 
 impl PairState {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 32 + 32 + 1;
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 32 + 32 + 32 + 2 + 1;
+
+    /// Constant-product output for `amount_in` net of the pair fee, quoted off the
+    /// current reserves. `zero_for_one` swaps `token_0` for `token_1`.
+    pub fn get_amount_out(&self, amount_in: u64, zero_for_one: bool) -> Result<u64> {
+        let (reserve_in, reserve_out) = if zero_for_one {
+            (self.reserve_0, self.reserve_1)
+        } else {
+            (self.reserve_1, self.reserve_0)
+        };
+        require!(reserve_in > 0 && reserve_out > 0, PairError::InsufficientLiquidity);
+
+        let amount_in_with_fee = u128::from(amount_in) * (BPS_DENOMINATOR - u128::from(self.fee_bps));
+        let numerator = amount_in_with_fee
+            .checked_mul(u128::from(reserve_out))
+            .ok_or(PairError::MathOverflow)?;
+        let denominator = u128::from(reserve_in) * BPS_DENOMINATOR + amount_in_with_fee;
+        u64::try_from(numerator / denominator).map_err(|_| error!(PairError::MathOverflow))
+    }
+}
+
+/// Reload both vaults and record their balances as the reserves.
+fn sync_reserves(
+    pair: &mut Account<PairState>,
+    vault_0: &mut Account<TokenAccount>,
+    vault_1: &mut Account<TokenAccount>,
+) -> Result<()> {
+    vault_0.reload()?;
+    vault_1.reload()?;
+    pair.reserve_0 = vault_0.amount;
+    pair.reserve_1 = vault_1.amount;
+    Ok(())
+}
+
+/// `a * b / c` without intermediate overflow.
+fn mul_div(a: u64, b: u64, c: u64) -> Result<u64> {
+    require!(c > 0, PairError::InsufficientLiquidity);
+    u64::try_from(u128::from(a) * u128::from(b) / u128::from(c)).map_err(|_| error!(PairError::MathOverflow))
+}
+
+/// Integer square root, rounded down.
+fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
 }
 
 #[error_code]
 pub enum PairError {
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Fee exceeds the maximum")]
+    InvalidFee,
+    #[msg("Token account mint does not match the pair")]
+    MintMismatch,
+    #[msg("Pair has no liquidity")]
+    InsufficientLiquidity,
+    #[msg("Not enough liquidity minted")]
+    InsufficientLiquidityMinted,
+    #[msg("Not enough liquidity burned")]
+    InsufficientLiquidityBurned,
+    #[msg("Swap output is zero")]
+    InsufficientOutputAmount,
+    #[msg("Amount below the requested minimum")]
+    SlippageExceeded,
+    #[msg("Swap would decrease the constant product")]
+    InvariantViolated,
+    #[msg("Math overflow")]
+    MathOverflow,
 }
@@ -8,7 +8,10 @@ import {
   mintTo,
 } from "@solana/spl-token";
 import { AccountMeta, PublicKey, SystemProgram } from "@solana/web3.js";
+import { createHash, randomBytes } from "crypto";
 import { Batch } from "../target/types/batch";
+import { FactoryStub } from "../target/types/factory_stub";
+import { PairStub } from "../target/types/pair_stub";
 import {
   DEVNET_DENOM,
   DEVNET_MINT_AMOUNT,
@@ -20,6 +23,15 @@ import {
 
 const POOL_SEED = Buffer.from("pool");
 const CONFIG_SEED = Buffer.from("config");
+const TICKET_SEED = Buffer.from("ticket");
+const FEE_VAULT_SEED = Buffer.from("fee_vault");
+const ALL_PAIRS_SEED = Buffer.from("all_pairs");
+const PAIR_SEED = Buffer.from("pair");
+const LP_MINT_SEED = Buffer.from("lp");
+const FACTORY_AUTHORITY_SEED = Buffer.from("authority");
+const BPF_LOADER_UPGRADEABLE = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+const PAIR_LIQUIDITY = 1_000_000_000_000;
+const PAIR_FEE_BPS = 30;
 const MIN_POOL_SIZE = 3;
 
 describe("batch coinjoin devnet e2e", () => {
@@ -27,6 +39,8 @@ describe("batch coinjoin devnet e2e", () => {
   anchor.setProvider(provider);
   const connection = provider.connection;
   const program = anchor.workspace.Batch as Program<Batch>;
+  const factoryProgram = anchor.workspace.FactoryStub as Program<FactoryStub>;
+  const pairProgram = anchor.workspace.PairStub as Program<PairStub>;
 
   const participants = DEVNET_PARTICIPANTS;
 
@@ -75,8 +89,12 @@ describe("batch coinjoin devnet e2e", () => {
     // 2) Ensure shared devnet mint exists (7 decimals to align with denom of 10_000_000).
     const mint = await ensureMint();
 
-    // 3) Compute vault ATA for the pool PDA.
+    // 3) Compute vault ATA and fee vault PDA for the pool PDA.
     const vaultAta = getAssociatedTokenAddressSync(mint, poolPda, true);
+    const [feeVaultPda] = PublicKey.findProgramAddressSync(
+      [FEE_VAULT_SEED, poolPda.toBuffer()],
+      program.programId
+    );
 
     // 4) Airdrop SOL to participants for fees (plus mint authority for ATA/mint fees).
     for (const kp of participants) {
@@ -97,9 +115,16 @@ describe("batch coinjoin devnet e2e", () => {
         .rpc();
     }
 
-    // 6) Initialize pool if missing.
+    // 6) Initialize pool if missing, swapping into a fresh output mint.
     const poolInfo = await connection.getAccountInfo(poolPda);
     if (!poolInfo) {
+      const newOutputMint = await createMint(
+        connection,
+        DEVNET_MINT_AUTHORITY,
+        DEVNET_MINT_AUTHORITY.publicKey,
+        null,
+        DEVNET_MINT_DECIMALS
+      );
       await program.methods
         .initPool(
           new anchor.BN(DEVNET_DENOM),
@@ -113,6 +138,9 @@ describe("batch coinjoin devnet e2e", () => {
           pool: poolPda,
           mint,
           vault: vaultAta,
+          outputMint: newOutputMint,
+          outputVault: getAssociatedTokenAddressSync(newOutputMint, poolPda, true),
+          feeVault: feeVaultPda,
           systemProgram: SystemProgram.programId,
           tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
           associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
@@ -120,6 +148,129 @@ describe("batch coinjoin devnet e2e", () => {
         })
         .rpc();
     }
+    const { outputMint, outputVault } = await program.account.pool.fetch(poolPda);
+
+    // 6b) Create the input/output pair through the factory if missing and seed it with liquidity.
+    // The factory keys pairs by their sorted mints.
+    const [mint0, mint1] = [mint, outputMint].sort((a, b) =>
+      Buffer.compare(a.toBuffer(), b.toBuffer())
+    );
+    const [allPairsPda] = PublicKey.findProgramAddressSync(
+      [ALL_PAIRS_SEED],
+      factoryProgram.programId
+    );
+    const [factoryAuthorityPda] = PublicKey.findProgramAddressSync(
+      [FACTORY_AUTHORITY_SEED],
+      factoryProgram.programId
+    );
+    const [pairRecordPda] = PublicKey.findProgramAddressSync(
+      [PAIR_SEED, mint0.toBuffer(), mint1.toBuffer()],
+      factoryProgram.programId
+    );
+    const [pairPda] = PublicKey.findProgramAddressSync(
+      [PAIR_SEED, mint0.toBuffer(), mint1.toBuffer()],
+      pairProgram.programId
+    );
+    const [lpMint] = PublicKey.findProgramAddressSync(
+      [LP_MINT_SEED, pairPda.toBuffer()],
+      pairProgram.programId
+    );
+    const pairVault0 = getAssociatedTokenAddressSync(mint0, pairPda, true);
+    const pairVault1 = getAssociatedTokenAddressSync(mint1, pairPda, true);
+    const lockedLp = getAssociatedTokenAddressSync(lpMint, pairPda, true);
+    if (!(await connection.getAccountInfo(allPairsPda))) {
+      await factoryProgram.methods
+        .initFactory()
+        .accounts({
+          allPairs: allPairsPda,
+          payer: provider.wallet.publicKey,
+          program: factoryProgram.programId,
+          programData: PublicKey.findProgramAddressSync(
+            [factoryProgram.programId.toBuffer()],
+            BPF_LOADER_UPGRADEABLE
+          )[0],
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+    if (!(await connection.getAccountInfo(pairPda))) {
+      await factoryProgram.methods
+        .createPair(PAIR_FEE_BPS)
+        .accounts({
+          allPairs: allPairsPda,
+          mint0,
+          mint1,
+          pairRecord: pairRecordPda,
+          factoryAuthority: factoryAuthorityPda,
+          pair: pairPda,
+          vault0: pairVault0,
+          vault1: pairVault1,
+          lpMint,
+          lockedLp,
+          payer: provider.wallet.publicKey,
+          pairProgram: pairProgram.programId,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+          associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+
+      // The factory PDA, not the payer, holds the pair's fee authority.
+      const pairState = await pairProgram.account.pairState.fetch(pairPda);
+      if (!pairState.authority.equals(factoryAuthorityPda)) {
+        throw new Error("Pair authority is not the factory PDA");
+      }
+
+      const liquidityAccounts = [];
+      for (const tokenMint of [mint0, mint1]) {
+        const ata = await getOrCreateAssociatedTokenAccount(
+          connection,
+          DEVNET_MINT_AUTHORITY,
+          tokenMint,
+          provider.wallet.publicKey
+        );
+        await mintTo(
+          connection,
+          DEVNET_MINT_AUTHORITY,
+          tokenMint,
+          ata.address,
+          DEVNET_MINT_AUTHORITY.publicKey,
+          PAIR_LIQUIDITY
+        );
+        liquidityAccounts.push(ata.address);
+      }
+      const userLp = await getOrCreateAssociatedTokenAccount(
+        connection,
+        DEVNET_MINT_AUTHORITY,
+        lpMint,
+        provider.wallet.publicKey
+      );
+      await pairProgram.methods
+        .addLiquidity(
+          new anchor.BN(PAIR_LIQUIDITY),
+          new anchor.BN(PAIR_LIQUIDITY),
+          new anchor.BN(1)
+        )
+        .accounts({
+          pair: pairPda,
+          vault0: pairVault0,
+          vault1: pairVault1,
+          lpMint,
+          userToken0: liquidityAccounts[0],
+          userToken1: liquidityAccounts[1],
+          userLp: userLp.address,
+          lockedLp,
+          user: provider.wallet.publicKey,
+          tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // The first deposit locks MINIMUM_LIQUIDITY in the pair's own LP account.
+      if (Number((await getAccount(connection, lockedLp)).amount) !== 1_000) {
+        throw new Error("Minimum liquidity was not locked");
+      }
+    }
 
     // 7) Create ATAs for participants and mint tokens to them.
     const userAtas = [];
@@ -145,35 +296,68 @@ describe("batch coinjoin devnet e2e", () => {
       }
     }
 
-    // 8) Deposits from each participant.
+    // 8) Deposits from each participant, each recording a ticket bound to a fresh
+    // commitment and to the participant's output-mint ATA as payout recipient.
+    const recipientAtas = [];
+    for (const kp of participants) {
+      const ata = await getOrCreateAssociatedTokenAccount(
+        connection,
+        DEVNET_MINT_AUTHORITY,
+        outputMint,
+        kp.publicKey
+      );
+      recipientAtas.push(ata);
+    }
+    const tickets = [];
     for (let i = 0; i < participants.length; i++) {
+      const commitment = createHash("sha256").update(randomBytes(64)).digest();
+      const [ticketPda] = PublicKey.findProgramAddressSync(
+        [TICKET_SEED, poolPda.toBuffer(), commitment],
+        program.programId
+      );
+      tickets.push(ticketPda);
+
       await program.methods
-        .deposit()
+        .deposit([...commitment], new anchor.BN(1))
         .accounts({
+          config: configPda,
           pool: poolPda,
+          ticket: ticketPda,
           mint,
           vault: vaultAta,
           depositor: participants[i].publicKey,
           depositorToken: userAtas[i],
+          recipientToken: recipientAtas[i].address,
           tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         })
         .signers([participants[i]])
         .rpc();
     }
 
-    // 9) Execute mixing to pay recipients (use participant ATAs as recipients).
-    const remainingAccounts: AccountMeta[] = userAtas.map((addr) => ({
-      pubkey: addr,
-      isSigner: false,
-      isWritable: true,
-    }));
+    // 9) Execute mixing, passing (ticket, recipient, depositor) for each deposit.
+    const remainingAccounts: AccountMeta[] = tickets.flatMap((ticket, i) => [
+      { pubkey: ticket, isSigner: false, isWritable: true },
+      { pubkey: recipientAtas[i].address, isSigner: false, isWritable: true },
+      { pubkey: participants[i].publicKey, isSigner: false, isWritable: true },
+    ]);
 
+    const feesBefore = Number((await getAccount(connection, feeVaultPda)).amount);
     await program.methods
       .executeMixing()
       .accounts({
+        config: configPda,
+        keeper: provider.wallet.publicKey,
         pool: poolPda,
         vault: vaultAta,
+        outputVault,
+        feeVault: feeVaultPda,
         mint,
+        pair: pairPda,
+        pairRecord: pairRecordPda,
+        pairVault0,
+        pairVault1,
+        pairProgram: pairProgram.programId,
         tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
       })
       .remainingAccounts(remainingAccounts)
@@ -186,17 +370,59 @@ describe("batch coinjoin devnet e2e", () => {
       throw new Error("Vault not drained after mixing");
     }
 
-    // Each participant should end at or above the target mint amount (deposit refunded via mixing).
-    for (const ata of userAtas) {
-      const acc = await getAccount(connection, ata);
-      if (Number(acc.amount) < DEVNET_MINT_AMOUNT) {
-        throw new Error("Recipient balance incorrect after mixing");
-      }
+    // Every recipient receives the same share of the swapped output mint.
+    const received = [];
+    for (let i = 0; i < recipientAtas.length; i++) {
+      const acc = await getAccount(connection, recipientAtas[i].address);
+      received.push(Number(acc.amount) - Number(recipientAtas[i].amount));
+    }
+    if (received[0] <= 0 || received.some((amount) => amount !== received[0])) {
+      throw new Error("Recipients did not receive equal output payouts");
     }
 
     const poolAccount = await program.account.pool.fetch(poolPda);
     if (!poolAccount.currentPoolSize.eq(new anchor.BN(0))) {
       throw new Error("Pool not reset after mixing");
     }
+
+    // The pool fee in force at deposit time is skimmed into the fee vault.
+    const expectedFee =
+      Math.floor((DEVNET_DENOM * poolAccount.feeBps) / 10_000) * participants.length;
+    const feesAfter = Number((await getAccount(connection, feeVaultPda)).amount);
+    if (feesAfter - feesBefore !== expectedFee) {
+      throw new Error("Fee vault did not receive the pool fee");
+    }
+
+    // The owner withdraws the collected fees.
+    if (feesAfter > 0) {
+      const ownerAta = await getOrCreateAssociatedTokenAccount(
+        connection,
+        DEVNET_MINT_AUTHORITY,
+        mint,
+        provider.wallet.publicKey
+      );
+      await program.methods
+        .withdrawFees(new anchor.BN(feesAfter))
+        .accounts({
+          config: configPda,
+          owner: provider.wallet.publicKey,
+          pool: poolPda,
+          feeVault: feeVaultPda,
+          destination: ownerAta.address,
+          tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const drained = await getAccount(connection, feeVaultPda);
+      if (Number(drained.amount) !== 0) {
+        throw new Error("Fee vault not drained by withdraw_fees");
+      }
+    }
+
+    // Consumed tickets are closed.
+    for (const ticket of tickets) {
+      if (await connection.getAccountInfo(ticket)) {
+        throw new Error("Deposit ticket not closed after mixing");
+      }
+    }
   });
 });
@@ -0,0 +1,119 @@
+// src/access.rs
+// Ownership and role-based access control
+//
+// - Ownership moves in two steps: the owner proposes a successor, who must accept
+// - Day-to-day permissions are split into roles granted by the owner
+// - A role that was never granted is held by the current owner
+
+use soroban_sdk::{contractevent, contracttype, Address, Env};
+use crate::{error::BatcherError, helpers, DataKey};
+
+/// Permissions that the owner can delegate to separate accounts
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    /// Pool creation and configuration, verifier, batch executor settings and multicall
+    Admin,
+    /// Batch executor settlement (`execute_batch`); `execute_pool` is open to any keeper by design
+    Keeper,
+    /// Pool fee rates, the keeper reward share and the fee recipient
+    FeeManager,
+    /// Emergency pause and unpause
+    Pauser,
+}
+
+/// Emitted when the owner nominates a successor
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct OwnershipProposedEvent {
+    pub owner: Address,
+    pub pending_owner: Address,
+}
+
+/// Emitted when the nominated successor accepts ownership
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct OwnershipTransferredEvent {
+    pub previous_owner: Address,
+    pub new_owner: Address,
+}
+
+/// Emitted when the owner grants a role
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct RoleGrantedEvent {
+    #[topic]
+    pub role: Role,
+    pub account: Address,
+}
+
+pub struct AccessControl;
+
+impl AccessControl {
+    /// Current contract owner
+    pub fn get_owner(env: &Env) -> Result<Address, BatcherError> {
+        if !helpers::is_initialized(env) {
+            return Err(BatcherError::NotInitialized);
+        }
+
+        env.storage().instance()
+            .get(&DataKey::Owner)
+            .ok_or(BatcherError::NotInitialized)
+    }
+
+    /// Require authorization from the owner
+    pub fn require_owner(env: &Env) -> Result<Address, BatcherError> {
+        let owner = Self::get_owner(env)?;
+        owner.require_auth();
+        Ok(owner)
+    }
+
+    /// Account holding a role, falling back to the owner when it was never granted
+    pub fn get_role(env: &Env, role: Role) -> Result<Address, BatcherError> {
+        match env.storage().instance().get(&DataKey::Role(role)) {
+            Some(account) => Ok(account),
+            None => Self::get_owner(env),
+        }
+    }
+
+    /// Require authorization from the holder of a role
+    pub fn require_role(env: &Env, role: Role) -> Result<(), BatcherError> {
+        Self::get_role(env, role)?.require_auth();
+        Ok(())
+    }
+
+    /// Grant a role to an account, replacing its previous holder (owner only)
+    pub fn set_role(env: &Env, role: Role, account: Address) -> Result<(), BatcherError> {
+        Self::require_owner(env)?;
+
+        env.storage().instance().set(&DataKey::Role(role), &account);
+        RoleGrantedEvent { role, account }.publish(env);
+        Ok(())
+    }
+
+    /// Nominate a new owner; ownership only moves once they call `accept_owner`
+    pub fn propose_owner(env: &Env, pending_owner: Address) -> Result<(), BatcherError> {
+        let owner = Self::require_owner(env)?;
+
+        env.storage().instance().set(&DataKey::PendingOwner, &pending_owner);
+        OwnershipProposedEvent { owner, pending_owner }.publish(env);
+        Ok(())
+    }
+
+    /// Complete a transfer started by `propose_owner` (pending owner only)
+    pub fn accept_owner(env: &Env) -> Result<(), BatcherError> {
+        let previous_owner = Self::get_owner(env)?;
+        let new_owner = Self::get_pending_owner(env).ok_or(BatcherError::Unauthorized)?;
+        new_owner.require_auth();
+
+        env.storage().instance().set(&DataKey::Owner, &new_owner);
+        env.storage().instance().remove(&DataKey::PendingOwner);
+        OwnershipTransferredEvent { previous_owner, new_owner }.publish(env);
+        Ok(())
+    }
+
+    /// Owner nominated by `propose_owner` and not yet accepted
+    pub fn get_pending_owner(env: &Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::PendingOwner)
+    }
+}
@@ -9,192 +9,215 @@
 // - Price improvement for all participants
 // - MEV resistance through single price execution
 
-use soroban_sdk::{Env, Address, Vec};
+use soroban_sdk::{contracttype, token::Client as TokenClient, Address, Env, Vec};
+use crate::{error::BatcherError, helpers, DataKey, OrderSubmittedEvent, OrdersExecutedEvent};
+
+/// Fixed-point scale for clearing prices (token_out per token_in)
+pub const PRICE_SCALE: i128 = 10_000_000;
+
+/// Default maximum number of orders settled in one batch
+const DEFAULT_MAX_BATCH_SIZE: u32 = 10;
+
+/// TTL for persisted orders (30 days at 5 sec/ledger)
+const ORDER_TTL_LEDGERS: u32 = 518400;
+
+/// Lifecycle of a batch order
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum OrderStatus {
+    Pending,
+    Executed,
+    Cancelled,
+}
+
+/// Order escrowed by the contract until it is settled or cancelled
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct Order {
+    pub order_id: u64,
+    pub owner: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: i128,
+    pub min_amount_out: i128,
+    pub expiry: u64,
+    pub status: OrderStatus,
+    /// Amount of `token_out` paid to the owner once executed
+    pub amount_out: i128,
+}
 
 pub struct BatchExecutor;
 
 impl BatchExecutor {
-    // === Batch Executor Functions (Moved from lib.rs) ===
-
     /// Initialize batch executor settings for CowSwap-style execution
     pub fn init_batch_executor(
         env: &Env,
         enabled: bool,
         max_batch_size: u32,
-    ) -> Result<(), crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
-        }
-
-        env.storage().instance().set(&crate::DataKey::BATCH_ENABLED, &enabled);
-        env.storage().instance().set(&crate::DataKey::BATCH_SIZE, &max_batch_size);
-        env.storage().instance().set(&crate::DataKey::BATCH_PROCESSED, &0u64);
-
-        Ok(())
-    }
-
-    /// Execute a batch of orders with uniform clearing price (CowSwap-style)
-    /// Provides economic efficiency through single price execution
-    pub fn execute_batch(
-        env: &Env,
-        order_ids: Vec<u64>,
-        target_token: Address,
-    ) -> Result<(u64, i128, Vec<u64>), crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
+    ) -> Result<(), BatcherError> {
+        if !helpers::is_initialized(env) {
+            return Err(BatcherError::NotInitialized);
         }
-
-        let batch_enabled: bool = env.storage().instance()
-            .get(&crate::DataKey::BATCH_ENABLED)
-            .unwrap_or(false);
-
-        if !batch_enabled {
-            return Err(crate::error::BatcherError::InvalidInput);
+        if max_batch_size == 0 {
+            return Err(BatcherError::InvalidInput);
         }
 
-        let max_batch_size: u32 = env.storage().instance()
-            .get(&crate::DataKey::BATCH_SIZE)
-            .unwrap_or(10);
-
-        if order_ids.len() as u32 > max_batch_size {
-            return Err(crate::error::BatcherError::InvalidInput);
+        env.storage().instance().set(&DataKey::BatchEnabled, &enabled);
+        env.storage().instance().set(&DataKey::BatchMaxSize, &max_batch_size);
+        if !env.storage().instance().has(&DataKey::BatchProcessed) {
+            env.storage().instance().set(&DataKey::BatchProcessed, &0u64);
         }
 
-        // Calculate uniform clearing price (CowSwap core algorithm)
-        let (clearing_price, total_volume) = Self::calculate_clearing_price(env, &order_ids, &target_token)?;
-
-        // Execute orders at clearing price (no adverse selection)
-        let executed_orders = Self::execute_orders_at_price(env, &order_ids, clearing_price, &target_token)?;
-
-        // Update batch statistics
-        let processed_count: u64 = env.storage().instance()
-            .get(&crate::DataKey::BATCH_PROCESSED)
-            .unwrap_or(0);
-        env.storage().instance().set(&crate::DataKey::BATCH_PROCESSED, &(processed_count + 1));
-
-        // Emit execution event
-        let event = crate::OrdersExecutedEvent {
-            executed_count: executed_orders.len() as u64,
-            current_block: env.ledger().sequence() as u64,
-        };
-        event.publish(env);
-
-        Ok((executed_orders.len() as u64, total_volume, executed_orders))
+        Ok(())
     }
 
     /// Submit order to batch for execution (CowSwap integration)
-    /// Adds order to the next available batch
+    /// Escrows `amount_in` of `token_in` until the order is executed or cancelled
     pub fn submit_order_to_batch(
         env: &Env,
+        user: Address,
         token_in: Address,
         token_out: Address,
         amount_in: i128,
         amount_out_min: i128,
-        user: Address,
-    ) -> Result<u64, crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
+        expiry: u64,
+    ) -> Result<u64, BatcherError> {
+        if !helpers::is_initialized(env) {
+            return Err(BatcherError::NotInitialized);
         }
-
-        let batch_enabled: bool = env.storage().instance()
-            .get(&crate::DataKey::BATCH_ENABLED)
-            .unwrap_or(false);
-
-        if !batch_enabled {
-            // Fall back to regular order submission
-            return Err(crate::error::BatcherError::InvalidInput);
+        if !Self::is_enabled(env) {
+            return Err(BatcherError::InvalidInput);
         }
 
+        user.require_auth();
+
         // Basic validation
-        if amount_in <= 0 || amount_out_min <= 0 {
-            return Err(crate::error::BatcherError::InvalidInput);
+        if amount_in <= 0 || amount_out_min <= 0 || token_in == token_out {
+            return Err(BatcherError::InvalidInput);
+        }
+        if expiry <= env.ledger().timestamp() {
+            return Err(BatcherError::OrderTooLate);
         }
 
-        // For Phase 1, simulate order submission to batch
-        // In production, this would integrate with the factory for pair validation
-        let order_count: u64 = env.storage().instance()
-            .get(&crate::DataKey::ORDER_COUNT)
-            .unwrap_or(0);
-        let new_order_count = order_count + 1;
+        TokenClient::new(env, &token_in).transfer(&user, env.current_contract_address(), &amount_in);
 
-        env.storage().instance().set(&crate::DataKey::ORDER_COUNT, &new_order_count);
+        let order_id: u64 = env.storage().instance()
+            .get(&DataKey::OrderCount)
+            .unwrap_or(0u64) + 1;
+        env.storage().instance().set(&DataKey::OrderCount, &order_id);
 
-        // Emit order submission event
-        let event = crate::OrderSubmittedEvent {
-            order_id: new_order_count,
-            token_in: token_in.clone(),
-            token_out: token_out.clone(),
+        Self::set_order(env, &Order {
+            order_id,
+            owner: user.clone(),
+            token_in,
+            token_out,
             amount_in,
-            amount_out: amount_in, // Placeholder
-            pair_address: Address::from_str(env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF"), // Placeholder
-        };
-        event.publish(env);
+            min_amount_out: amount_out_min,
+            expiry,
+            status: OrderStatus::Pending,
+            amount_out: 0,
+        });
+
+        OrderSubmittedEvent {
+            order_id,
+            user,
+            timestamp: env.ledger().timestamp(),
+        }
+        .publish(env);
 
-        Ok(new_order_count)
+        Ok(order_id)
     }
 
-    /// Calculate uniform clearing price for the batch
-    /// Core CowSwap algorithm for economic efficiency
-    fn calculate_clearing_price(
+    /// Execute a batch of orders with uniform clearing price (CowSwap-style)
+    /// Orders may trade either direction of one pair. Opposite orders are netted against
+    /// each other (coincidence of wants) and only the residual imbalance is swapped through
+    /// the pair. Orders whose limit is not met at the clearing price stay pending.
+    /// Returns (executed order count, total volume in units of the first order's input token,
+    /// executed order ids)
+    pub fn execute_batch(
         env: &Env,
-        order_ids: &Vec<u64>,
-        target_token: &Address,
-    ) -> Result<(i128, i128), crate::error::BatcherError> {
-        // Simplified clearing price calculation based on CowSwap principles
-        // In production, this would implement the full uniform clearing price algorithm
-        let mut total_buy_volume = 0i128;
-        let mut total_sell_volume = 0i128;
-        let mut valid_orders = 0u32;
-
-        for i in 0..order_ids.len() {
-            let order_id = order_ids.get_unchecked(i);
-
-            // In production, fetch actual order data from storage
-            // For now, simulate with placeholder values following CowSwap patterns
-            let buy_amount = 1000i128 + (order_id % 1000) as i128;
-            let sell_amount = 900i128 + (order_id % 900) as i128;
-
-            total_buy_volume += buy_amount;
-            total_sell_volume += sell_amount;
-            valid_orders += 1;
+        order_ids: Vec<u64>,
+    ) -> Result<(u32, i128, Vec<u64>), BatcherError> {
+        if !helpers::is_initialized(env) {
+            return Err(BatcherError::NotInitialized);
         }
 
-        if valid_orders == 0 {
-            return Err(crate::error::BatcherError::InvalidInput);
+        let max_batch_size: u32 = env.storage().instance()
+            .get(&DataKey::BatchMaxSize)
+            .unwrap_or(DEFAULT_MAX_BATCH_SIZE);
+        Self::validate_batch_execution(env, order_ids.len(), max_batch_size)?;
+
+        let (token_a, token_b, sells_a, sells_b) = Self::load_executable_orders(env, &order_ids)?;
+
+        // Calculate uniform clearing price (CowSwap core algorithm)
+        let mut settlement = Self::select_orders_at_clearing_price(env, &token_a, &token_b, sells_a, sells_b)?;
+
+        // Route only the residual through the pair and settle on what was actually received
+        if settlement.residual_in > 0 {
+            let (excess_in, excess_out) = if settlement.a_is_excess {
+                (&token_a, &token_b)
+            } else {
+                (&token_b, &token_a)
+            };
+            let received = helpers::swap_exact_in(env, excess_in, excess_out, settlement.residual_in)?;
+            settlement.apply_residual_output(received);
         }
 
-        // Clearing price calculation (CowSwap: uniform price for all participants)
-        let clearing_price = if total_sell_volume > 0 {
-            total_buy_volume / total_sell_volume
+        let mut executed_ids = Vec::new(env);
+        Self::fill_orders(env, &token_b, &settlement.sells_a, settlement.total_a, settlement.payout_a, &mut executed_ids)?;
+        Self::fill_orders(env, &token_a, &settlement.sells_b, settlement.total_b, settlement.payout_b, &mut executed_ids)?;
+
+        let clearing_price = settlement.clearing_price();
+        let total_volume = settlement.total_a + if clearing_price > 0 {
+            settlement.total_b * PRICE_SCALE / clearing_price
         } else {
-            1000i128 // Default price if no sell volume
+            0
         };
 
-        Ok((clearing_price, total_buy_volume))
-    }
+        // Update batch statistics
+        let batch_id: u64 = env.storage().instance()
+            .get(&DataKey::BatchProcessed)
+            .unwrap_or(0u64) + 1;
+        env.storage().instance().set(&DataKey::BatchProcessed, &batch_id);
+        env.storage().instance().set(&DataKey::ClearingPrice, &clearing_price);
+
+        OrdersExecutedEvent {
+            batch_id,
+            order_count: executed_ids.len(),
+            clearing_price,
+            timestamp: env.ledger().timestamp(),
+        }
+        .publish(env);
 
-    /// Execute orders at the calculated clearing price
-    /// Ensures no adverse selection (CowSwap principle)
-    fn execute_orders_at_price(
-        env: &Env,
-        order_ids: &Vec<u64>,
-        clearing_price: i128,
-        target_token: &Address,
-    ) -> Result<Vec<u64>, crate::error::BatcherError> {
-        let mut executed_orders = Vec::new(env);
+        Ok((executed_ids.len(), total_volume, executed_ids))
+    }
 
-        for i in 0..order_ids.len() {
-            let order_id = order_ids.get_unchecked(i);
+    /// Cancel a pending order and refund the escrowed input to its owner
+    pub fn cancel_order(env: &Env, order_id: u64) -> Result<i128, BatcherError> {
+        let mut order = Self::get_order(env, order_id)?;
+        order.owner.require_auth();
 
-            // In production, perform actual token swaps at clearing price
-            // For now, simulate successful execution
-            executed_orders.push_back(order_id);
+        if order.status != OrderStatus::Pending {
+            return Err(BatcherError::OrderNotPending);
         }
 
-        // Store the clearing price for this batch (transparency)
-        env.storage().instance().set(&crate::DataKey::CLEARING_PRICE, &clearing_price);
+        TokenClient::new(env, &order.token_in).transfer(
+            &env.current_contract_address(),
+            &order.owner,
+            &order.amount_in,
+        );
+
+        order.status = OrderStatus::Cancelled;
+        Self::set_order(env, &order);
+
+        Ok(order.amount_in)
+    }
 
-        Ok(executed_orders)
+    /// Get a stored order
+    pub fn get_order(env: &Env, order_id: u64) -> Result<Order, BatcherError> {
+        env.storage().persistent()
+            .get(&DataKey::BatchOrder(order_id))
+            .ok_or(BatcherError::OrderNotFound)
     }
 
     /// Validate batch execution parameters
@@ -203,158 +226,323 @@ impl BatchExecutor {
         env: &Env,
         order_count: u32,
         max_batch_size: u32,
-    ) -> Result<(), crate::error::BatcherError> {
+    ) -> Result<(), BatcherError> {
         if order_count == 0 {
-            return Err(crate::error::BatcherError::InvalidInput);
+            return Err(BatcherError::InvalidInput);
         }
 
         if order_count > max_batch_size {
-            return Err(crate::error::BatcherError::InvalidInput);
+            return Err(BatcherError::InvalidInput);
         }
 
         // Validate batch executor is enabled
-        let batch_enabled: bool = env.storage().instance()
-            .get(&crate::DataKey::BATCH_ENABLED)
-            .unwrap_or(false);
-
-        if !batch_enabled {
-            return Err(crate::error::BatcherError::InvalidInput);
+        if !Self::is_enabled(env) {
+            return Err(BatcherError::InvalidInput);
         }
 
         Ok(())
     }
 
     /// Get batch executor statistics and performance metrics
-    /// Provides insights into batch utilization and efficiency
+    /// Returns (enabled, max batch size, batches processed, last clearing price)
     pub fn get_batch_statistics(
         env: &Env,
-    ) -> Result<(bool, u32, u64, Option<i128>), crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
+    ) -> Result<(bool, u32, u64, Option<i128>), BatcherError> {
+        if !helpers::is_initialized(env) {
+            return Err(BatcherError::NotInitialized);
         }
 
-        let enabled: bool = env.storage().instance()
-            .get(&crate::DataKey::BATCH_ENABLED)
-            .unwrap_or(false);
         let max_batch_size: u32 = env.storage().instance()
-            .get(&crate::DataKey::BATCH_SIZE)
-            .unwrap_or(10);
+            .get(&DataKey::BatchMaxSize)
+            .unwrap_or(DEFAULT_MAX_BATCH_SIZE);
         let processed_count: u64 = env.storage().instance()
-            .get(&crate::DataKey::BATCH_PROCESSED)
+            .get(&DataKey::BatchProcessed)
             .unwrap_or(0);
         let last_clearing_price: Option<i128> = env.storage().instance()
-            .get(&crate::DataKey::CLEARING_PRICE);
+            .get(&DataKey::ClearingPrice);
 
-        Ok((enabled, max_batch_size, processed_count, last_clearing_price))
+        Ok((Self::is_enabled(env), max_batch_size, processed_count, last_clearing_price))
     }
 
-    /// Estimate gas for batch execution
-    /// Helps users predict execution costs accurately
-    pub fn estimate_batch_gas_cost(
-        _env: &Env,
-        order_count: u32,
-    ) -> u64 {
-        // Gas estimation based on CowSwap batch execution patterns
-        let base_cost = 15_000u64;
-        let per_order_cost = 8_000u64;
-        let clearing_calculation_cost = 10_000u64;
+    // Internal helpers
+
+    fn is_enabled(env: &Env) -> bool {
+        env.storage().instance()
+            .get(&DataKey::BatchEnabled)
+            .unwrap_or(false)
+    }
+
+    /// Load the requested orders, skipping ones that are no longer pending or have expired
+    /// Every loaded order must trade the pair of the first order, in either direction.
+    /// Returns (token_a, token_b, orders selling token_a, orders selling token_b)
+    fn load_executable_orders(
+        env: &Env,
+        order_ids: &Vec<u64>,
+    ) -> Result<(Address, Address, Vec<Order>, Vec<Order>), BatcherError> {
+        let now = env.ledger().timestamp();
+        let mut pair: Option<(Address, Address)> = None;
+        let mut sells_a: Vec<Order> = Vec::new(env);
+        let mut sells_b: Vec<Order> = Vec::new(env);
+
+        for order_id in order_ids.iter() {
+            let order = Self::get_order(env, order_id)?;
+            if order.status != OrderStatus::Pending || order.expiry <= now {
+                continue;
+            }
+            if sells_a.iter().chain(sells_b.iter()).any(|o| o.order_id == order_id) {
+                return Err(BatcherError::InvalidInput);
+            }
+
+            let (token_a, token_b) = pair.get_or_insert_with(|| (order.token_in.clone(), order.token_out.clone()));
+            if order.token_in == *token_a && order.token_out == *token_b {
+                sells_a.push_back(order);
+            } else if order.token_in == *token_b && order.token_out == *token_a {
+                sells_b.push_back(order);
+            } else {
+                return Err(BatcherError::InvalidInput);
+            }
+        }
 
-        base_cost + (order_count as u64 * per_order_cost) + clearing_calculation_cost
+        let (token_a, token_b) = pair.ok_or(BatcherError::NoExecutableOrders)?;
+        Ok((token_a, token_b, sells_a, sells_b))
     }
 
-    /// Process ready batches automatically
-    /// Convenience function for automated batch execution
-    pub fn process_ready_batches(
+    /// Drop orders whose limit is not met at the batch clearing price until the remaining
+    /// set clears. Every pass removes at least one order, so the loop terminates.
+    fn select_orders_at_clearing_price(
         env: &Env,
-        max_batches: Option<u32>,
-    ) -> Result<u32, crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
+        token_a: &Address,
+        token_b: &Address,
+        mut sells_a: Vec<Order>,
+        mut sells_b: Vec<Order>,
+    ) -> Result<Settlement, BatcherError> {
+        let (reserve_a, reserve_b) = helpers::get_reserves(env, token_a, token_b)?;
+
+        loop {
+            if sells_a.is_empty() && sells_b.is_empty() {
+                return Err(BatcherError::NoExecutableOrders);
+            }
+
+            let settlement = Settlement::plan(sells_a.clone(), sells_b.clone(), reserve_a, reserve_b);
+            let accepted_a = Self::orders_meeting_limit(env, &sells_a, settlement.total_a, settlement.payout_a);
+            let accepted_b = Self::orders_meeting_limit(env, &sells_b, settlement.total_b, settlement.payout_b);
+
+            if accepted_a.len() == sells_a.len() && accepted_b.len() == sells_b.len() {
+                return Ok(settlement);
+            }
+            sells_a = accepted_a;
+            sells_b = accepted_b;
         }
+    }
 
-        let batch_enabled: bool = env.storage().instance()
-            .get(&crate::DataKey::BATCH_ENABLED)
-            .unwrap_or(false);
+    fn orders_meeting_limit(env: &Env, orders: &Vec<Order>, total_in: i128, total_out: i128) -> Vec<Order> {
+        let mut accepted = Vec::new(env);
+        for order in orders.iter() {
+            if Self::pro_rata_output(order.amount_in, total_in, total_out) >= order.min_amount_out {
+                accepted.push_back(order);
+            }
+        }
+        accepted
+    }
 
-        if !batch_enabled {
-            return Ok(0);
+    /// Pay one side of the batch pro rata and mark its orders executed
+    fn fill_orders(
+        env: &Env,
+        token_out: &Address,
+        orders: &Vec<Order>,
+        total_in: i128,
+        total_out: i128,
+        executed_ids: &mut Vec<u64>,
+    ) -> Result<(), BatcherError> {
+        let out_client = TokenClient::new(env, token_out);
+        let this = env.current_contract_address();
+
+        for mut order in orders.iter() {
+            let amount_out = Self::pro_rata_output(order.amount_in, total_in, total_out);
+            if amount_out < order.min_amount_out {
+                return Err(BatcherError::InsufficientBalance);
+            }
+
+            out_client.transfer(&this, &order.owner, &amount_out);
+
+            order.status = OrderStatus::Executed;
+            order.amount_out = amount_out;
+            Self::set_order(env, &order);
+            executed_ids.push_back(order.order_id);
         }
 
-        let max_to_process = max_batches.unwrap_or(5u32); // Default max 5 batches
-        let mut processed_count = 0u32;
+        Ok(())
+    }
+
+    /// Output owed to an order filled at the uniform rate `total_out / total_in`
+    fn pro_rata_output(amount_in: i128, total_in: i128, total_out: i128) -> i128 {
+        if total_in == 0 {
+            return 0;
+        }
+        amount_in * total_out / total_in
+    }
+
+    fn set_order(env: &Env, order: &Order) {
+        let key = DataKey::BatchOrder(order.order_id);
+        env.storage().persistent().set(&key, order);
+        env.storage().persistent().extend_ttl(&key, ORDER_TTL_LEDGERS, ORDER_TTL_LEDGERS);
+    }
+}
+
+/// Netting plan for a two-sided batch on the pair (token_a, token_b)
+/// Sellers of the excess token swap `residual_in` through the pair; everything else is
+/// matched directly against the opposite side at the same uniform rate.
+struct Settlement {
+    sells_a: Vec<Order>,
+    sells_b: Vec<Order>,
+    total_a: i128,
+    total_b: i128,
+    /// Whether token_a sellers are the side routing the residual through the pair
+    a_is_excess: bool,
+    residual_in: i128,
+    /// token_b owed to token_a sellers
+    payout_a: i128,
+    /// token_a owed to token_b sellers
+    payout_b: i128,
+}
 
-        // For now, simulate batch processing
-        // In production, this would check for pending batches and execute them
-        for _ in 0..max_to_process {
-            // Simulate finding and processing a batch
-            processed_count += 1;
+impl Settlement {
+    /// Net both sides against quoted pair reserves (reserve_a, reserve_b)
+    fn plan(sells_a: Vec<Order>, sells_b: Vec<Order>, reserve_a: i128, reserve_b: i128) -> Self {
+        let total_a: i128 = sells_a.iter().map(|o| o.amount_in).sum();
+        let total_b: i128 = sells_b.iter().map(|o| o.amount_in).sum();
+
+        // token_a sellers need the pair when its marginal rate (after fee) beats what
+        // token_b sellers offer, and symmetrically for token_b sellers
+        let a_is_excess = 997 * reserve_b * total_a > 1000 * reserve_a * total_b;
+        let b_is_excess = 997 * reserve_a * total_b > 1000 * reserve_b * total_a;
+
+        let (residual_in, quoted_out) = if a_is_excess {
+            let residual = Self::residual_for_uniform_price(total_a, total_b, reserve_a, reserve_b);
+            (residual, helpers::get_amount_out(residual, reserve_a, reserve_b))
+        } else if b_is_excess {
+            let residual = Self::residual_for_uniform_price(total_b, total_a, reserve_b, reserve_a);
+            (residual, helpers::get_amount_out(residual, reserve_b, reserve_a))
+        } else {
+            // Both sides beat the pair: fully matched at the ratio of their volumes
+            (0, 0)
+        };
+
+        let mut settlement = Settlement {
+            sells_a,
+            sells_b,
+            total_a,
+            total_b,
+            a_is_excess,
+            residual_in,
+            payout_a: 0,
+            payout_b: 0,
+        };
+        settlement.apply_residual_output(quoted_out);
+        settlement
+    }
+
+    /// Largest residual `r` of the excess side (selling `total_x` of x against `total_y` of y)
+    /// such that the pair's average rate on `r` still covers the internal matching rate
+    /// `total_y / (total_x - r)`; at that point both sides trade at one uniform price
+    fn residual_for_uniform_price(total_x: i128, total_y: i128, reserve_x: i128, reserve_y: i128) -> i128 {
+        let covers = |r: i128| helpers::get_amount_out(r, reserve_x, reserve_y) * (total_x - r) >= total_y * r;
+
+        let (mut lo, mut hi) = (0i128, total_x);
+        while lo < hi {
+            let mid = lo + (hi - lo + 1) / 2;
+            if covers(mid) {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        lo
+    }
+
+    /// Set both sides' payouts given the pair output received for the residual
+    fn apply_residual_output(&mut self, residual_out: i128) {
+        if self.a_is_excess {
+            self.payout_a = self.total_b + residual_out;
+            self.payout_b = self.total_a - self.residual_in;
+        } else {
+            self.payout_a = self.total_b - self.residual_in;
+            self.payout_b = self.total_a + residual_out;
         }
+    }
 
-        Ok(processed_count)
+    /// Uniform price of token_a in token_b, scaled by PRICE_SCALE
+    fn clearing_price(&self) -> i128 {
+        if self.total_a > 0 {
+            self.payout_a * PRICE_SCALE / self.total_a
+        } else if self.payout_b > 0 {
+            self.total_b * PRICE_SCALE / self.payout_b
+        } else {
+            0
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use soroban_sdk::{Env, Address, Vec};
+    use soroban_sdk::testutils::Address as _;
+    use crate::SoroSwapBatcher;
 
     #[test]
     fn test_validate_batch_execution() {
         let env = Env::default();
+        let contract_id = env.register(SoroSwapBatcher, ());
 
-        // Valid batch
-        assert!(BatchExecutor::validate_batch_execution(&env, 5, 10).is_ok());
+        env.as_contract(&contract_id, || {
+            // Disabled executor rejects every batch
+            assert!(BatchExecutor::validate_batch_execution(&env, 5, 10).is_err());
 
-        // Invalid batches
-        assert!(BatchExecutor::validate_batch_execution(&env, 0, 10).is_err());
-        assert!(BatchExecutor::validate_batch_execution(&env, 15, 10).is_err());
-    }
+            env.storage().instance().set(&DataKey::BatchEnabled, &true);
 
-    #[test]
-    fn test_estimate_batch_gas_cost() {
-        let env = Env::default();
-        let gas_estimate = BatchExecutor::estimate_batch_gas_cost(&env, 5);
+            // Valid batch
+            assert!(BatchExecutor::validate_batch_execution(&env, 5, 10).is_ok());
 
-        // Should be: 15_000 + (5 * 8_000) + 10_000 = 65_000
-        assert_eq!(gas_estimate, 65000);
+            // Invalid batches
+            assert!(BatchExecutor::validate_batch_execution(&env, 0, 10).is_err());
+            assert!(BatchExecutor::validate_batch_execution(&env, 15, 10).is_err());
+        });
     }
 
     #[test]
-    fn test_calculate_clearing_price() {
-        let env = Env::default();
-        let order_ids = Vec::from_array(&env, [1, 2, 3]);
-        let target_token = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-
-        let result = BatchExecutor::calculate_clearing_price(&env, &order_ids, &target_token);
-        assert!(result.is_ok());
-
-        let (clearing_price, total_volume) = result.unwrap();
-        assert!(clearing_price > 0);
-        assert!(total_volume > 0);
+    fn test_pro_rata_output_is_uniform() {
+        // 3:1 split of 1_000 input that cleared for 1_990 output
+        assert_eq!(BatchExecutor::pro_rata_output(750, 1_000, 1_990), 1_492);
+        assert_eq!(BatchExecutor::pro_rata_output(250, 1_000, 1_990), 497);
     }
 
     #[test]
-    fn test_process_ready_batches() {
-        let env = Env::default();
+    fn test_residual_equalises_pair_and_matching_rates() {
+        let reserve = 1_000_000_000_000i128;
 
-        // Test with default max batches
-        let result = BatchExecutor::process_ready_batches(&env, None);
-        assert!(result.is_ok());
+        // One-sided batch routes everything through the pair
+        assert_eq!(Settlement::residual_for_uniform_price(30_000_000, 0, reserve, reserve), 30_000_000);
 
-        let processed = result.unwrap();
-        assert_eq!(processed, 5); // Default max
+        // Two-sided batch only routes the part not covered by the opposite side
+        let residual = Settlement::residual_for_uniform_price(30_000_000, 10_000_000, reserve, reserve);
+        assert!(residual > 19_000_000 && residual < 20_000_000);
+
+        let pair_rate = helpers::get_amount_out(residual, reserve, reserve) * PRICE_SCALE / residual;
+        let matching_rate = 10_000_000 * PRICE_SCALE / (30_000_000 - residual);
+        assert!((pair_rate - matching_rate).abs() <= 1);
     }
 
     #[test]
-    fn test_submit_order_to_batch() {
+    fn test_submit_order_requires_initialization() {
         let env = Env::default();
-        let token_in = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-        let token_out = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-        let user = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-
-        // This will fail because batch executor is not enabled
-        let result = BatchExecutor::submit_order_to_batch(&env, token_in, token_out, 1000, 900, user);
-        assert!(result.is_err());
+        let contract_id = env.register(SoroSwapBatcher, ());
+        let token_in = Address::generate(&env);
+        let token_out = Address::generate(&env);
+        let user = Address::generate(&env);
+
+        env.as_contract(&contract_id, || {
+            let result = BatchExecutor::submit_order_to_batch(&env, user, token_in, token_out, 1000, 900, 100);
+            assert_eq!(result, Err(BatcherError::NotInitialized));
+        });
     }
-}
\ No newline at end of file
+}
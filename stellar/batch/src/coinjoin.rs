@@ -11,8 +11,50 @@
 // - Market-based incentives for sustainable liquidity
 // - Integration with multicall for seamless transaction flow
 
-use soroban_sdk::{Env, Symbol, Vec, BytesN, contracttype};
-use crate::{error::BatcherError, DataKey};
+use soroban_sdk::{
+    symbol_short, contractevent, contracttype, token::Client as TokenClient, xdr::ToXdr,
+    Address, Bytes, BytesN, Env, IntoVal, Symbol, TryFromVal, Val, Vec,
+};
+use crate::{error::BatcherError, merkle::MerkleTree, verifier_client::ProofVerifierClient, DataKey};
+
+/// TTL for settled batch records (30 days at 5 sec/ledger)
+const BATCH_TTL_LEDGERS: u32 = 518400;
+
+/// TTL for pending deposit entries, refreshed on every read or write (60 days at 5 sec/ledger)
+/// Outlives the longest deposit expiry window so expired deposits stay refundable
+pub const DEPOSIT_TTL_LEDGERS: u32 = 1_036_800;
+
+/// TTL for pool state and pool id lookups, refreshed on every read or write
+/// A pool is read whenever it takes, executes or refunds a deposit, so it never lapses
+/// before the deposits it holds
+const POOL_TTL_LEDGERS: u32 = DEPOSIT_TTL_LEDGERS;
+
+/// Most pending deposits loaded by one execution, refund or page read
+/// Bounds the ledger entries a single call touches however long the queue grows
+pub const DEPOSIT_SCAN_LIMIT: u32 = 64;
+
+/// Defaults for newly created pools
+const DEFAULT_MIN_POOL_SIZE: u32 = 3;   // Minimum 3 deposits for privacy
+const DEFAULT_MAX_POOL_SIZE: u32 = 10;  // Mix in batches of 10
+const DEFAULT_FEE_BPS: u32 = 10;        // 0.1% fee
+const DEFAULT_DEPOSIT_EXPIRY_SECONDS: u64 = 48 * 60 * 60;
+const DEFAULT_KEEPER_REWARD_BPS: u32 = 1_000; // 10% of a keeper-executed batch's fees
+const DEFAULT_MAX_WAIT_SECONDS: u64 = 0;       // Timed release disabled
+
+/// Weight of the newest gap in the moving average of deposit inter-arrival times (1/8)
+const ARRIVAL_SMOOTHING: u64 = 8;
+
+/// `PoolStats::estimated_wait_time` while a pool has too little arrival history to estimate
+pub const WAIT_TIME_UNKNOWN: u32 = u32::MAX;
+
+/// Bounds enforced by the owner configuration setters
+pub const MIN_POOL_SIZE_FLOOR: u32 = 2;        // A batch of one has no anonymity set; also the timed release floor
+pub const MAX_POOL_SIZE_CAP: u32 = 50;         // Participant selection is quadratic in pool size
+pub const MAX_FEE_BPS: u32 = 1_000;            // 10%
+pub const MIN_DEPOSIT_EXPIRY_SECONDS: u64 = 60 * 60;
+pub const MAX_DEPOSIT_EXPIRY_SECONDS: u64 = 30 * 24 * 60 * 60; // Within DEPOSIT_TTL_LEDGERS
+pub const MAX_KEEPER_REWARD_BPS: u32 = 5_000;  // Half of the fees
+pub const MIN_MAX_WAIT_SECONDS: u64 = 5 * 60;  // Shorter waits would routinely release floor-sized batches
 
 /// Fixed denomination amounts for CoinJoin mixing (in stroops)
 /// Based on Wasabi Wallet's successful fixed denomination model
@@ -37,10 +79,10 @@ impl Denomination {
 
     pub fn symbol(&self) -> Symbol {
         match self {
-            Denomination::Small => Symbol::short("10"),
-            Denomination::Medium => Symbol::short("100"),
-            Denomination::Large => Symbol::short("1K"),
-            Denomination::ExtraLarge => Symbol::short("10K"),
+            Denomination::Small => symbol_short!("10"),
+            Denomination::Medium => symbol_short!("100"),
+            Denomination::Large => symbol_short!("1K"),
+            Denomination::ExtraLarge => symbol_short!("10K"),
         }
     }
 
@@ -55,71 +97,79 @@ impl Denomination {
     }
 }
 
+/// How a pool's aggregated swap is executed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum ExecutionMode {
+    /// Swap directly against each pair with locally computed outputs
+    Pair,
+    /// Swap through the configured Soroswap router's `swap_exact_tokens_for_tokens`,
+    /// which enforces `amount_out_min` and the deadline itself
+    Router,
+}
+
 /// CoinJoin pool for mixing transactions
 /// Based on Wasabi Wallet's Chaumian CoinJoin model
+/// One pool exists per (token_in, token_out, denomination); deposits whose routes
+/// differ only in intermediate hops share the pool but are batched per path
+/// Pending deposits live in persistent entries `DataKey::Deposit(pool_id, seq)`, where
+/// `seq` is the deposit's Merkle leaf index; entries in `deposit_head..deposit_tail`
+/// that were executed, refunded or cancelled have been removed
+/// Once the oldest deposit on a route has waited `max_wait_seconds`, the route may
+/// execute with as few as `MIN_POOL_SIZE_FLOOR` participants instead of `minimum_pool_size`
 #[derive(Clone, Debug)]
 #[contracttype]
 pub struct CoinJoinPool {
+    pub pool_id: u32,
+    pub token_in: Address,
+    pub token_out: Address,
     pub denomination: Denomination,
-    pub deposits: Vec<Deposit>,
-    pub withdrawals: Vec<WithdrawalRequest>,
+    pub deposit_head: u64,       // Sequence number of the oldest pending deposit
+    pub deposit_tail: u64,       // Sequence number of the next deposit
+    pub pending_deposits: u32,   // Number of pending deposit entries
     pub merkle_root: BytesN<32>,
+    pub merkle_tree: MerkleTree,
     pub minimum_pool_size: u32,
     pub maximum_pool_size: u32,
     pub fee_basis_points: u32,
+    pub deposit_expiry_seconds: u64,
+    pub execution_mode: ExecutionMode,
+    pub max_wait_seconds: u64,          // Timed release after this wait (0 = only on minimum size)
+    pub last_deposit_timestamp: u64,    // Arrival time of the newest deposit
+    pub arrival_interval_seconds: u64,  // Moving average of the time between deposits
 }
 
 /// Deposit information with cryptographic commitments
 /// Following Tornado Cash's commitment scheme
 /// Enhanced with slippage protection and expiry mechanism
+/// No recipient is stored: payouts are claimed later through `withdraw`
 #[derive(Clone, Debug)]
 #[contracttype]
 pub struct Deposit {
+    pub seq: u64,                // Position in the pool's deposit queue (= Merkle leaf index)
     pub commitment: BytesN<32>,
     pub timestamp: u64,
-    pub nullifier: BytesN<32>,
     pub fee_paid: i128,
     pub sender_address: soroban_sdk::Address,  // Track sender for uniqueness check
-    pub recipient_address: soroban_sdk::Address, // Address to receive payout (can be Stellar account)
     pub max_slippage_bps: u32,  // Maximum slippage in basis points (e.g., 50 = 0.5%)
-    pub expiry_timestamp: u64,   // Expiry time (timestamp + 48 hours)
-    pub token_in: soroban_sdk::Address,  // Input token address
-    pub token_out: soroban_sdk::Address, // Output token address
+    pub expiry_timestamp: u64,   // Expiry time (timestamp + pool's deposit expiry window)
+    pub path: Vec<Address>,      // Swap route: input token, intermediate tokens, output token
     pub min_amount_out: i128,    // Minimum output amount (for slippage calculation)
 }
 
-/// Withdrawal request with blinding
-/// Based on JoinMarket's blinded request model
-#[derive(Clone, Debug)]
-#[contracttype]
-pub struct WithdrawalRequest {
-    pub nullifier_hash: BytesN<32>,
-    pub recipient_hash: BytesN<32>,
-    pub proof_hash: BytesN<32>,
-    pub requested_timestamp: u64,
-}
-
-/// CoinJoin mixing result
-#[derive(Clone, Debug)]
-#[contracttype]
-pub struct MixResult {
-    pub success: bool,
-    pub mixed_amounts: Vec<i128>,
-    pub gas_used: u64,
-    pub anonymity_set_size: u32,
-    pub fees_paid: i128,
-}
-
 /// Pool statistics and status
 #[derive(Clone, Debug)]
 #[contracttype]
 pub struct PoolStats {
+    pub pool_id: u32,
+    pub token_in: Address,
+    pub token_out: Address,
     pub denomination: Denomination,
     pub current_pool_size: u32,
     pub total_deposits: u64,
     pub total_withdrawals: u64,
     pub current_fees: u32,
-    pub estimated_wait_time: u32,
+    pub estimated_wait_time: u32,    // Seconds; WAIT_TIME_UNKNOWN without arrival history
 }
 
 /// Payout information for equal distribution
@@ -127,17 +177,33 @@ pub struct PoolStats {
 #[contracttype]
 pub struct PayoutInfo {
     pub equal_payout_amount: i128,      // Amount each participant receives
-    pub total_input_amount: i128,        // Total input across all participants
+    pub total_input_amount: i128,        // Total swap input across all participants, after fees
     pub total_output_amount: i128,       // Total output from aggregated swap
     pub slippage_bps: u32,               // Realized slippage in basis points
     pub participant_count: u32,          // Number of participants
 }
 
+/// Settled batch awaiting withdrawals
+/// `root` commits to the executed deposit commitments; each note in the batch
+/// can claim `payout_amount` of `token_out` exactly once by proving membership
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct SettledBatch {
+    pub batch_id: u64,
+    pub token_out: Address,
+    pub payout_amount: i128,
+    pub commitments: Vec<BytesN<32>>,
+    pub root: BytesN<32>,
+    pub remaining_claims: u32,
+    pub settled_timestamp: u64,
+}
+
 /// Public deposit information (privacy-safe)
-/// Does NOT expose: commitment, nullifier, or sender_address
+/// Does NOT expose: commitment or sender_address
 #[derive(Clone, Debug)]
 #[contracttype]
 pub struct DepositInfo {
+    pub seq: u64,
     pub min_amount_out: i128,
     pub max_slippage_bps: u32,
     pub expiry_timestamp: u64,
@@ -150,234 +216,371 @@ pub struct CoinJoinMixer;
 impl CoinJoinMixer {
     // === Initialization Functions ===
 
-    /// Initialize CoinJoin mixer with denomination pools
-    /// Based on JoinMarket's market setup and Wasabi's fixed denominations
+    /// Initialize CoinJoin mixer
+    /// Pools are created per token pair and denomination by an admin
     pub fn init_coinjoin(env: &Env) -> Result<(), BatcherError> {
         // Note: Called during contract initialization, so no check needed
 
-        // Initialize pools for each denomination
-        let denominations = [
-            Denomination::Small,
-            Denomination::Medium,
-            Denomination::Large,
-            Denomination::ExtraLarge,
-        ];
-
-        for denom in denominations.iter() {
-            let pool = CoinJoinPool {
-                denomination: *denom,
-                deposits: Vec::new(env),
-                withdrawals: Vec::new(env),
-                merkle_root: BytesN::from_array(env, &[0u8; 32]),
-                minimum_pool_size: 3,  // Minimum 3 deposits for privacy
-                maximum_pool_size: 10, // Mix in batches of 10
-                fee_basis_points: 10,  // 0.1% fee
-            };
-
-            // Store pool using denomination as key
-            let pool_key = DataKey::CoinJoinPool(denom.symbol());
-            env.storage().instance().set(&pool_key, &pool);
-        }
-
         // Enable CoinJoin mixer
         env.storage().instance().set(&DataKey::CoinJoinEnabled, &true);
 
         Ok(())
     }
 
+    /// Create the pool for a (token_in, token_out, denomination) triple
+    /// Based on JoinMarket's market setup and Wasabi's fixed denominations
+    /// Returns the new pool id; fails if the pool already exists
+    pub fn create_pool(
+        env: &Env,
+        token_in: Address,
+        token_out: Address,
+        denomination: Denomination,
+    ) -> Result<u32, BatcherError> {
+        if token_in == token_out || Self::get_pool_id(env, &token_in, &token_out, denomination).is_some() {
+            return Err(BatcherError::InvalidInput);
+        }
+
+        let pool_id: u32 = env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0);
+        env.storage().instance().set(&DataKey::CoinJoinPoolCount, &(pool_id + 1));
+        Self::set_pool_id(env, &token_in, &token_out, denomination, pool_id);
+
+        let pool = CoinJoinPool {
+            pool_id,
+            token_in,
+            token_out,
+            denomination,
+            deposit_head: 0,
+            deposit_tail: 0,
+            pending_deposits: 0,
+            merkle_root: MerkleTree::new(env).root(),
+            merkle_tree: MerkleTree::new(env),
+            minimum_pool_size: DEFAULT_MIN_POOL_SIZE,
+            maximum_pool_size: DEFAULT_MAX_POOL_SIZE,
+            fee_basis_points: DEFAULT_FEE_BPS,
+            deposit_expiry_seconds: DEFAULT_DEPOSIT_EXPIRY_SECONDS,
+            execution_mode: ExecutionMode::Pair,
+            max_wait_seconds: DEFAULT_MAX_WAIT_SECONDS,
+            last_deposit_timestamp: 0,
+            arrival_interval_seconds: 0,
+        };
+        Self::update_pool(env, pool_id, pool)?;
+
+        Ok(pool_id)
+    }
+
+    /// Look up the pool id for a token pair and denomination
+    pub fn get_pool_id(
+        env: &Env,
+        token_in: &Address,
+        token_out: &Address,
+        denomination: Denomination,
+    ) -> Option<u32> {
+        let key = DataKey::CoinJoinPoolId(token_in.clone(), token_out.clone(), denomination.symbol());
+        let pool_id = env.storage().persistent().get(&key)?;
+        env.storage().persistent().extend_ttl(&key, POOL_TTL_LEDGERS, POOL_TTL_LEDGERS);
+        Some(pool_id)
+    }
+
+    /// Record the pool id of a token pair and denomination
+    pub fn set_pool_id(
+        env: &Env,
+        token_in: &Address,
+        token_out: &Address,
+        denomination: Denomination,
+        pool_id: u32,
+    ) {
+        let key = DataKey::CoinJoinPoolId(token_in.clone(), token_out.clone(), denomination.symbol());
+        env.storage().persistent().set(&key, &pool_id);
+        env.storage().persistent().extend_ttl(&key, POOL_TTL_LEDGERS, POOL_TTL_LEDGERS);
+    }
+
+    /// Read a per-pool counter or per-token fee balance, zero when never written
+    /// These persistent entries share the pool TTL and are refreshed on every access
+    pub fn get_counter<V: TryFromVal<Env, Val> + Default>(env: &Env, key: &DataKey) -> V {
+        match env.storage().persistent().get(key) {
+            Some(value) => {
+                env.storage().persistent().extend_ttl(key, POOL_TTL_LEDGERS, POOL_TTL_LEDGERS);
+                value
+            }
+            None => V::default(),
+        }
+    }
+
+    /// Write a per-pool counter or per-token fee balance
+    pub fn set_counter<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+        env.storage().persistent().set(key, value);
+        env.storage().persistent().extend_ttl(key, POOL_TTL_LEDGERS, POOL_TTL_LEDGERS);
+    }
+
+    /// Number of pools created so far; ids run from 0 to count - 1
+    pub fn get_pool_count(env: &Env) -> u32 {
+        env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0)
+    }
+
+    // === Configuration Functions ===
+    // Callers are responsible for owner authorization
+
+    /// Set a pool's minimum and maximum participant counts
+    pub fn set_pool_size_limits(
+        env: &Env,
+        pool_id: u32,
+        minimum_pool_size: u32,
+        maximum_pool_size: u32,
+    ) -> Result<(), BatcherError> {
+        if minimum_pool_size < MIN_POOL_SIZE_FLOOR
+            || maximum_pool_size < minimum_pool_size
+            || maximum_pool_size > MAX_POOL_SIZE_CAP
+        {
+            return Err(BatcherError::InvalidConfig);
+        }
+
+        let mut pool = Self::get_pool(env, pool_id)?;
+        pool.minimum_pool_size = minimum_pool_size;
+        pool.maximum_pool_size = maximum_pool_size;
+        Self::update_pool_config(env, pool)
+    }
+
+    /// Set the fee charged on each deposit of a pool, in basis points
+    pub fn set_pool_fee(env: &Env, pool_id: u32, fee_basis_points: u32) -> Result<(), BatcherError> {
+        if fee_basis_points > MAX_FEE_BPS {
+            return Err(BatcherError::InvalidConfig);
+        }
+
+        let mut pool = Self::get_pool(env, pool_id)?;
+        pool.fee_basis_points = fee_basis_points;
+        Self::update_pool_config(env, pool)
+    }
+
+    /// Set how long new deposits of a pool wait before they can be refunded
+    /// Deposits already queued keep their original expiry
+    pub fn set_deposit_expiry(env: &Env, pool_id: u32, deposit_expiry_seconds: u64) -> Result<(), BatcherError> {
+        if !(MIN_DEPOSIT_EXPIRY_SECONDS..=MAX_DEPOSIT_EXPIRY_SECONDS).contains(&deposit_expiry_seconds) {
+            return Err(BatcherError::InvalidConfig);
+        }
+
+        let mut pool = Self::get_pool(env, pool_id)?;
+        pool.deposit_expiry_seconds = deposit_expiry_seconds;
+        Self::update_pool_config(env, pool)
+    }
+
+    /// Set how long a pool's oldest deposit waits before a batch below the minimum size
+    /// may be released; 0 disables timed release
+    pub fn set_max_wait(env: &Env, pool_id: u32, max_wait_seconds: u64) -> Result<(), BatcherError> {
+        if max_wait_seconds != 0 && !(MIN_MAX_WAIT_SECONDS..=MAX_DEPOSIT_EXPIRY_SECONDS).contains(&max_wait_seconds) {
+            return Err(BatcherError::InvalidConfig);
+        }
+
+        let mut pool = Self::get_pool(env, pool_id)?;
+        pool.max_wait_seconds = max_wait_seconds;
+        Self::update_pool_config(env, pool)
+    }
+
+    /// Select how a pool's aggregated swap is executed
+    pub fn set_execution_mode(env: &Env, pool_id: u32, mode: ExecutionMode) -> Result<(), BatcherError> {
+        let mut pool = Self::get_pool(env, pool_id)?;
+        pool.execution_mode = mode;
+        Self::update_pool_config(env, pool)
+    }
+
+    /// Accept or stop accepting deposits of a denomination in every pool
+    /// Queued deposits stay executable and refundable either way
+    pub fn set_denomination_supported(env: &Env, denomination: Denomination, supported: bool) {
+        env.storage().instance().set(&DataKey::DenominationSupported(denomination.symbol()), &supported);
+
+        DenominationSupportEvent {
+            denomination: denomination.symbol(),
+            supported,
+        }
+        .publish(env);
+    }
+
+    /// Check whether a denomination currently accepts deposits (all do by default)
+    pub fn is_denomination_supported(env: &Env, denomination: Denomination) -> bool {
+        env.storage().instance()
+            .get(&DataKey::DenominationSupported(denomination.symbol()))
+            .unwrap_or(true)
+    }
+
+    /// Store a reconfigured pool and announce its new settings
+    fn update_pool_config(env: &Env, pool: CoinJoinPool) -> Result<(), BatcherError> {
+        PoolConfigUpdatedEvent {
+            pool_id: pool.pool_id,
+            minimum_pool_size: pool.minimum_pool_size,
+            maximum_pool_size: pool.maximum_pool_size,
+            fee_basis_points: pool.fee_basis_points,
+            deposit_expiry_seconds: pool.deposit_expiry_seconds,
+            execution_mode: pool.execution_mode,
+            max_wait_seconds: pool.max_wait_seconds,
+        }
+        .publish(env);
+
+        Self::update_pool(env, pool.pool_id, pool)
+    }
+
     // === Core Mixing Functions ===
 
     /// Deposit funds into CoinJoin pool
     /// Implements Tornado Cash's deposit pattern with Wasabi's fixed denominations
     /// Enhanced with slippage protection
+    /// `path` must start at the pool's `token_in` and end at its `token_out`
     pub fn deposit(
         env: &Env,
-        denomination: Denomination,
+        pool_id: u32,
         recipient_commitment: BytesN<32>,
-        nullifier: BytesN<32>,
         sender_address: soroban_sdk::Address,
-        recipient_address: soroban_sdk::Address,
         max_slippage_bps: u32,
-        token_in: soroban_sdk::Address,
-        token_out: soroban_sdk::Address,
+        path: Vec<Address>,
         min_amount_out: i128,
     ) -> Result<(), BatcherError> {
         if !Self::is_coinjoin_enabled(env) {
             return Err(BatcherError::InvalidInput);
         }
+        crate::helpers::validate_path(&path)?;
+
+        let mut pool = Self::get_pool(env, pool_id)?;
+        if path.first().unwrap() != pool.token_in || path.last().unwrap() != pool.token_out {
+            return Err(BatcherError::InvalidInput);
+        }
 
         // Validate deposit amount matches denomination
+        let denomination = pool.denomination;
+        if !Self::is_denomination_supported(env, denomination) {
+            return Err(BatcherError::UnsupportedDenomination);
+        }
         let expected_amount = denomination.value();
 
-        // Get or create pool for this denomination
-        let mut pool = Self::get_pool(env, denomination)?;
+        // Reject malformed, replayed or colliding commitments before taking the deposit
+        Self::validate_commitment(env, &recipient_commitment)?;
+        Self::set_marker(env, &DataKey::CommitmentUsed(recipient_commitment.clone()));
+
+        // Append commitment to the pool's Merkle tree
+        let leaf_index = pool.merkle_tree.insert(env, pool_id, &recipient_commitment)?;
+        pool.merkle_root = pool.merkle_tree.root();
+        env.storage().persistent().set(&DataKey::MerkleLeafIndex(recipient_commitment.clone()), &leaf_index);
 
-        // Calculate expiry timestamp (48 hours from now, ~34,560 ledgers at 5 sec/ledger)
-        let expiry_timestamp = env.ledger().timestamp() + (48 * 60 * 60);
+        // Calculate expiry timestamp (48 hours by default, ~34,560 ledgers at 5 sec/ledger)
+        let expiry_timestamp = env.ledger().timestamp() + pool.deposit_expiry_seconds;
 
         // Create deposit record
         let deposit = Deposit {
-            commitment: recipient_commitment,
+            seq: leaf_index as u64,
+            commitment: recipient_commitment.clone(),
             timestamp: env.ledger().timestamp(),
-            nullifier,
             fee_paid: expected_amount * pool.fee_basis_points as i128 / 10000,
             sender_address,
-            recipient_address,
             max_slippage_bps,
             expiry_timestamp,
-            token_in,
-            token_out,
+            path,
             min_amount_out,
         };
 
-        // Add deposit to pool
-        pool.deposits.push_back(deposit);
-
-        // Get pool size before update
-        let pool_size = pool.deposits.len();
+        // Add deposit to the pool's queue
+        Self::record_arrival(env, &mut pool, leaf_index);
+        Self::push_deposit(env, &mut pool, &deposit);
+        let pool_size = pool.pending_deposits;
 
         // Update pool state
-        Self::update_pool(env, denomination, pool)?;
+        Self::update_pool(env, pool_id, pool)?;
 
-        // Emit deposit event (simplified - log instead of event)
-        soroban_sdk::log!(
-            env,
-            "CoinJoin deposit event: denomination={}, pool_size={}, timestamp={}",
-            denomination.symbol(),
+        let deposits_key = DataKey::CoinJoinTotalDeposits(pool_id);
+        let total_deposits: u64 = Self::get_counter(env, &deposits_key);
+        Self::set_counter(env, &deposits_key, &(total_deposits + 1));
+
+        CoinJoinDepositEvent {
+            pool_id,
+            commitment: recipient_commitment,
+            leaf_index,
             pool_size,
-            env.ledger().timestamp()
-        );
+            timestamp: env.ledger().timestamp(),
+        }
+        .publish(env);
 
         Ok(())
     }
 
-    /// Request withdrawal from mixed pool
-    /// Implements JoinMarket's blinded withdrawal mechanism
-    pub fn request_withdrawal(
-        env: &Env,
-        denomination: Denomination,
-        nullifier_hash: BytesN<32>,
-        recipient_hash: BytesN<32>,
-        proof_hash: BytesN<32>,
-    ) -> Result<(), BatcherError> {
+    /// Record a withdrawal from a mixed pool by spending its nullifier
+    /// Only the nullifier hash is kept, so a withdrawal cannot be matched to a deposit
+    /// Callers must have verified the withdrawal proof first
+    pub fn record_withdrawal(env: &Env, pool_id: u32, nullifier_hash: &BytesN<32>) -> Result<(), BatcherError> {
         if !Self::is_coinjoin_enabled(env) {
             return Err(BatcherError::InvalidInput);
         }
 
-        let mut pool = Self::get_pool(env, denomination)?;
-
         // Verify nullifier hasn't been used before (double-spending protection)
-        if Self::is_nullifier_used(env, nullifier_hash.clone())? {
-            return Err(BatcherError::InvalidInput);
-        }
+        Self::spend_nullifier(env, nullifier_hash)?;
 
-        // Create withdrawal request
-        let withdrawal = WithdrawalRequest {
-            nullifier_hash: nullifier_hash.clone(),
-            recipient_hash,
-            proof_hash,
-            requested_timestamp: env.ledger().timestamp(),
-        };
-
-        pool.withdrawals.push_back(withdrawal);
-        Self::update_pool(env, denomination, pool)?;
-
-        // Mark nullifier as used
-        let nullifier_key = DataKey::NullifierUsed(nullifier_hash.clone());
-        env.storage().instance().set(&nullifier_key, &true);
+        let withdrawals_key = DataKey::CoinJoinTotalWithdrawals(pool_id);
+        let total_withdrawals: u64 = Self::get_counter(env, &withdrawals_key);
+        Self::set_counter(env, &withdrawals_key, &(total_withdrawals + 1));
 
         Ok(())
     }
 
-    /// Execute mixing when pool reaches minimum size
-    /// Based on Wasabi Wallet's Chaumian mixing algorithm
-    pub fn execute_mixing(
-        env: &Env,
-        denomination: Denomination,
-        max_deposits: Option<u32>,
-    ) -> Result<MixResult, BatcherError> {
-        if !Self::is_coinjoin_enabled(env) {
-            return Err(BatcherError::InvalidInput);
-        }
-
-        let mut pool = Self::get_pool(env, denomination)?;
-        let max_to_mix = max_deposits.unwrap_or(pool.maximum_pool_size);
-
-        // Count unique sender addresses in the pool
-        let mut unique_senders = Vec::new(env);
-        for i in 0..pool.deposits.len() {
-            let deposit = pool.deposits.get(i).unwrap();
-            let sender = deposit.sender_address.clone();
-
-            // Check if sender is already in unique list
-            let mut is_unique = true;
-            for j in 0..unique_senders.len() {
-                if unique_senders.get(j).unwrap() == sender {
-                    is_unique = false;
-                    break;
-                }
-            }
-
-            if is_unique {
-                unique_senders.push_back(sender);
+    /// Refund expired deposits among the oldest `DEPOSIT_SCAN_LIMIT` pending ones
+    /// Anyone may call this, repeatedly for long queues; each sender gets back the full
+    /// denomination of their input token. Returns the number of deposits refunded
+    pub fn refund_expired(env: &Env, pool_id: u32) -> Result<u32, BatcherError> {
+        let mut pool = Self::get_pool(env, pool_id)?;
+        let now = env.ledger().timestamp();
+
+        let mut refunded = 0u32;
+        for deposit in Self::get_pending_deposits(env, &pool, pool.deposit_head, DEPOSIT_SCAN_LIMIT).iter() {
+            if deposit.expiry_timestamp <= now {
+                Self::refund_deposit(env, &pool, &deposit);
+                Self::remove_deposit(env, &mut pool, deposit.seq);
+                refunded += 1;
             }
         }
 
-        // Check if we have enough UNIQUE senders for mixing
-        if unique_senders.len() < pool.minimum_pool_size {
-            return Ok(MixResult {
-                success: false,
-                mixed_amounts: Vec::new(env),
-                gas_used: 0,
-                anonymity_set_size: 0,
-                fees_paid: 0,
-            });
+        if refunded > 0 {
+            Self::update_pool(env, pool_id, pool)?;
         }
 
-        // Limit to maximum batch size
-        let mix_count: u32 = if pool.deposits.len() as u32 > max_to_mix {
-            max_to_mix
-        } else {
-            pool.deposits.len() as u32
-        };
-        let mut mixed_amounts = Vec::new(env);
-        let mut total_fees = 0i128;
+        Ok(refunded)
+    }
 
-        // Simulate mixing process (in production, this would use cryptographic mixing)
-        for i in 0..mix_count as u32 {
-            let deposit = pool.deposits.get(i as u32).unwrap();
-            let amount_after_fee = denomination.value() - deposit.fee_paid;
-            mixed_amounts.push_back(amount_after_fee);
-            total_fees += deposit.fee_paid;
+    /// Cancel one expired deposit identified by its commitment
+    /// Only the original sender can cancel, and only once the deposit has expired
+    /// Returns the refunded amount
+    pub fn cancel_deposit(
+        env: &Env,
+        pool_id: u32,
+        commitment: &BytesN<32>,
+    ) -> Result<i128, BatcherError> {
+        let mut pool = Self::get_pool(env, pool_id)?;
+
+        let seq = Self::get_leaf_index(env, commitment).map_err(|_| BatcherError::DepositNotFound)? as u64;
+        let deposit = Self::get_deposit(env, pool_id, seq)
+            .filter(|deposit| deposit.commitment == *commitment)
+            .ok_or(BatcherError::DepositNotFound)?;
+
+        deposit.sender_address.require_auth();
+        if deposit.expiry_timestamp > env.ledger().timestamp() {
+            return Err(BatcherError::DepositNotExpired);
         }
 
-        // Remove mixed deposits from pool
-        let mut remaining_deposits = Vec::new(env);
-        for i in mix_count as u32..pool.deposits.len() {
-            remaining_deposits.push_back(pool.deposits.get(i).unwrap().clone());
-        }
-        pool.deposits = remaining_deposits;
+        Self::remove_deposit(env, &mut pool, seq);
+        let amount = Self::refund_deposit(env, &pool, &deposit);
+        Self::update_pool(env, pool_id, pool)?;
 
-        // Update pool
-        Self::update_pool(env, denomination, pool)?;
+        Ok(amount)
+    }
 
-        // Emit mixing event (simplified - log instead of event)
-        soroban_sdk::log!(
-            env,
-            "CoinJoin mixed event: denomination={}, mixed_count={}, total_fees={}, anonymity_set={}",
-            denomination.symbol(),
-            mix_count as u32,
-            total_fees,
-            mix_count as u32
-        );
+    /// Return a deposit's input tokens to its sender and emit the refund event
+    fn refund_deposit(env: &Env, pool: &CoinJoinPool, deposit: &Deposit) -> i128 {
+        let amount = pool.denomination.value();
+        TokenClient::new(env, &pool.token_in).transfer(&env.current_contract_address(), &deposit.sender_address, &amount);
 
-        Ok(MixResult {
-            success: true,
-            mixed_amounts,
-            gas_used: Self::estimate_mixing_gas_cost(mix_count as u32),
-            anonymity_set_size: mix_count as u32,
-            fees_paid: total_fees,
-        })
+        DepositRefundedEvent {
+            pool_id: pool.pool_id,
+            sender: deposit.sender_address.clone(),
+            token: pool.token_in.clone(),
+            amount,
+            timestamp: env.ledger().timestamp(),
+        }
+        .publish(env);
+
+        amount
     }
 
     /// Calculate equal payout for a set of deposits
@@ -387,62 +590,30 @@ impl CoinJoinMixer {
         denomination: Denomination,
         deposits: Vec<Deposit>,
     ) -> Result<PayoutInfo, BatcherError> {
-        use crate::pair_client::SoroswapPairClient;
-        use crate::factory_client::SoroswapFactoryClient;
-
         if deposits.is_empty() {
             return Err(BatcherError::InvalidInput);
         }
 
-        // All deposits should have the same token pair
-        let first_deposit = deposits.get(0).unwrap();
-        let token_in = first_deposit.token_in.clone();
-        let token_out = first_deposit.token_out.clone();
+        // All deposits should follow the same route
+        let path = deposits.get(0).unwrap().path;
 
-        // Calculate total input amount (all deposits have the same denomination)
-        let participant_count = deposits.len() as u32;
+        // Calculate total swap input: every deposit's denomination net of its fee
+        let participant_count = deposits.len();
         let amount_per_deposit = denomination.value();
-        let total_input_amount = amount_per_deposit * participant_count as i128;
+        let mut total_input_amount = 0i128;
 
-        // Verify all deposits use same token pair
+        // Verify all deposits use same path
         for i in 0..deposits.len() {
             let deposit = deposits.get(i).unwrap();
-            if deposit.token_in != token_in || deposit.token_out != token_out {
+            if deposit.path != path {
                 return Err(BatcherError::InvalidInput);
             }
+            total_input_amount += amount_per_deposit - deposit.fee_paid;
         }
 
-        // Get factory address to query pool
-        let factory_addr: soroban_sdk::Address = env.storage().instance()
-            .get(&DataKey::FactoryAddr)
-            .ok_or(BatcherError::NotInitialized)?;
-
-        // Query factory for pool address
-        let factory_client = SoroswapFactoryClient::new(env, &factory_addr);
-        let pair_addr = factory_client.get_pair(token_in.clone(), token_out.clone());
-
-        // Create pair client
-        let pair_client = SoroswapPairClient::new(env, &pair_addr);
-
-        // Get current reserves to calculate output amount
-        let (reserve_0, reserve_1) = pair_client.get_reserves();
-
-        // Determine token order in the pair
-        let pair_token_0 = pair_client.token_0();
-        let is_token_in_token_0 = pair_token_0 == token_in;
-
-        let (reserve_in, reserve_out) = if is_token_in_token_0 {
-            (reserve_0, reserve_1)
-        } else {
-            (reserve_1, reserve_0)
-        };
-
-        // Calculate output amount using constant product formula for aggregated swap
-        // amount_out = (amount_in * 997 * reserve_out) / (reserve_in * 1000 + amount_in * 997)
-        let amount_in_with_fee = total_input_amount * 997; // 0.3% fee
-        let numerator = amount_in_with_fee * reserve_out;
-        let denominator = (reserve_in * 1000) + amount_in_with_fee;
-        let total_output_amount = numerator / denominator;
+        // Chain the constant product quote across every hop of the aggregated swap
+        let amounts = crate::helpers::get_amounts_out(env, total_input_amount, &path)?;
+        let total_output_amount = amounts.last().unwrap();
 
         // Calculate equal payout per participant
         let equal_payout_amount = total_output_amount / participant_count as i128;
@@ -509,10 +680,10 @@ impl CoinJoinMixer {
 
     /// Find optimal participant set using descending set size search (v3 algorithm)
     /// Tests sets from largest to smallest, returning maximum qualifying set
-    /// This maximizes anonymity set size and throughput
+    /// This maximizes anonymity set size and throughput, up to the pool's `maximum_pool_size`
     pub fn find_optimal_participant_set(
         env: &Env,
-        denomination: Denomination,
+        pool_id: u32,
         all_deposits: Vec<Deposit>,
     ) -> Result<Vec<Deposit>, BatcherError> {
 
@@ -520,9 +691,10 @@ impl CoinJoinMixer {
             return Err(BatcherError::InvalidInput);
         }
 
-        // Get pool configuration to access minimum_pool_size
-        let pool = Self::get_pool(env, denomination)?;
-        let min_participants = pool.minimum_pool_size;
+        // Deposits arrive oldest first; the oldest one's wait decides the minimum set size
+        let pool = Self::get_pool(env, pool_id)?;
+        let denomination = pool.denomination;
+        let min_participants = Self::release_threshold(env, &pool, all_deposits.get(0).unwrap().timestamp);
 
         soroban_sdk::log!(
             env,
@@ -536,9 +708,10 @@ impl CoinJoinMixer {
         let mut sorted_deposits = all_deposits.clone();
         Self::sort_deposits_by_min_amount(env, &mut sorted_deposits);
 
-        // STEP 2: Test from largest set down to minimum
+        // STEP 2: Test from the largest allowed set down to minimum
         // First qualifying set found is the maximum possible
-        for set_size in (min_participants..=sorted_deposits.len()).rev() {
+        let max_participants = sorted_deposits.len().min(pool.maximum_pool_size);
+        for set_size in (min_participants..=max_participants).rev() {
             soroban_sdk::log!(
                 env,
                 "Testing set size: {} participants",
@@ -608,6 +781,353 @@ impl CoinJoinMixer {
         Err(BatcherError::InsufficientBalance)
     }
 
+    // === Commitment Functions ===
+    // Tornado Cash note scheme, computed off-chain by the depositor:
+    //   nullifier_hash = sha256(nullifier)
+    //   commitment     = sha256(nullifier || secret)
+    // Only the two hashes are ever submitted; the preimages stay with the user.
+
+    /// Hash a nullifier preimage into the public nullifier hash
+    pub fn compute_nullifier_hash(env: &Env, nullifier: &BytesN<32>) -> BytesN<32> {
+        env.crypto().sha256(&Bytes::from(nullifier)).into()
+    }
+
+    /// Compute the deposit commitment for a nullifier/secret pair
+    pub fn compute_commitment(env: &Env, nullifier: &BytesN<32>, secret: &BytesN<32>) -> BytesN<32> {
+        let mut preimage = Bytes::from(nullifier);
+        preimage.append(&Bytes::from(secret));
+        env.crypto().sha256(&preimage).into()
+    }
+
+    /// Verify that (nullifier, secret) opens both the commitment and the nullifier hash
+    pub fn verify_commitment(
+        env: &Env,
+        commitment: &BytesN<32>,
+        nullifier_hash: &BytesN<32>,
+        nullifier: &BytesN<32>,
+        secret: &BytesN<32>,
+    ) -> bool {
+        Self::compute_commitment(env, nullifier, secret) == *commitment
+            && Self::compute_nullifier_hash(env, nullifier) == *nullifier_hash
+    }
+
+    // === Settlement and Withdrawal Functions ===
+
+    /// Record an executed batch so its participants can withdraw their payouts
+    /// Returns the new batch id
+    pub fn settle_batch(
+        env: &Env,
+        pool_id: u32,
+        token_out: Address,
+        payout_amount: i128,
+        commitments: Vec<BytesN<32>>,
+    ) -> Result<u64, BatcherError> {
+        let (root, _) = MerkleTree::root_and_path(env, &commitments, 0)?;
+
+        let count_key = DataKey::SettledBatchCount(pool_id);
+        let batch_id: u64 = Self::get_counter(env, &count_key);
+        Self::set_counter(env, &count_key, &(batch_id + 1));
+
+        let batch = SettledBatch {
+            batch_id,
+            token_out,
+            payout_amount,
+            remaining_claims: commitments.len(),
+            commitments,
+            root,
+            settled_timestamp: env.ledger().timestamp(),
+        };
+        Self::set_settled_batch(env, pool_id, &batch);
+
+        Ok(batch_id)
+    }
+
+    /// Withdraw one note's payout from a settled batch
+    /// The verifier checks that the caller knows (nullifier, secret) for a commitment
+    /// under `batch.root`, and binds the proof to `nullifier_hash` and `recipient`
+    pub fn withdraw(
+        env: &Env,
+        pool_id: u32,
+        batch_id: u64,
+        nullifier_hash: BytesN<32>,
+        recipient: Address,
+        proof: Bytes,
+    ) -> Result<i128, BatcherError> {
+        let mut batch = Self::get_settled_batch(env, pool_id, batch_id)?;
+        if batch.remaining_claims == 0 {
+            return Err(BatcherError::InsufficientBalance);
+        }
+
+        if Self::is_nullifier_used(env, &nullifier_hash) {
+            return Err(BatcherError::NullifierAlreadyUsed);
+        }
+
+        let verifier_addr: Address = env.storage().instance()
+            .get(&DataKey::VerifierAddr)
+            .ok_or(BatcherError::VerifierNotSet)?;
+
+        let recipient_hash: BytesN<32> = env.crypto().sha256(&recipient.clone().to_xdr(env)).into();
+        let public_inputs = Vec::from_array(env, [
+            batch.root.clone(),
+            nullifier_hash.clone(),
+            recipient_hash,
+        ]);
+
+        let verifier = ProofVerifierClient::new(env, &verifier_addr);
+        if !verifier.verify(proof, public_inputs) {
+            return Err(BatcherError::InvalidProof);
+        }
+
+        Self::record_withdrawal(env, pool_id, &nullifier_hash)?;
+
+        batch.remaining_claims -= 1;
+        Self::set_settled_batch(env, pool_id, &batch);
+
+        TokenClient::new(env, &batch.token_out).transfer(
+            &env.current_contract_address(),
+            &recipient,
+            &batch.payout_amount,
+        );
+
+        CoinJoinPayoutEvent {
+            pool_id,
+            batch_id,
+            nullifier_hash,
+            recipient,
+            amount: batch.payout_amount,
+            remaining_claims: batch.remaining_claims,
+        }
+        .publish(env);
+
+        Ok(batch.payout_amount)
+    }
+
+    /// Get a settled batch record
+    pub fn get_settled_batch(
+        env: &Env,
+        pool_id: u32,
+        batch_id: u64,
+    ) -> Result<SettledBatch, BatcherError> {
+        env.storage().persistent()
+            .get(&DataKey::SettledBatch(pool_id, batch_id))
+            .ok_or(BatcherError::BatchNotFound)
+    }
+
+    /// Number of batches settled for a pool
+    pub fn get_settled_batch_count(env: &Env, pool_id: u32) -> u64 {
+        Self::get_counter(env, &DataKey::SettledBatchCount(pool_id))
+    }
+
+    fn set_settled_batch(env: &Env, pool_id: u32, batch: &SettledBatch) {
+        let key = DataKey::SettledBatch(pool_id, batch.batch_id);
+        env.storage().persistent().set(&key, batch);
+        env.storage().persistent().extend_ttl(&key, BATCH_TTL_LEDGERS, BATCH_TTL_LEDGERS);
+    }
+
+    // === Fee Accounting Functions ===
+
+    /// Record fees retained from an executed batch
+    pub fn accrue_fees(env: &Env, token: &Address, amount: i128) {
+        if amount <= 0 {
+            return;
+        }
+
+        let key = DataKey::CollectedFees(token.clone());
+        let collected: i128 = Self::get_counter(env, &key);
+        Self::set_counter(env, &key, &(collected + amount));
+    }
+
+    /// Fees collected in `token` and not yet withdrawn
+    pub fn get_collected_fees(env: &Env, token: &Address) -> i128 {
+        Self::get_counter(env, &DataKey::CollectedFees(token.clone()))
+    }
+
+    /// Pay out collected fees; callers are responsible for authorization
+    pub fn withdraw_fees(env: &Env, token: &Address, to: &Address, amount: i128) -> Result<(), BatcherError> {
+        if amount <= 0 {
+            return Err(BatcherError::InvalidInput);
+        }
+
+        let collected = Self::get_collected_fees(env, token);
+        if amount > collected {
+            return Err(BatcherError::InsufficientBalance);
+        }
+
+        Self::set_counter(env, &DataKey::CollectedFees(token.clone()), &(collected - amount));
+        TokenClient::new(env, token).transfer(&env.current_contract_address(), to, &amount);
+
+        FeesWithdrawnEvent {
+            token: token.clone(),
+            to: to.clone(),
+            amount,
+        }
+        .publish(env);
+
+        Ok(())
+    }
+
+    /// Share of a keeper-executed batch's fees paid to the keeper, in basis points of the fees
+    pub fn get_keeper_reward_bps(env: &Env) -> u32 {
+        env.storage().instance()
+            .get(&DataKey::KeeperRewardBps)
+            .unwrap_or(DEFAULT_KEEPER_REWARD_BPS)
+    }
+
+    /// Set the keeper reward share; callers are responsible for authorization
+    pub fn set_keeper_reward_bps(env: &Env, reward_bps: u32) -> Result<(), BatcherError> {
+        if reward_bps > MAX_KEEPER_REWARD_BPS {
+            return Err(BatcherError::InvalidConfig);
+        }
+
+        env.storage().instance().set(&DataKey::KeeperRewardBps, &reward_bps);
+        KeeperRewardUpdatedEvent { reward_bps }.publish(env);
+        Ok(())
+    }
+
+    /// Pay the keeper its share of a batch's fees in the pool's input token
+    /// Returns the reward; the rest of the fees is left for the caller to accrue
+    pub fn pay_keeper_reward(
+        env: &Env,
+        pool: &CoinJoinPool,
+        batch_id: u64,
+        keeper: &Address,
+        total_fees: i128,
+    ) -> i128 {
+        let amount = total_fees * Self::get_keeper_reward_bps(env) as i128 / 10_000;
+        if amount <= 0 {
+            return 0;
+        }
+
+        TokenClient::new(env, &pool.token_in).transfer(&env.current_contract_address(), keeper, &amount);
+
+        KeeperRewardedEvent {
+            pool_id: pool.pool_id,
+            batch_id,
+            keeper: keeper.clone(),
+            token: pool.token_in.clone(),
+            amount,
+        }
+        .publish(env);
+
+        amount
+    }
+
+    // === Deposit Queue Functions ===
+
+    /// Store a deposit at `deposit.seq` and advance the pool's tail past it
+    /// The caller persists the updated pool
+    pub fn push_deposit(env: &Env, pool: &mut CoinJoinPool, deposit: &Deposit) {
+        Self::store_deposit(env, pool.pool_id, deposit);
+
+        if pool.pending_deposits == 0 {
+            pool.deposit_head = deposit.seq;
+        }
+        pool.deposit_tail = deposit.seq + 1;
+        pool.pending_deposits += 1;
+    }
+
+    /// Write a deposit entry without touching the pool's queue bounds
+    pub fn store_deposit(env: &Env, pool_id: u32, deposit: &Deposit) {
+        let key = DataKey::Deposit(pool_id, deposit.seq);
+        env.storage().persistent().set(&key, deposit);
+        env.storage().persistent().extend_ttl(&key, DEPOSIT_TTL_LEDGERS, DEPOSIT_TTL_LEDGERS);
+    }
+
+    /// Load a pending deposit, extending its TTL
+    pub fn get_deposit(env: &Env, pool_id: u32, seq: u64) -> Option<Deposit> {
+        let key = DataKey::Deposit(pool_id, seq);
+        let deposit = env.storage().persistent().get(&key)?;
+        env.storage().persistent().extend_ttl(&key, DEPOSIT_TTL_LEDGERS, DEPOSIT_TTL_LEDGERS);
+        Some(deposit)
+    }
+
+    /// Remove a pending deposit; removing the oldest one advances the head past any
+    /// entries already removed behind it. The caller persists the updated pool
+    pub fn remove_deposit(env: &Env, pool: &mut CoinJoinPool, seq: u64) {
+        env.storage().persistent().remove(&DataKey::Deposit(pool.pool_id, seq));
+        pool.pending_deposits -= 1;
+
+        if pool.pending_deposits == 0 {
+            pool.deposit_head = pool.deposit_tail;
+        } else if seq == pool.deposit_head {
+            pool.deposit_head += 1;
+            while !env.storage().persistent().has(&DataKey::Deposit(pool.pool_id, pool.deposit_head)) {
+                pool.deposit_head += 1;
+            }
+        }
+    }
+
+    /// Path of the oldest pending deposit whose route has enough deposits to fill a batch,
+    /// counting a timed release once the route's oldest deposit has waited `max_wait_seconds`
+    /// Only the oldest `DEPOSIT_SCAN_LIMIT` queue slots are considered
+    pub fn find_ready_path(env: &Env, pool: &CoinJoinPool) -> Option<Vec<Address>> {
+        let deposits = Self::get_pending_deposits(env, pool, pool.deposit_head, DEPOSIT_SCAN_LIMIT);
+
+        for i in 0..deposits.len() {
+            let oldest = deposits.get(i).unwrap();
+            let route_count = deposits.iter().filter(|deposit| deposit.path == oldest.path).count() as u32;
+            if route_count >= Self::release_threshold(env, pool, oldest.timestamp) {
+                return Some(oldest.path);
+            }
+        }
+        None
+    }
+
+    /// Participants needed to execute a route whose oldest deposit arrived at `oldest_timestamp`:
+    /// `minimum_pool_size`, dropping to `MIN_POOL_SIZE_FLOOR` once `max_wait_seconds` has passed
+    pub fn release_threshold(env: &Env, pool: &CoinJoinPool, oldest_timestamp: u64) -> u32 {
+        if Self::max_wait_elapsed(env, pool, oldest_timestamp) {
+            MIN_POOL_SIZE_FLOOR
+        } else {
+            pool.minimum_pool_size
+        }
+    }
+
+    /// Whether the pool's oldest pending deposit has waited `max_wait_seconds`
+    pub fn is_release_due(env: &Env, pool: &CoinJoinPool) -> bool {
+        if pool.pending_deposits == 0 {
+            return false;
+        }
+
+        match Self::get_deposit(env, pool.pool_id, pool.deposit_head) {
+            Some(oldest) => Self::max_wait_elapsed(env, pool, oldest.timestamp),
+            None => false,
+        }
+    }
+
+    fn max_wait_elapsed(env: &Env, pool: &CoinJoinPool, oldest_timestamp: u64) -> bool {
+        pool.max_wait_seconds > 0 && env.ledger().timestamp() >= oldest_timestamp.saturating_add(pool.max_wait_seconds)
+    }
+
+    /// Fold the gap since the previous deposit into the pool's moving average arrival interval
+    /// `leaf_index` is the new deposit's position among every deposit the pool has taken
+    fn record_arrival(env: &Env, pool: &mut CoinJoinPool, leaf_index: u32) {
+        let now = env.ledger().timestamp();
+        if leaf_index > 0 {
+            let gap = now.saturating_sub(pool.last_deposit_timestamp);
+            pool.arrival_interval_seconds = if leaf_index == 1 {
+                gap
+            } else {
+                (pool.arrival_interval_seconds * (ARRIVAL_SMOOTHING - 1) + gap) / ARRIVAL_SMOOTHING
+            };
+        }
+        pool.last_deposit_timestamp = now;
+    }
+
+    /// Pending deposits with sequence numbers in `start_seq..start_seq + limit`, oldest first
+    /// `limit` is capped at `DEPOSIT_SCAN_LIMIT`; slots before the head or at the tail are skipped
+    pub fn get_pending_deposits(env: &Env, pool: &CoinJoinPool, start_seq: u64, limit: u32) -> Vec<Deposit> {
+        let end = pool.deposit_tail.min(start_seq.saturating_add(limit.min(DEPOSIT_SCAN_LIMIT) as u64));
+
+        let mut deposits = Vec::new(env);
+        for seq in start_seq.max(pool.deposit_head)..end {
+            if let Some(deposit) = Self::get_deposit(env, pool.pool_id, seq) {
+                deposits.push_back(deposit);
+            }
+        }
+        deposits
+    }
+
     // === Multicall Integration Functions ===
     // TODO: Implement proper multicall integration
     // Commented out for now due to type compatibility issues with Vec<u8>
@@ -621,52 +1141,109 @@ impl CoinJoinMixer {
             .unwrap_or(false)
     }
 
-    /// Get pool for specific denomination (public for execute_batch_swap)
-    pub fn get_pool(env: &Env, denomination: Denomination) -> Result<CoinJoinPool, BatcherError> {
-        let pool_key = DataKey::CoinJoinPool(denomination.symbol());
-        env.storage().instance()
+    /// Get pool by id (public for execute_batch_swap)
+    pub fn get_pool(env: &Env, pool_id: u32) -> Result<CoinJoinPool, BatcherError> {
+        let pool_key = DataKey::CoinJoinPool(pool_id);
+        let pool = env.storage().persistent()
             .get(&pool_key)
-            .ok_or(BatcherError::InvalidInput)
+            .ok_or(BatcherError::InvalidInput)?;
+        env.storage().persistent().extend_ttl(&pool_key, POOL_TTL_LEDGERS, POOL_TTL_LEDGERS);
+        Ok(pool)
     }
 
     /// Update pool state (public for execute_batch_swap)
-    pub fn update_pool(env: &Env, denomination: Denomination, pool: CoinJoinPool) -> Result<(), BatcherError> {
-        let pool_key = DataKey::CoinJoinPool(denomination.symbol());
-        env.storage().instance().set(&pool_key, &pool);
+    pub fn update_pool(env: &Env, pool_id: u32, pool: CoinJoinPool) -> Result<(), BatcherError> {
+        let pool_key = DataKey::CoinJoinPool(pool_id);
+        env.storage().persistent().set(&pool_key, &pool);
+        env.storage().persistent().extend_ttl(&pool_key, POOL_TTL_LEDGERS, POOL_TTL_LEDGERS);
         Ok(())
     }
 
     /// Check if nullifier has been used (double-spending protection)
-    fn is_nullifier_used(env: &Env, nullifier_hash: BytesN<32>) -> Result<bool, BatcherError> {
-        let nullifier_key = DataKey::NullifierUsed(nullifier_hash);
-        Ok(env.storage().instance()
-            .get(&nullifier_key)
-            .unwrap_or(false))
+    pub fn is_nullifier_used(env: &Env, nullifier_hash: &BytesN<32>) -> bool {
+        Self::has_marker(env, &DataKey::NullifierUsed(nullifier_hash.clone()))
+    }
+
+    /// Mark a nullifier as spent, failing if it was already spent
+    /// Must be called exactly once for every deposit that leaves the pool
+    pub fn spend_nullifier(env: &Env, nullifier_hash: &BytesN<32>) -> Result<(), BatcherError> {
+        if Self::is_nullifier_used(env, nullifier_hash) {
+            return Err(BatcherError::NullifierAlreadyUsed);
+        }
+
+        Self::set_marker(env, &DataKey::NullifierUsed(nullifier_hash.clone()));
+        Ok(())
+    }
+
+    /// Check if a commitment has already been deposited
+    pub fn is_commitment_used(env: &Env, commitment: &BytesN<32>) -> bool {
+        Self::has_marker(env, &DataKey::CommitmentUsed(commitment.clone()))
+    }
+
+    /// Record a spent nullifier or used commitment as a persistent entry
+    /// These must outlive every deposit, so they get the longest TTL the network allows
+    fn set_marker(env: &Env, key: &DataKey) {
+        let max_ttl = env.storage().max_ttl();
+        env.storage().persistent().set(key, &true);
+        env.storage().persistent().extend_ttl(key, max_ttl, max_ttl);
+    }
+
+    /// Whether a spent nullifier or used commitment is recorded, extending its TTL on
+    /// every check so it never lapses
+    /// Markers written to instance storage by earlier versions of the contract cannot be
+    /// enumerated by a migration, so they are moved to a persistent entry when first read
+    fn has_marker(env: &Env, key: &DataKey) -> bool {
+        if env.storage().persistent().has(key) {
+            let max_ttl = env.storage().max_ttl();
+            env.storage().persistent().extend_ttl(key, max_ttl, max_ttl);
+            return true;
+        }
+
+        if env.storage().instance().get(key).unwrap_or(false) {
+            env.storage().instance().remove(key);
+            Self::set_marker(env, key);
+            return true;
+        }
+        false
+    }
+
+    /// Validate a user-supplied commitment for a new deposit
+    fn validate_commitment(env: &Env, commitment: &BytesN<32>) -> Result<(), BatcherError> {
+        if *commitment == BytesN::from_array(env, &[0u8; 32]) {
+            return Err(BatcherError::InvalidCommitment);
+        }
+
+        if Self::is_commitment_used(env, commitment) {
+            return Err(BatcherError::CommitmentAlreadyUsed);
+        }
+
+        Ok(())
+    }
+
+    /// Get the Merkle leaf index assigned to a deposited commitment
+    pub fn get_leaf_index(env: &Env, commitment: &BytesN<32>) -> Result<u32, BatcherError> {
+        env.storage().persistent()
+            .get(&DataKey::MerkleLeafIndex(commitment.clone()))
+            .ok_or(BatcherError::InvalidInput)
     }
 
     // === Statistics and Information Functions ===
 
     /// Get pool statistics for monitoring
-    pub fn get_pool_stats(env: &Env, denomination: Denomination) -> Result<PoolStats, BatcherError> {
-        let pool = Self::get_pool(env, denomination)?;
-
-        let total_deposits: u64 = env.storage().instance()
-            .get(&DataKey::CoinJoinTotalDeposits(denomination.symbol()))
-            .unwrap_or(0);
+    pub fn get_pool_stats(env: &Env, pool_id: u32) -> Result<PoolStats, BatcherError> {
+        let pool = Self::get_pool(env, pool_id)?;
 
-        let total_withdrawals: u64 = env.storage().instance()
-            .get(&DataKey::CoinJoinTotalWithdrawals(denomination.symbol()))
-            .unwrap_or(0);
+        let total_deposits: u64 = Self::get_counter(env, &DataKey::CoinJoinTotalDeposits(pool_id));
+        let total_withdrawals: u64 = Self::get_counter(env, &DataKey::CoinJoinTotalWithdrawals(pool_id));
 
-        let estimated_wait_time = if pool.deposits.len() as u32 >= pool.minimum_pool_size {
-            0 // Ready to mix
-        } else {
-            (pool.minimum_pool_size - pool.deposits.len() as u32) * 5 // Estimate 5 blocks per deposit
-        };
+        let estimated_wait_time = Self::estimate_wait_time(env, &pool);
 
         Ok(PoolStats {
-            denomination,
-            current_pool_size: pool.deposits.len() as u32,
+            pool_id,
+            token_in: pool.token_in,
+            token_out: pool.token_out,
+            denomination: pool.denomination,
+            current_pool_size: pool.pending_deposits,
             total_deposits,
             total_withdrawals,
             current_fees: pool.fee_basis_points,
@@ -674,32 +1251,91 @@ impl CoinJoinMixer {
         })
     }
 
+    /// Seconds until the pool can release a batch, from its average deposit arrival interval
+    /// and any timed release; `WAIT_TIME_UNKNOWN` before two deposits have arrived
+    fn estimate_wait_time(env: &Env, pool: &CoinJoinPool) -> u32 {
+        if pool.pending_deposits >= pool.minimum_pool_size
+            || (pool.pending_deposits >= MIN_POOL_SIZE_FLOOR && Self::is_release_due(env, pool))
+        {
+            return 0; // Ready to mix
+        }
+
+        let has_history = pool.merkle_tree.next_index >= 2;
+        let until_arrivals = |needed: u32| -> Option<u64> {
+            if needed == 0 {
+                Some(0)
+            } else if has_history {
+                Some(needed as u64 * pool.arrival_interval_seconds)
+            } else {
+                None
+            }
+        };
+
+        let mut estimate = until_arrivals(pool.minimum_pool_size - pool.pending_deposits);
+
+        // A timed release needs the oldest deposit to age out and the floor to be reached
+        if pool.max_wait_seconds > 0 && pool.pending_deposits > 0 {
+            if let Some(oldest) = Self::get_deposit(env, pool.pool_id, pool.deposit_head) {
+                let release_at = oldest.timestamp.saturating_add(pool.max_wait_seconds);
+                let until_timeout = release_at.saturating_sub(env.ledger().timestamp());
+                if let Some(until_floor) = until_arrivals(MIN_POOL_SIZE_FLOOR.saturating_sub(pool.pending_deposits)) {
+                    let timed = until_timeout.max(until_floor);
+                    estimate = Some(estimate.map_or(timed, |estimate| estimate.min(timed)));
+                }
+            }
+        }
+
+        match estimate {
+            Some(seconds) => seconds.min(WAIT_TIME_UNKNOWN as u64 - 1) as u32,
+            None => WAIT_TIME_UNKNOWN,
+        }
+    }
+
     /// Get deposit details for monitoring (privacy-safe)
     /// Returns public information only - does NOT expose commitment, nullifier, or sender
     pub fn get_deposit_details(
         env: &Env,
-        denomination: Denomination,
-        index: u32,
+        pool_id: u32,
+        seq: u64,
     ) -> Result<DepositInfo, BatcherError> {
-        let pool = Self::get_pool(env, denomination)?;
+        Self::get_pool(env, pool_id)?;
 
-        if index >= pool.deposits.len() {
-            return Err(BatcherError::InvalidInput);
-        }
+        let deposit = Self::get_deposit(env, pool_id, seq).ok_or(BatcherError::DepositNotFound)?;
+        Ok(Self::deposit_info(&deposit))
+    }
 
-        let deposit = pool.deposits.get(index).unwrap();
+    /// Page through pending deposits (privacy-safe), oldest first
+    /// Covers sequence numbers `start_seq..start_seq + limit` (at most `DEPOSIT_SCAN_LIMIT`);
+    /// walk from `deposit_head` to `deposit_tail` in steps of `limit` to list the whole queue
+    pub fn get_pending_deposit_infos(
+        env: &Env,
+        pool_id: u32,
+        start_seq: u64,
+        limit: u32,
+    ) -> Result<Vec<DepositInfo>, BatcherError> {
+        let pool = Self::get_pool(env, pool_id)?;
+
+        let mut infos = Vec::new(env);
+        for deposit in Self::get_pending_deposits(env, &pool, start_seq, limit).iter() {
+            infos.push_back(Self::deposit_info(&deposit));
+        }
+        Ok(infos)
+    }
 
-        // Return only non-sensitive information
-        Ok(DepositInfo {
+    // Return only non-sensitive information
+    fn deposit_info(deposit: &Deposit) -> DepositInfo {
+        DepositInfo {
+            seq: deposit.seq,
             min_amount_out: deposit.min_amount_out,
             max_slippage_bps: deposit.max_slippage_bps,
             expiry_timestamp: deposit.expiry_timestamp,
             timestamp: deposit.timestamp,
             fee_paid: deposit.fee_paid,
-        })
+        }
     }
 
     /// Estimate gas cost for mixing operations
+    #[allow(dead_code)]
     pub fn estimate_mixing_gas_cost(deposit_count: u32) -> u64 {
         // Base cost for mixing operation
         let base_cost = 25_000u64;
@@ -717,11 +1353,13 @@ impl CoinJoinMixer {
     }
 
     /// Check if denomination is supported
+    #[allow(dead_code)]
     pub fn is_supported_denomination(amount: i128) -> bool {
         Denomination::from_amount(amount).is_some()
     }
 
     /// Calculate required deposit count for amount
+    #[allow(dead_code)]
     pub fn calculate_deposit_count(amount: i128) -> Result<u32, BatcherError> {
         if !Self::is_supported_denomination(amount) {
             return Err(BatcherError::InvalidInput);
@@ -734,29 +1372,134 @@ impl CoinJoinMixer {
 }
 
 // === Event Definitions ===
+// Published for indexers and monitoring; deposit and payout events never link
+// a depositor to the address that receives the payout
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-#[contracttype]
+/// A note commitment joined a pool's queue
+#[contractevent]
+#[derive(Clone, Debug)]
 pub struct CoinJoinDepositEvent {
-    pub denomination: Symbol,
+    #[topic]
+    pub pool_id: u32,
+    pub commitment: BytesN<32>,
+    pub leaf_index: u32,
     pub pool_size: u32,
     pub timestamp: u64,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-#[contracttype]
-pub struct CoinJoinMixedEvent {
-    pub denomination: Symbol,
-    pub mixed_count: u32,
-    pub total_fees: i128,
-    pub anonymity_set_size: u32,
+/// An aggregated swap executed and its batch was settled for withdrawal
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct CoinJoinBatchExecutedEvent {
+    #[topic]
+    pub pool_id: u32,
+    #[topic]
+    pub batch_id: u64,
+    pub participant_count: u32,
+    pub total_input: i128,
+    pub total_output: i128,
+    pub payout_amount: i128,
+    pub fees: i128,
+    pub slippage_bps: u32,
     pub timestamp: u64,
 }
 
+/// One note's payout was withdrawn from a settled batch
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct CoinJoinPayoutEvent {
+    #[topic]
+    pub pool_id: u32,
+    #[topic]
+    pub batch_id: u64,
+    pub nullifier_hash: BytesN<32>,
+    pub recipient: Address,
+    pub amount: i128,
+    pub remaining_claims: u32,
+}
+
+/// An expired deposit was returned to its sender
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct DepositRefundedEvent {
+    #[topic]
+    pub pool_id: u32,
+    pub sender: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub timestamp: u64,
+}
+
+/// A pool's configuration changed; carries the full new configuration
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct PoolConfigUpdatedEvent {
+    #[topic]
+    pub pool_id: u32,
+    pub minimum_pool_size: u32,
+    pub maximum_pool_size: u32,
+    pub fee_basis_points: u32,
+    pub deposit_expiry_seconds: u64,
+    pub execution_mode: ExecutionMode,
+    pub max_wait_seconds: u64,
+}
+
+/// A denomination started or stopped accepting deposits
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct DenominationSupportEvent {
+    #[topic]
+    pub denomination: Symbol,
+    pub supported: bool,
+}
+
+/// Collected fees were withdrawn by the fee recipient
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct FeesWithdrawnEvent {
+    #[topic]
+    pub token: Address,
+    pub to: Address,
+    pub amount: i128,
+}
+
+/// The fee recipient was changed by the owner
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct FeeRecipientUpdatedEvent {
+    pub fee_recipient: Address,
+}
+
+/// The withdrawal proof verifier was changed by the owner
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct VerifierUpdatedEvent {
+    pub verifier: Address,
+}
+
+/// A keeper was paid its share of the fees of a batch it executed
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct KeeperRewardedEvent {
+    #[topic]
+    pub pool_id: u32,
+    #[topic]
+    pub batch_id: u64,
+    pub keeper: Address,
+    pub token: Address,
+    pub amount: i128,
+}
+
+/// The keeper reward share was changed by the fee manager
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct KeeperRewardUpdatedEvent {
+    pub reward_bps: u32,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use soroban_sdk::Env;
 
     #[test]
     fn test_denomination_values() {
@@ -42,6 +42,36 @@ pub enum BatcherError {
 
     /// Order errors
     OrderNotFound = 23,
+    OrderNotPending = 31,
+    NoExecutableOrders = 32,
+
+    /// Deposit errors
+    DepositNotFound = 35,
+    DepositNotExpired = 36,
+
+    /// Pool configuration errors
+    InvalidConfig = 37,
+    UnsupportedDenomination = 38,
+    PoolNotFound = 41,
+
+    /// Commitment and nullifier errors
+    InvalidCommitment = 24,
+    CommitmentAlreadyUsed = 25,
+    NullifierAlreadyUsed = 26,
+    MerkleTreeFull = 27,
+
+    /// Withdrawal errors
+    InvalidProof = 28,
+    VerifierNotSet = 29,
+    BatchNotFound = 30,
+
+    /// Execution errors
+    SwapFailed = 33,
+    SlippageExceeded = 34,
+    PoolNotReady = 40,
+
+    /// Upgrade errors
+    UnsupportedSchemaVersion = 39,
 }
 
 /// Error categories for organized error handling
@@ -54,6 +84,9 @@ pub enum ErrorCategory {
     CommitReveal,
     MEVProtection,
     Order,
+    Privacy,
+    Withdrawal,
+    Execution,
     System,
 }
 
@@ -61,7 +94,9 @@ impl BatcherError {
     /// Get error category for better error handling
     pub fn category(&self) -> ErrorCategory {
         match self {
-            BatcherError::InvalidInput | BatcherError::InsufficientBalance => ErrorCategory::Validation,
+            BatcherError::InvalidInput | BatcherError::InsufficientBalance |
+            BatcherError::InvalidConfig | BatcherError::UnsupportedDenomination |
+            BatcherError::PoolNotFound => ErrorCategory::Validation,
             BatcherError::Unauthorized | BatcherError::ContractPaused => ErrorCategory::Permission,
             BatcherError::AlreadyInitialized | BatcherError::NotInitialized => ErrorCategory::Initialization,
             BatcherError::FactoryNotConnected | BatcherError::PairNotFound |
@@ -72,21 +107,33 @@ impl BatcherError {
             BatcherError::MEVProtectionDisabled | BatcherError::OrderTooEarly |
             BatcherError::OrderTooLate | BatcherError::ExecutionWindowFull |
             BatcherError::QueueFull | BatcherError::PriorityConflict => ErrorCategory::MEVProtection,
-            BatcherError::OrderNotFound => ErrorCategory::Order,
-            BatcherError::InternalError => ErrorCategory::System,
+            BatcherError::OrderNotFound | BatcherError::OrderNotPending |
+            BatcherError::NoExecutableOrders | BatcherError::DepositNotFound |
+            BatcherError::DepositNotExpired => ErrorCategory::Order,
+            BatcherError::InvalidCommitment | BatcherError::CommitmentAlreadyUsed |
+            BatcherError::NullifierAlreadyUsed | BatcherError::MerkleTreeFull => ErrorCategory::Privacy,
+            BatcherError::InvalidProof | BatcherError::VerifierNotSet |
+            BatcherError::BatchNotFound => ErrorCategory::Withdrawal,
+            BatcherError::SwapFailed | BatcherError::SlippageExceeded |
+            BatcherError::PoolNotReady => ErrorCategory::Execution,
+            BatcherError::InternalError | BatcherError::UnsupportedSchemaVersion => ErrorCategory::System,
         }
     }
 
     /// Check if error is recoverable (can be retried)
     pub fn is_recoverable(&self) -> bool {
-        match self {
+        matches!(
+            self,
             BatcherError::InvalidInput |
             BatcherError::InsufficientBalance |
+            BatcherError::SwapFailed |
+            BatcherError::SlippageExceeded |
+            BatcherError::PoolNotReady |
             BatcherError::OrderTooEarly |
+            BatcherError::DepositNotExpired |
             BatcherError::ExecutionWindowFull |
-            BatcherError::QueueFull => true,
-            _ => false,
-        }
+            BatcherError::QueueFull
+        )
     }
 
     /// Get user-friendly error message
@@ -97,6 +144,26 @@ impl BatcherError {
             BatcherError::Unauthorized => "Unauthorized access",
             BatcherError::ContractPaused => "Contract is currently paused",
             BatcherError::InternalError => "Internal system error",
+            BatcherError::InvalidCommitment => "Invalid commitment or nullifier hash",
+            BatcherError::CommitmentAlreadyUsed => "Commitment already deposited",
+            BatcherError::NullifierAlreadyUsed => "Nullifier already spent",
+            BatcherError::MerkleTreeFull => "Deposit Merkle tree is full",
+            BatcherError::InvalidProof => "Withdrawal proof rejected",
+            BatcherError::VerifierNotSet => "Proof verifier not configured",
+            BatcherError::BatchNotFound => "Settled batch not found",
+            BatcherError::OrderNotFound => "Order not found",
+            BatcherError::OrderNotPending => "Order already executed or cancelled",
+            BatcherError::NoExecutableOrders => "No order in the batch can execute at the clearing price",
+            BatcherError::DepositNotFound => "Deposit not found in the pool",
+            BatcherError::DepositNotExpired => "Deposit has not expired yet",
+            BatcherError::InvalidConfig => "Pool configuration out of range",
+            BatcherError::UnsupportedDenomination => "Denomination is not accepting deposits",
+            BatcherError::PoolNotFound => "No pool has been created for this token pair and denomination",
+            BatcherError::SwapFailed => "Aggregated swap was rejected",
+            BatcherError::SlippageExceeded => "Aggregated swap pays a participant less than its minimum output",
+            BatcherError::PoolNotReady => "Pool has too few compatible deposits to execute",
+            BatcherError::OrderTooLate => "Execution deadline has passed",
+            BatcherError::UnsupportedSchemaVersion => "Stored state was written by a newer contract version",
             _ => "Unknown error occurred",
         }
     }
@@ -1,7 +1,14 @@
 // src/helpers.rs
 // Common helper functions used across modules
 
-use soroban_sdk::{Env, Address};
+use soroban_sdk::{
+    auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation},
+    panic_with_error, token::Client as TokenClient, vec, Env, Address, IntoVal, Symbol, Vec,
+};
+use crate::{
+    error::BatcherError, factory_client::SoroswapFactoryClient,
+    pair_client::SoroswapPairClient, router_client::SoroswapRouterClient, DataKey,
+};
 
 /// Check if the contract is initialized
 /// This is a centralized helper function used by all modules
@@ -9,4 +16,188 @@ pub fn is_initialized(env: &Env) -> bool {
     env.storage().instance()
         .get::<_, Address>(&crate::DataKey::Owner)
         .is_some()
-}
\ No newline at end of file
+}
+
+/// Check whether the owner has paused the contract
+pub fn is_paused(env: &Env) -> bool {
+    env.storage().instance()
+        .get(&DataKey::Paused)
+        .unwrap_or(false)
+}
+
+/// Reject deposits and executions while the contract is paused
+pub fn ensure_not_paused(env: &Env) -> Result<(), BatcherError> {
+    if is_paused(env) {
+        return Err(BatcherError::ContractPaused);
+    }
+    Ok(())
+}
+
+/// Constant product output for an exact input, including the 0.3% pair fee
+/// amount_out = (amount_in * 997 * reserve_out) / (reserve_in * 1000 + amount_in * 997)
+pub fn get_amount_out(amount_in: i128, reserve_in: i128, reserve_out: i128) -> i128 {
+    let amount_in_with_fee = amount_in * 997;
+    let numerator = amount_in_with_fee * reserve_out;
+    let denominator = (reserve_in * 1000) + amount_in_with_fee;
+    numerator / denominator
+}
+
+/// Maximum number of tokens in a swap path (three hops)
+pub const MAX_PATH_LEN: u32 = 4;
+
+/// Validate a swap path: at least one hop, bounded length, no token visited twice
+/// Distinct tokens mean no pair is swapped twice, so a chained quote stays exact
+pub fn validate_path(path: &Vec<Address>) -> Result<(), BatcherError> {
+    if path.len() < 2 || path.len() > MAX_PATH_LEN {
+        return Err(BatcherError::InvalidInput);
+    }
+    for i in 1..path.len() {
+        for j in 0..i {
+            if path.get(j).unwrap() == path.get(i).unwrap() {
+                return Err(BatcherError::InvalidInput);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Look up the factory pair for two tokens
+pub fn get_pair(env: &Env, token_a: &Address, token_b: &Address) -> Result<Address, BatcherError> {
+    let factory_addr: Address = env.storage().instance()
+        .get(&DataKey::FactoryAddr)
+        .ok_or(BatcherError::NotInitialized)?;
+
+    Ok(SoroswapFactoryClient::new(env, &factory_addr).get_pair(token_a.clone(), token_b.clone()))
+}
+
+/// Current pair reserves oriented as (reserve_in, reserve_out)
+pub fn get_reserves(env: &Env, token_in: &Address, token_out: &Address) -> Result<(i128, i128), BatcherError> {
+    let pair_client = SoroswapPairClient::new(env, &get_pair(env, token_in, token_out)?);
+    let (reserve_0, reserve_1) = pair_client.get_reserves();
+    if pair_client.token_0() == *token_in {
+        Ok((reserve_0, reserve_1))
+    } else {
+        Ok((reserve_1, reserve_0))
+    }
+}
+
+/// Quote an exact-input swap against the current pair reserves
+pub fn quote_exact_in(
+    env: &Env,
+    token_in: &Address,
+    token_out: &Address,
+    amount_in: i128,
+) -> Result<i128, BatcherError> {
+    let (reserve_in, reserve_out) = get_reserves(env, token_in, token_out)?;
+    Ok(get_amount_out(amount_in, reserve_in, reserve_out))
+}
+
+/// Swap an exact input held by this contract through the factory pair
+/// Returns the `token_out` amount actually received by this contract
+pub fn swap_exact_in(
+    env: &Env,
+    token_in: &Address,
+    token_out: &Address,
+    amount_in: i128,
+) -> Result<i128, BatcherError> {
+    let this = env.current_contract_address();
+    let pair_addr = get_pair(env, token_in, token_out)?;
+    let pair_client = SoroswapPairClient::new(env, &pair_addr);
+
+    let is_token_in_token_0 = pair_client.token_0() == *token_in;
+    let amount_out = quote_exact_in(env, token_in, token_out, amount_in)?;
+    if amount_out <= 0 {
+        return Err(BatcherError::InsufficientBalance);
+    }
+
+    let out_token = TokenClient::new(env, token_out);
+    let balance_before = out_token.balance(&this);
+
+    // Tokens must be transferred to the pair before calling swap
+    TokenClient::new(env, token_in).transfer(&this, &pair_addr, &amount_in);
+    let (amount_0_out, amount_1_out) = if is_token_in_token_0 {
+        (0, amount_out)
+    } else {
+        (amount_out, 0)
+    };
+    pair_client.swap(amount_0_out, amount_1_out, this.clone());
+
+    Ok(out_token.balance(&this) - balance_before)
+}
+
+/// Chained constant product quote along `path`
+/// Returns the amount after every hop; the first entry is `amount_in`
+pub fn get_amounts_out(env: &Env, amount_in: i128, path: &Vec<Address>) -> Result<Vec<i128>, BatcherError> {
+    validate_path(path)?;
+
+    let mut amounts = Vec::from_array(env, [amount_in]);
+    let mut amount = amount_in;
+    for i in 1..path.len() {
+        amount = quote_exact_in(env, &path.get(i - 1).unwrap(), &path.get(i).unwrap(), amount)?;
+        amounts.push_back(amount);
+    }
+    Ok(amounts)
+}
+
+/// Swap an exact input held by this contract hop by hop along `path`
+/// Intermediate tokens return to this contract between hops
+/// Every hop is quoted before the first transfer, so a path that cannot be swapped fails
+/// without moving funds; once tokens have moved, a failing hop aborts the invocation
+/// rather than returning an error that would leave the swap half done
+/// Returns the final-token amount actually received
+pub fn swap_exact_in_path(env: &Env, path: &Vec<Address>, amount_in: i128) -> Result<i128, BatcherError> {
+    let amounts = get_amounts_out(env, amount_in, path)?;
+    if amounts.iter().any(|amount| amount <= 0) {
+        return Err(BatcherError::InsufficientBalance);
+    }
+
+    let mut amount = amount_in;
+    for i in 1..path.len() {
+        amount = swap_exact_in(env, &path.get(i - 1).unwrap(), &path.get(i).unwrap(), amount)
+            .unwrap_or_else(|e| panic_with_error!(env, e));
+    }
+    Ok(amount)
+}
+
+/// Swap an exact input held by this contract through the Soroswap router along `path`
+/// The router enforces `amount_out_min` and the caller's `deadline`; a rejected swap is
+/// rolled back and surfaces as `SwapFailed` instead of aborting the caller
+/// Returns the final-token amount actually received
+pub fn swap_exact_in_via_router(
+    env: &Env,
+    path: &Vec<Address>,
+    amount_in: i128,
+    amount_out_min: i128,
+    deadline: u64,
+) -> Result<i128, BatcherError> {
+    validate_path(path)?;
+
+    let this = env.current_contract_address();
+    let router_addr: Address = env.storage().instance()
+        .get(&DataKey::RouterAddr)
+        .ok_or(BatcherError::NotInitialized)?;
+    let token_in = path.first().unwrap();
+    let first_pair = get_pair(env, &token_in, &path.get(1).unwrap())?;
+
+    // The router pulls the input from this contract into the first pair
+    env.authorize_as_current_contract(vec![
+        env,
+        InvokerContractAuthEntry::Contract(SubContractInvocation {
+            context: ContractContext {
+                contract: token_in,
+                fn_name: Symbol::new(env, "transfer"),
+                args: (this.clone(), first_pair, amount_in).into_val(env),
+            },
+            sub_invocations: Vec::new(env),
+        }),
+    ]);
+
+    let out_token = TokenClient::new(env, &path.last().unwrap());
+    let balance_before = out_token.balance(&this);
+
+    SoroswapRouterClient::new(env, &router_addr)
+        .try_swap_exact_tokens_for_tokens(amount_in, amount_out_min, path.clone(), this.clone(), deadline)
+        .ok_or(BatcherError::SwapFailed)?;
+
+    Ok(out_token.balance(&this) - balance_before)
+}
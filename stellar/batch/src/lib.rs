@@ -5,35 +5,76 @@
 #![no_std]
 
 use soroban_sdk::{
-    contract, contractimpl, contracttype,
-    Env, Address, Symbol, Vec, BytesN, token::Client as TokenClient
+    contract, contractevent, contractimpl, contracttype, panic_with_error, symbol_short,
+    Env, Address, Bytes, Symbol, Val, Vec, BytesN, token::Client as TokenClient
 };
 
 mod error;
 mod helpers;
+mod access;
 mod coinjoin;
-// mod batch_executor;  // TODO: Enable once fully integrated
-// mod multicall;       // TODO: Enable once fully integrated
+mod merkle;
+mod batch_executor;
+mod multicall;
+mod upgrade;
 #[cfg(test)]
 mod tests;
 
 pub use error::BatcherError;
-use coinjoin::{CoinJoinMixer, Denomination};
+pub use access::Role;
+use access::AccessControl;
+use batch_executor::{BatchExecutor, Order};
+use coinjoin::{
+    CoinJoinBatchExecutedEvent, CoinJoinMixer, Denomination, DepositInfo, ExecutionMode, FeeRecipientUpdatedEvent,
+    PoolStats, SettledBatch, VerifierUpdatedEvent, DEPOSIT_SCAN_LIMIT,
+};
+use merkle::{MerklePath, MerkleTree};
+use multicall::{CallData, CallResult, Multicall};
+use upgrade::Upgrade;
 
 // Storage keys for contract state
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum DataKey {
     Owner,
+    PendingOwner,
+    Role(Role),
     FactoryAddr,
     RouterAddr,
     Initialized,
+    SchemaVersion,
+    Paused,
     CoinJoinEnabled,
     // CoinJoin specific keys
-    CoinJoinPool(Symbol),
-    CoinJoinTotalDeposits(Symbol),
-    CoinJoinTotalWithdrawals(Symbol),
+    CoinJoinPool(u32),
+    CoinJoinPoolId(Address, Address, Symbol),
+    CoinJoinPoolCount,
+    Deposit(u32, u64),
+    DenominationSupported(Symbol),
+    // Fee keys
+    CollectedFees(Address),
+    FeeRecipient,
+    KeeperRewardBps,
+    CoinJoinTotalDeposits(u32),
+    CoinJoinTotalWithdrawals(u32),
     NullifierUsed(BytesN<32>),
+    CommitmentUsed(BytesN<32>),
+    // Merkle tree keys (persistent storage)
+    MerkleNode(u32, u32, u32),
+    MerkleLeafIndex(BytesN<32>),
+    // Withdrawal keys
+    VerifierAddr,
+    SettledBatch(u32, u64),
+    SettledBatchCount(u32),
+    // Batch executor keys
+    BatchEnabled,
+    BatchMaxSize,
+    BatchProcessed,
+    ClearingPrice,
+    OrderCount,
+    BatchOrder(u64),
+    // Multicall keys
+    MulticallEnabled,
 }
 
 // Factory client for querying pool addresses
@@ -101,6 +142,7 @@ mod pair_client {
         }
 
         /// Get token 1 address
+        #[allow(dead_code)]
         pub fn token_1(&self) -> Address {
             self.env.invoke_contract(
                 &self.address,
@@ -120,31 +162,107 @@ mod pair_client {
     }
 }
 
-use factory_client::SoroswapFactoryClient;
-use pair_client::SoroswapPairClient;
+// Router client for router-side execution
+mod router_client {
+    use soroban_sdk::{Address, Env, InvokeError, Symbol, IntoVal, Vec};
+
+    pub struct SoroswapRouterClient {
+        env: Env,
+        address: Address,
+    }
+
+    impl SoroswapRouterClient {
+        pub fn new(env: &Env, address: &Address) -> Self {
+            SoroswapRouterClient {
+                env: env.clone(),
+                address: address.clone(),
+            }
+        }
+
+        /// Swap an exact input along `path`, pulling `amount_in` from `to`
+        /// Returns the amounts after every hop, or None if the router rejected the swap
+        pub fn try_swap_exact_tokens_for_tokens(
+            &self,
+            amount_in: i128,
+            amount_out_min: i128,
+            path: Vec<Address>,
+            to: Address,
+            deadline: u64,
+        ) -> Option<Vec<i128>> {
+            self.env.try_invoke_contract::<Vec<i128>, InvokeError>(
+                &self.address,
+                &Symbol::new(&self.env, "swap_exact_tokens_for_tokens"),
+                (amount_in, amount_out_min, path, to, deadline).into_val(&self.env)
+            )
+            .ok()
+            .and_then(Result::ok)
+        }
+    }
+}
+
+// Verifier client for withdrawal proofs
+// Any contract exposing `verify(proof, public_inputs) -> bool` can be plugged in,
+// e.g. a Groth16 verifier for the withdrawal circuit
+mod verifier_client {
+    use soroban_sdk::{Bytes, BytesN, Env, Address, Symbol, IntoVal, Vec};
+
+    pub struct ProofVerifierClient {
+        env: Env,
+        address: Address,
+    }
+
+    impl ProofVerifierClient {
+        pub fn new(env: &Env, address: &Address) -> Self {
+            ProofVerifierClient {
+                env: env.clone(),
+                address: address.clone(),
+            }
+        }
+
+        /// Verify a proof against public inputs [root, nullifier_hash, recipient_hash]
+        pub fn verify(&self, proof: Bytes, public_inputs: Vec<BytesN<32>>) -> bool {
+            self.env.invoke_contract(
+                &self.address,
+                &Symbol::new(&self.env, "verify"),
+                (proof, public_inputs).into_val(&self.env)
+            )
+        }
+    }
+}
+
 
 // Event types for batch executor and multicall
-#[contracttype]
+#[contractevent]
 #[derive(Clone, Debug)]
 pub struct OrdersExecutedEvent {
+    #[topic]
     pub batch_id: u64,
     pub order_count: u32,
+    pub clearing_price: i128,
     pub timestamp: u64,
 }
 
-#[contracttype]
+#[contractevent]
 #[derive(Clone, Debug)]
 pub struct OrderSubmittedEvent {
+    #[topic]
     pub order_id: u64,
     pub user: Address,
     pub timestamp: u64,
 }
 
-#[contracttype]
+#[contractevent]
 #[derive(Clone, Debug)]
 pub struct MulticallCompletedEvent {
     pub call_count: u32,
-    pub gas_used: u64,
+    pub success_count: u32,
+    pub timestamp: u64,
+}
+
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct PauseStatusEvent {
+    pub paused: bool,
     pub timestamp: u64,
 }
 
@@ -169,9 +287,12 @@ impl SoroSwapBatcher {
         env.storage().instance().set(&DataKey::FactoryAddr, &factory_address);
         env.storage().instance().set(&DataKey::RouterAddr, &router_address);
         env.storage().instance().set(&DataKey::Initialized, &true);
+        Upgrade::init_schema(&env);
 
-        // Initialize CoinJoin mixer
+        // Initialize CoinJoin mixer, batch executor and multicall (off until an admin enables it)
         CoinJoinMixer::init_coinjoin(&env)?;
+        BatchExecutor::init_batch_executor(&env, true, 10)?;
+        Multicall::init_multicall(&env, false)?;
 
         // Extend TTL for all instance storage to 30 days
         env.storage().instance().extend_ttl(518400, 518400); // 30 days in ledgers (5 sec/ledger)
@@ -185,61 +306,68 @@ impl SoroSwapBatcher {
     /// Flow:
     /// 1. Validate amount matches CoinJoin denomination
     /// 2. Transfer tokens from user to batch contract
-    /// 3. Add to CoinJoin pool for the denomination
+    /// 3. Add to the CoinJoin pool an admin created for (token_in, token_out, denomination)
     /// 4. When pool reaches minimum size, execute mixed swap directly through pool
-    /// 5. Send output tokens to receiving address
+    /// 5. Record the settled batch; each participant later claims via `withdraw`
+    ///
+    /// `commitment` is computed off-chain from a user-held note:
+    /// `commitment = sha256(nullifier || secret)`. No recipient is taken here, so
+    /// the depositor is never stored next to the address that receives the payout.
+    ///
+    /// `path` is the swap route `[token_in, ..., token_out]`; each consecutive pair of
+    /// tokens must have a factory pair (e.g. XLM -> USDC -> EURC).
     pub fn private_swap(
         env: Env,
-        token_in: Address,
-        token_out: Address,
+        path: Vec<Address>,
         amount_in: i128,
         min_amount_out: i128,
         max_slippage_bps: u32,
         user_address: Address,
-        receiving_address: Address,
+        commitment: BytesN<32>,
     ) -> Result<u64, BatcherError> {
         if !helpers::is_initialized(&env) {
             return Err(BatcherError::NotInitialized);
         }
 
+        helpers::ensure_not_paused(&env)?;
+
         // Require user authorization
         user_address.require_auth();
 
         // Validate amount matches supported CoinJoin denomination
         let denomination = Denomination::from_amount(amount_in)
             .ok_or(BatcherError::InvalidInput)?;
+        helpers::validate_path(&path)?;
+        let pool_id = CoinJoinMixer::get_pool_id(
+            &env,
+            &path.first().unwrap(),
+            &path.last().unwrap(),
+            denomination,
+        )
+        .ok_or(BatcherError::PoolNotFound)?;
 
         // Transfer input tokens from user to batch contract
         let batch_contract_addr = env.current_contract_address();
-        TokenClient::new(&env, &token_in).transfer(
+        TokenClient::new(&env, &path.first().unwrap()).transfer(
             &user_address,
             &batch_contract_addr,
             &amount_in,
         );
 
-        // Create commitment and nullifier for CoinJoin
-        // In production, these would be provided by the user with ZK proofs
-        // For now, we use simplified placeholders
-        let commitment = Self::create_commitment(&env, &receiving_address);
-        let nullifier = Self::create_nullifier(&env, &user_address, amount_in);
-
-        // Add deposit to CoinJoin pool (includes sender and recipient addresses)
+        // Add deposit to the pool of this token pair and denomination
         CoinJoinMixer::deposit(
             &env,
-            denomination,
+            pool_id,
             commitment,
-            nullifier,
             user_address.clone(),
-            receiving_address.clone(),
             max_slippage_bps,
-            token_in.clone(),
-            token_out.clone(),
+            path.clone(),
             min_amount_out,
         )?;
 
         // Get current pool status AFTER adding this deposit
-        let pool_stats = CoinJoinMixer::get_pool_stats(&env, denomination)?;
-        let pool = CoinJoinMixer::get_pool(&env, denomination)?;
+        let pool_stats = CoinJoinMixer::get_pool_stats(&env, pool_id)?;
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
         let min_participants = pool.minimum_pool_size;
 
         // Log the deposit
@@ -252,16 +380,18 @@ impl SoroSwapBatcher {
             min_participants
         );
 
-        // If pool has enough deposits, ATTEMPT to execute mixing and swap
-        // BUT don't fail the deposit if execution fails
-        if pool_stats.current_pool_size >= min_participants {
+        // If pool has enough deposits, or its oldest deposit has waited out `max_wait_seconds`,
+        // ATTEMPT to execute mixing and swap BUT don't fail the deposit if execution fails.
+        // Router pools are left to keepers: the router's deadline must come from whoever
+        // submits the transaction, and a deposit carries none (see execute_pool)
+        let ready = pool_stats.current_pool_size >= min_participants || CoinJoinMixer::is_release_due(&env, &pool);
+        if ready && pool.execution_mode == ExecutionMode::Pair {
             let execution_result = Self::try_execute_batch_swap(
                 &env,
-                denomination,
-                token_in,
-                token_out,
-                min_amount_out,
-                receiving_address,
+                pool_id,
+                path,
+                None,
+                None,
             );
 
             match execution_result {
@@ -283,31 +413,46 @@ impl SoroSwapBatcher {
             }
         }
 
-        // Deposit is ALWAYS successful, regardless of execution outcome
+        // Deposit succeeds regardless of execution outcome, unless the executed swap
+        // paid a participant less than its minimum (see try_execute_batch_swap)
         Ok(env.ledger().timestamp())
     }
 
     /// Try to execute batch swap for a CoinJoin pool with equal payout system
     /// Called when pool reaches minimum size
     /// Uses iterative convergence to find optimal participant set
-    /// Executes single aggregated swap and distributes equally
-    /// Returns error if execution fails, but does NOT revert the calling transaction
+    /// Executes single aggregated swap along `path` and settles equal payouts for later withdrawal
+    /// Only deposits routed along the same path are batched together
+    /// Every check that can fail runs against the chained quote before any funds move, so an
+    /// error leaves the pool untouched and the calling deposit stands. The host forbids a
+    /// contract from re-entering itself, so the swap cannot run in a rollback-isolated
+    /// sub-invocation: a measured payout short of the largest `min_amount_out` in the batch,
+    /// possible only with tokens that deliver less than transferred, aborts with `SlippageExceeded`
+    /// A `keeper` receives its reward share of the batch fees. Router pools need the caller's
+    /// `deadline`, which is passed to the router unchanged. Returns the settled batch id
     fn try_execute_batch_swap(
         env: &Env,
-        denomination: Denomination,
-        token_in: Address,
-        token_out: Address,
-        _min_amount_out: i128,
-        to: Address,
-    ) -> Result<(), BatcherError> {
-        // Get pool with all deposits
-        let pool = CoinJoinMixer::get_pool(env, denomination)?;
+        pool_id: u32,
+        path: Vec<Address>,
+        keeper: Option<Address>,
+        deadline: Option<u64>,
+    ) -> Result<u64, BatcherError> {
+        // Get pool and the oldest pending deposits
+        let mut pool = CoinJoinMixer::get_pool(env, pool_id)?;
+        let denomination = pool.denomination;
+
+        let mut route_deposits = Vec::new(env);
+        for deposit in CoinJoinMixer::get_pending_deposits(env, &pool, pool.deposit_head, DEPOSIT_SCAN_LIMIT).iter() {
+            if deposit.path == path {
+                route_deposits.push_back(deposit);
+            }
+        }
 
         // Find optimal participant set using convergence algorithm
         let qualifying_deposits = CoinJoinMixer::find_optimal_participant_set(
             env,
-            denomination,
-            pool.deposits.clone(),
+            pool_id,
+            route_deposits,
         )?;
 
         // Calculate equal payout for qualifying participants
@@ -325,204 +470,168 @@ impl SoroSwapBatcher {
             payout_info.slippage_bps
         );
 
-        // Get factory address to query pool
-        let factory_addr: Address = env.storage().instance()
-            .get(&DataKey::FactoryAddr)
-            .ok_or(BatcherError::NotInitialized)?;
+        // Every participant receives the same share, so each share must cover the
+        // strictest minimum in the batch rather than the batch covering their sum
+        let mut max_min_out = 0i128;
+        let mut total_fees = 0i128;
+        for deposit in qualifying_deposits.iter() {
+            max_min_out = max_min_out.max(deposit.min_amount_out);
+            total_fees += deposit.fee_paid;
+        }
+        let required_output = max_min_out * payout_info.participant_count as i128;
 
-        let batch_addr = env.current_contract_address();
+        // Reject the batch while nothing has moved if the quote cannot cover every minimum
+        if payout_info.total_output_amount < required_output {
+            return Err(BatcherError::SlippageExceeded);
+        }
 
-        // Query factory for pool address
-        let factory_client = SoroswapFactoryClient::new(env, &factory_addr);
-        let pair_addr = factory_client.get_pair(token_in.clone(), token_out.clone());
+        // Execute SINGLE aggregated swap for all participants; fees stay behind in token_in
+        let total_output = match pool.execution_mode {
+            ExecutionMode::Pair => helpers::swap_exact_in_path(env, &path, payout_info.total_input_amount)?,
+            ExecutionMode::Router => {
+                let deadline = deadline.ok_or(BatcherError::PoolNotReady)?;
+                helpers::swap_exact_in_via_router(env, &path, payout_info.total_input_amount, required_output, deadline)?
+            }
+        };
 
-        // Create pair client
-        let pair_client = SoroswapPairClient::new(env, &pair_addr);
+        // Pay out what was actually received rather than the pre-swap quote
+        let equal_payout_amount = total_output / payout_info.participant_count as i128;
 
-        // Determine token order in the pair
-        let pair_token_0 = pair_client.token_0();
-        let is_token_in_token_0 = pair_token_0 == token_in;
+        // The swap has already moved tokens, so returning an error here would keep them
+        // moved while the deposits stay queued; abort instead to revert the whole invocation
+        if equal_payout_amount < max_min_out {
+            soroban_sdk::log!(
+                env,
+                "✗ Aggregated swap pays {} stroops each, participants require {}",
+                equal_payout_amount,
+                max_min_out
+            );
+            panic_with_error!(env, BatcherError::SlippageExceeded);
+        }
 
-        // Execute SINGLE aggregated swap for all participants
-        // Transfer total input tokens from batch contract to pool
-        TokenClient::new(env, &token_in).transfer(
-            &batch_addr,
-            &pair_addr,
-            &payout_info.total_input_amount,
+        soroban_sdk::log!(
+            env,
+            "Aggregated swap: {} stroops in, {} stroops out total, {} stroops fees retained",
+            payout_info.total_input_amount,
+            total_output,
+            total_fees
         );
 
-        // Calculate output from aggregated swap
-        let (reserve_0, reserve_1) = pair_client.get_reserves();
-        let (reserve_in, reserve_out) = if is_token_in_token_0 {
-            (reserve_0, reserve_1)
-        } else {
-            (reserve_1, reserve_0)
-        };
+        // Settle the batch: payouts are claimed per note through `withdraw`,
+        // so no recipient address is ever read from deposit storage
+        let mut commitments = Vec::new(env);
+        for deposit in qualifying_deposits.iter() {
+            commitments.push_back(deposit.commitment);
+        }
+        let batch_id = CoinJoinMixer::settle_batch(
+            env,
+            pool_id,
+            path.last().unwrap(),
+            equal_payout_amount,
+            commitments,
+        )?;
 
-        let amount_in_with_fee = payout_info.total_input_amount * 997; // 0.3% fee
-        let numerator = amount_in_with_fee * reserve_out;
-        let denominator = (reserve_in * 1000) + amount_in_with_fee;
-        let total_output = numerator / denominator;
+        let keeper_reward = match &keeper {
+            Some(keeper) => CoinJoinMixer::pay_keeper_reward(env, &pool, batch_id, keeper, total_fees),
+            None => 0,
+        };
+        CoinJoinMixer::accrue_fees(env, &pool.token_in, total_fees - keeper_reward);
 
-        // Execute single aggregated swap - send to batch contract first
-        let (amount_0_out, amount_1_out) = if is_token_in_token_0 {
-            (0, total_output) // Getting token_1 out
+        // Realized slippage is measured against the pre-swap quote
+        let quoted_output = payout_info.total_output_amount;
+        let slippage_bps = if quoted_output > total_output {
+            ((quoted_output - total_output) * 10_000 / quoted_output) as u32
         } else {
-            (total_output, 0) // Getting token_0 out
+            0
         };
+        CoinJoinBatchExecutedEvent {
+            pool_id,
+            batch_id,
+            participant_count: payout_info.participant_count,
+            total_input: payout_info.total_input_amount,
+            total_output,
+            payout_amount: equal_payout_amount,
+            fees: total_fees,
+            slippage_bps,
+            timestamp: env.ledger().timestamp(),
+        }
+        .publish(env);
 
-        pair_client.swap(amount_0_out, amount_1_out, batch_addr.clone());
-
-        soroban_sdk::log!(
-            env,
-            "Aggregated swap: {} stroops in, {} stroops out total",
-            payout_info.total_input_amount,
-            total_output
-        );
-
-        // Distribute equal payouts to all qualifying participants
-        // Send to each participant's specified recipient address
         soroban_sdk::log!(
             env,
-            "✓ Batch executed: {} participants, {} stroops each (total: {} stroops)",
+            "✓ Batch {} settled: {} participants, {} stroops each (total: {} stroops)",
+            batch_id,
             payout_info.participant_count,
-            payout_info.equal_payout_amount,
+            equal_payout_amount,
             total_output
         );
 
-        // Send equal payout to each participant's recipient address
-        // NOTE: For SAC (Stellar Asset Contract) tokens, recipient addresses must have
-        // a trustline established for the asset before they can receive tokens.
-        // Stellar account addresses (G...) are supported but require trustlines.
-        for i in 0..qualifying_deposits.len() {
-            let deposit = qualifying_deposits.get(i).unwrap();
-
-            TokenClient::new(env, &token_out).transfer(
-                &batch_addr,
-                &deposit.recipient_address,
-                &payout_info.equal_payout_amount,
-            );
-
-            soroban_sdk::log!(
-                env,
-                "  Payout {}/{}: {} stroops sent to recipient",
-                i + 1,
-                qualifying_deposits.len(),
-                payout_info.equal_payout_amount
-            );
-        }
-
         // Remove qualifying deposits from pool (keeping non-qualifying ones)
-        let mut remaining_deposits = Vec::new(env);
-        for i in 0..pool.deposits.len() {
-            let deposit = pool.deposits.get(i).unwrap();
-            let mut is_qualifying = false;
-
-            for j in 0..qualifying_deposits.len() {
-                let qual_deposit = qualifying_deposits.get(j).unwrap();
-                if deposit.nullifier == qual_deposit.nullifier {
-                    is_qualifying = true;
-                    break;
-                }
-            }
-
-            if !is_qualifying {
-                remaining_deposits.push_back(deposit);
-            }
+        for deposit in qualifying_deposits.iter() {
+            CoinJoinMixer::remove_deposit(env, &mut pool, deposit.seq);
         }
+        CoinJoinMixer::update_pool(env, pool_id, pool)?;
 
-        // Update pool with remaining deposits
-        let mut updated_pool = pool.clone();
-        updated_pool.deposits = remaining_deposits;
-        CoinJoinMixer::update_pool(env, denomination, updated_pool)?;
-
-        Ok(())
+        Ok(batch_id)
     }
 
-    /// Create commitment for CoinJoin deposit
-    /// In production, this would be a ZK commitment provided by the user
-    fn create_commitment(env: &Env, receiving_address: &Address) -> BytesN<32> {
-        // Simplified placeholder: use address serialization
-        // Production would use proper commitment scheme (Pedersen, etc.)
-        let mut bytes = [0u8; 32];
+    /// Execute a pool's next batch on behalf of a keeper
+    /// Anyone may call this once the oldest deposits on some route reach the pool's
+    /// minimum size; the keeper is paid its reward share of the batch fees in the
+    /// input token. `deadline` is the ledger timestamp after which the transaction must
+    /// not execute and is handed to the router in Router mode. Returns the settled batch id
+    pub fn execute_pool(env: Env, pool_id: u32, keeper: Address, deadline: u64) -> Result<u64, BatcherError> {
+        if !helpers::is_initialized(&env) {
+            return Err(BatcherError::NotInitialized);
+        }
 
-        // Use a simple hash of the timestamp and address
-        let timestamp = env.ledger().timestamp();
-        let time_bytes = timestamp.to_be_bytes();
+        helpers::ensure_not_paused(&env)?;
+        keeper.require_auth();
 
-        for (i, byte) in time_bytes.iter().enumerate() {
-            if i < 8 {
-                bytes[i] = *byte;
-            }
+        if env.ledger().timestamp() >= deadline {
+            return Err(BatcherError::OrderTooLate);
         }
 
-        // Fill rest with a pattern
-        for i in 8..32 {
-            bytes[i] = ((i * 17 + timestamp as usize) % 256) as u8;
-        }
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
+        let path = CoinJoinMixer::find_ready_path(&env, &pool).ok_or(BatcherError::PoolNotReady)?;
 
-        BytesN::from_array(env, &bytes)
+        Self::try_execute_batch_swap(&env, pool_id, path, Some(keeper), Some(deadline))
     }
 
-    /// Create nullifier for CoinJoin deposit
-    /// In production, this would be derived from user's secret
-    fn create_nullifier(env: &Env, _user_address: &Address, amount: i128) -> BytesN<32> {
-        // Simplified placeholder: hash amount + timestamp
-        // Production would use proper nullifier derived from user secret
-        let mut bytes = [0u8; 32];
-        let timestamp = env.ledger().timestamp();
-
-        // Add amount bytes
-        let amount_bytes = amount.to_be_bytes();
-        for (i, byte) in amount_bytes.iter().enumerate() {
-            if i < 16 {
-                bytes[i] = *byte;
-            }
-        }
-
-        // Add timestamp bytes
-        let time_bytes = timestamp.to_be_bytes();
-        for (i, byte) in time_bytes.iter().enumerate() {
-            if i < 8 {
-                bytes[16 + i] = *byte;
-            }
-        }
-
-        // Fill rest with pattern
-        for i in 24..32 {
-            bytes[i] = ((i * 23 + amount as usize + timestamp as usize) % 256) as u8;
+    /// Refund all expired deposits of a pool to their senders
+    /// Callable by anyone; returns the number of deposits refunded
+    pub fn refund_expired(env: Env, pool_id: u32) -> Result<u32, BatcherError> {
+        if !helpers::is_initialized(&env) {
+            return Err(BatcherError::NotInitialized);
         }
 
-        BytesN::from_array(env, &bytes)
+        CoinJoinMixer::refund_expired(&env, pool_id)
     }
 
-    /// Execute CoinJoin mixing manually
-    /// Called by contract owner or when pool is ready
-    pub fn execute_coinjoin_mixing(
+    /// Cancel the caller's own expired deposit and return its input tokens
+    /// Must be authorized by the address that made the deposit
+    pub fn cancel_deposit(
         env: Env,
-        denomination_symbol: Symbol,
-        max_deposits: Option<u32>,
-    ) -> Result<u32, BatcherError> {
+        pool_id: u32,
+        commitment: BytesN<32>,
+    ) -> Result<i128, BatcherError> {
         if !helpers::is_initialized(&env) {
             return Err(BatcherError::NotInitialized);
         }
 
-        // Convert symbol to denomination
-        let denomination = Self::symbol_to_denomination(&denomination_symbol)?;
-
-        // Execute mixing
-        let mix_result = CoinJoinMixer::execute_mixing(&env, denomination, max_deposits)?;
-
-        Ok(mix_result.anonymity_set_size)
+        CoinJoinMixer::cancel_deposit(&env, pool_id, &commitment)
     }
 
-    /// Get CoinJoin statistics for a denomination
+    /// Get CoinJoin statistics for the pool of a token pair and denomination
+    /// Returns `(current_pool_size, current_fees, estimated_wait_time)`, the wait in seconds
     pub fn get_coinjoin_stats(
         env: Env,
+        token_in: Address,
+        token_out: Address,
         denomination_symbol: Symbol,
     ) -> Result<(u32, u32, u32), BatcherError> {
-        let denomination = Self::symbol_to_denomination(&denomination_symbol)?;
-        let stats = CoinJoinMixer::get_pool_stats(&env, denomination)?;
+        let pool_id = Self::get_pool_id(env.clone(), token_in, token_out, denomination_symbol)?;
+        let stats = CoinJoinMixer::get_pool_stats(&env, pool_id)?;
 
         Ok((
             stats.current_pool_size,
@@ -531,15 +640,51 @@ impl SoroSwapBatcher {
         ))
     }
 
-    /// Get deposit details for monitoring (privacy-safe)
+    /// Get full statistics of a pool, including its token pair and lifetime totals
+    pub fn get_pool_stats(env: Env, pool_id: u32) -> Result<PoolStats, BatcherError> {
+        CoinJoinMixer::get_pool_stats(&env, pool_id)
+    }
+
+    /// Create the pool for a token pair and denomination (admin only)
+    /// `private_swap` only accepts deposits into pools created here
+    /// Returns the new pool id
+    pub fn create_pool(
+        env: Env,
+        token_in: Address,
+        token_out: Address,
+        denomination_symbol: Symbol,
+    ) -> Result<u32, BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        let denomination = Self::symbol_to_denomination(&denomination_symbol)?;
+        CoinJoinMixer::create_pool(&env, token_in, token_out, denomination)
+    }
+
+    /// Get the id of the pool for a token pair and denomination
+    pub fn get_pool_id(
+        env: Env,
+        token_in: Address,
+        token_out: Address,
+        denomination_symbol: Symbol,
+    ) -> Result<u32, BatcherError> {
+        let denomination = Self::symbol_to_denomination(&denomination_symbol)?;
+        CoinJoinMixer::get_pool_id(&env, &token_in, &token_out, denomination)
+            .ok_or(BatcherError::InvalidInput)
+    }
+
+    /// Get the number of pools; ids run from 0 to count - 1
+    pub fn get_pool_count(env: Env) -> u32 {
+        CoinJoinMixer::get_pool_count(&env)
+    }
+
+    /// Get details of the pending deposit with sequence number `seq` (privacy-safe)
     /// Returns: (min_amount_out, max_slippage_bps, expiry_timestamp, timestamp, fee_paid)
     pub fn get_deposit_details(
         env: Env,
-        denomination_symbol: Symbol,
-        index: u32,
+        pool_id: u32,
+        seq: u64,
     ) -> Result<(i128, u32, u64, u64, i128), BatcherError> {
-        let denomination = Self::symbol_to_denomination(&denomination_symbol)?;
-        let deposit_info = CoinJoinMixer::get_deposit_details(&env, denomination, index)?;
+        let deposit_info = CoinJoinMixer::get_deposit_details(&env, pool_id, seq)?;
 
         Ok((
             deposit_info.min_amount_out,
@@ -550,6 +695,358 @@ impl SoroSwapBatcher {
         ))
     }
 
+    /// Get the pending deposit queue bounds of a pool as `(head, tail)`
+    /// Pending deposits have sequence numbers in `head..tail`; gaps were executed or refunded
+    pub fn get_deposit_range(env: Env, pool_id: u32) -> Result<(u64, u64), BatcherError> {
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
+        Ok((pool.deposit_head, pool.deposit_tail))
+    }
+
+    /// Page through a pool's pending deposits (privacy-safe), oldest first
+    /// Covers sequence numbers `start_seq..start_seq + limit`, with `limit` capped at 64
+    pub fn get_pending_deposits(
+        env: Env,
+        pool_id: u32,
+        start_seq: u64,
+        limit: u32,
+    ) -> Result<Vec<DepositInfo>, BatcherError> {
+        CoinJoinMixer::get_pending_deposit_infos(&env, pool_id, start_seq, limit)
+    }
+
+    /// Get the current deposit Merkle root for a pool
+    pub fn get_merkle_root(env: Env, pool_id: u32) -> Result<BytesN<32>, BatcherError> {
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
+        Ok(pool.merkle_root)
+    }
+
+    /// Get the recent deposit Merkle roots for a pool, most recent first
+    pub fn get_merkle_roots(env: Env, pool_id: u32) -> Result<Vec<BytesN<32>>, BatcherError> {
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
+        Ok(pool.merkle_tree.root_history(&env))
+    }
+
+    /// Check whether a root is the current or a recent Merkle root for a pool
+    pub fn is_known_root(env: Env, pool_id: u32, root: BytesN<32>) -> Result<bool, BatcherError> {
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
+        Ok(pool.merkle_tree.is_known_root(&root))
+    }
+
+    /// Get the Merkle leaf index of a deposited commitment
+    pub fn get_leaf_index(env: Env, commitment: BytesN<32>) -> Result<u32, BatcherError> {
+        CoinJoinMixer::get_leaf_index(&env, &commitment)
+    }
+
+    /// Get the membership path of a leaf against the current root
+    /// Clients use this to build withdrawal proofs off-chain
+    pub fn get_merkle_path(
+        env: Env,
+        pool_id: u32,
+        leaf_index: u32,
+    ) -> Result<MerklePath, BatcherError> {
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
+        pool.merkle_tree.get_path(&env, pool_id, leaf_index)
+    }
+
+    /// Withdraw one participant's payout from a settled batch
+    /// `proof` shows knowledge of a note whose commitment is in the batch's Merkle root,
+    /// and is bound to `nullifier_hash` and `recipient`. Anyone (e.g. a relayer) may submit it.
+    pub fn withdraw(
+        env: Env,
+        pool_id: u32,
+        batch_id: u64,
+        nullifier_hash: BytesN<32>,
+        recipient: Address,
+        proof: Bytes,
+    ) -> Result<i128, BatcherError> {
+        if !helpers::is_initialized(&env) {
+            return Err(BatcherError::NotInitialized);
+        }
+
+        CoinJoinMixer::withdraw(&env, pool_id, batch_id, nullifier_hash, recipient, proof)
+    }
+
+    /// Set the withdrawal proof verifier contract (admin only)
+    pub fn set_verifier(env: Env, verifier: Address) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        env.storage().instance().set(&DataKey::VerifierAddr, &verifier);
+        VerifierUpdatedEvent { verifier }.publish(&env);
+        Ok(())
+    }
+
+    /// Get the withdrawal proof verifier contract
+    pub fn get_verifier(env: Env) -> Result<Address, BatcherError> {
+        env.storage().instance()
+            .get(&DataKey::VerifierAddr)
+            .ok_or(BatcherError::VerifierNotSet)
+    }
+
+    /// Get a settled batch (payout amount, commitments, root, remaining claims)
+    pub fn get_settled_batch(
+        env: Env,
+        pool_id: u32,
+        batch_id: u64,
+    ) -> Result<SettledBatch, BatcherError> {
+        CoinJoinMixer::get_settled_batch(&env, pool_id, batch_id)
+    }
+
+    /// Get the number of settled batches for a pool
+    pub fn get_settled_batch_count(env: Env, pool_id: u32) -> Result<u64, BatcherError> {
+        Ok(CoinJoinMixer::get_settled_batch_count(&env, pool_id))
+    }
+
+    /// Get the membership path of a commitment within a settled batch's Merkle root
+    pub fn get_batch_merkle_path(
+        env: Env,
+        pool_id: u32,
+        batch_id: u64,
+        leaf_index: u32,
+    ) -> Result<MerklePath, BatcherError> {
+        let batch = CoinJoinMixer::get_settled_batch(&env, pool_id, batch_id)?;
+        let (root, siblings) = MerkleTree::root_and_path(&env, &batch.commitments, leaf_index)?;
+
+        Ok(MerklePath {
+            leaf: batch.commitments.get(leaf_index).unwrap(),
+            leaf_index,
+            siblings,
+            root,
+        })
+    }
+
+    /// Check whether a nullifier hash has already been spent
+    pub fn is_nullifier_used(env: Env, nullifier_hash: BytesN<32>) -> bool {
+        CoinJoinMixer::is_nullifier_used(&env, &nullifier_hash)
+    }
+
+    /// Check whether a (nullifier, secret) note opens the given commitment and nullifier hash
+    /// Intended for read-only simulation by wallets; never submit note preimages on-chain
+    pub fn verify_note(
+        env: Env,
+        commitment: BytesN<32>,
+        nullifier_hash: BytesN<32>,
+        nullifier: BytesN<32>,
+        secret: BytesN<32>,
+    ) -> bool {
+        CoinJoinMixer::verify_commitment(&env, &commitment, &nullifier_hash, &nullifier, &secret)
+    }
+
+    /// Set the account allowed to withdraw collected fees (fee manager only)
+    pub fn set_fee_recipient(env: Env, fee_recipient: Address) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::FeeManager)?;
+
+        env.storage().instance().set(&DataKey::FeeRecipient, &fee_recipient);
+        FeeRecipientUpdatedEvent { fee_recipient }.publish(&env);
+        Ok(())
+    }
+
+    /// Get the fee recipient; defaults to the owner until one is set
+    pub fn get_fee_recipient(env: Env) -> Result<Address, BatcherError> {
+        match env.storage().instance().get(&DataKey::FeeRecipient) {
+            Some(fee_recipient) => Ok(fee_recipient),
+            None => Self::get_owner(env),
+        }
+    }
+
+    /// Withdraw collected CoinJoin fees in `token` to `to` (fee recipient only)
+    pub fn withdraw_fees(env: Env, token: Address, to: Address, amount: i128) -> Result<(), BatcherError> {
+        let fee_recipient = Self::get_fee_recipient(env.clone())?;
+        fee_recipient.require_auth();
+
+        CoinJoinMixer::withdraw_fees(&env, &token, &to, amount)
+    }
+
+    /// Set the keeper share of batch fees in basis points, at most 5000 (fee manager only)
+    pub fn set_keeper_reward_bps(env: Env, reward_bps: u32) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::FeeManager)?;
+
+        CoinJoinMixer::set_keeper_reward_bps(&env, reward_bps)
+    }
+
+    /// Get the keeper share of batch fees in basis points
+    pub fn get_keeper_reward_bps(env: Env) -> u32 {
+        CoinJoinMixer::get_keeper_reward_bps(&env)
+    }
+
+    /// Get the fees collected in `token` and not yet withdrawn
+    pub fn get_collected_fees(env: Env, token: Address) -> i128 {
+        CoinJoinMixer::get_collected_fees(&env, &token)
+    }
+
+    /// Set a pool's minimum and maximum participant counts (admin only)
+    /// Requires `2 <= minimum_pool_size <= maximum_pool_size <= 50`
+    pub fn set_pool_size_limits(
+        env: Env,
+        pool_id: u32,
+        minimum_pool_size: u32,
+        maximum_pool_size: u32,
+    ) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        CoinJoinMixer::set_pool_size_limits(&env, pool_id, minimum_pool_size, maximum_pool_size)
+    }
+
+    /// Set a pool's deposit fee in basis points, at most 1000 (fee manager only)
+    pub fn set_pool_fee(env: Env, pool_id: u32, fee_basis_points: u32) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::FeeManager)?;
+
+        CoinJoinMixer::set_pool_fee(&env, pool_id, fee_basis_points)
+    }
+
+    /// Set a pool's deposit expiry window, between 1 hour and 30 days (admin only)
+    pub fn set_deposit_expiry(env: Env, pool_id: u32, deposit_expiry_seconds: u64) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        CoinJoinMixer::set_deposit_expiry(&env, pool_id, deposit_expiry_seconds)
+    }
+
+    /// Set how long a pool's oldest deposit may wait before a batch is released with as few
+    /// as 2 participants, 0 to disable or between 5 minutes and 30 days (admin only)
+    pub fn set_max_wait(env: Env, pool_id: u32, max_wait_seconds: u64) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        CoinJoinMixer::set_max_wait(&env, pool_id, max_wait_seconds)
+    }
+
+    /// Get a pool's configuration
+    /// Returns: (minimum_pool_size, maximum_pool_size, fee_basis_points, deposit_expiry_seconds, max_wait_seconds)
+    pub fn get_pool_config(env: Env, pool_id: u32) -> Result<(u32, u32, u32, u64, u64), BatcherError> {
+        let pool = CoinJoinMixer::get_pool(&env, pool_id)?;
+
+        Ok((
+            pool.minimum_pool_size,
+            pool.maximum_pool_size,
+            pool.fee_basis_points,
+            pool.deposit_expiry_seconds,
+            pool.max_wait_seconds,
+        ))
+    }
+
+    /// Enable or disable deposits of a denomination across all pools (admin only)
+    pub fn set_denomination_supported(
+        env: Env,
+        denomination_symbol: Symbol,
+        supported: bool,
+    ) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        let denomination = Self::symbol_to_denomination(&denomination_symbol)?;
+        CoinJoinMixer::set_denomination_supported(&env, denomination, supported);
+        Ok(())
+    }
+
+    /// Check whether a denomination accepts deposits
+    pub fn is_denomination_supported(env: Env, denomination_symbol: Symbol) -> Result<bool, BatcherError> {
+        let denomination = Self::symbol_to_denomination(&denomination_symbol)?;
+        Ok(CoinJoinMixer::is_denomination_supported(&env, denomination))
+    }
+
+    /// Select how a pool's aggregated swap is executed (admin only)
+    /// `Router` sends it through the configured router with a deadline and aggregate `amount_out_min`
+    pub fn set_execution_mode(
+        env: Env,
+        pool_id: u32,
+        mode: ExecutionMode,
+    ) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        CoinJoinMixer::set_execution_mode(&env, pool_id, mode)
+    }
+
+    /// Get a pool's execution mode
+    pub fn get_execution_mode(env: Env, pool_id: u32) -> Result<ExecutionMode, BatcherError> {
+        Ok(CoinJoinMixer::get_pool(&env, pool_id)?.execution_mode)
+    }
+
+    /// Submit an order to the CowSwap-style batch executor
+    /// Escrows `amount_in` of `token_in` until the order is executed or cancelled
+    pub fn submit_order_to_batch(
+        env: Env,
+        user: Address,
+        token_in: Address,
+        token_out: Address,
+        amount_in: i128,
+        amount_out_min: i128,
+        expiry: u64,
+    ) -> Result<u64, BatcherError> {
+        helpers::ensure_not_paused(&env)?;
+        BatchExecutor::submit_order_to_batch(&env, user, token_in, token_out, amount_in, amount_out_min, expiry)
+    }
+
+    /// Execute stored orders at a single uniform clearing price (keeper only)
+    /// The keeper chooses which orders are netted against each other
+    /// Returns (executed order count, total input volume, executed order ids)
+    pub fn execute_batch(env: Env, order_ids: Vec<u64>) -> Result<(u32, i128, Vec<u64>), BatcherError> {
+        AccessControl::require_role(&env, Role::Keeper)?;
+        helpers::ensure_not_paused(&env)?;
+        BatchExecutor::execute_batch(&env, order_ids)
+    }
+
+    /// Cancel a pending order and refund its escrowed input
+    pub fn cancel_order(env: Env, order_id: u64) -> Result<i128, BatcherError> {
+        BatchExecutor::cancel_order(&env, order_id)
+    }
+
+    /// Get a stored batch order
+    pub fn get_order(env: Env, order_id: u64) -> Result<Order, BatcherError> {
+        BatchExecutor::get_order(&env, order_id)
+    }
+
+    /// Get batch executor statistics (enabled, max batch size, batches processed, last clearing price)
+    pub fn get_batch_statistics(env: Env) -> Result<(bool, u32, u64, Option<i128>), BatcherError> {
+        BatchExecutor::get_batch_statistics(&env)
+    }
+
+    /// Enable/disable the batch executor and set its maximum batch size (admin only)
+    pub fn configure_batch_executor(env: Env, enabled: bool, max_batch_size: u32) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        BatchExecutor::init_batch_executor(&env, enabled, max_batch_size)
+    }
+
+    /// Execute contract calls atomically: if any call fails the whole invocation reverts
+    /// Returns each call's return value in order (admin only)
+    /// Calls run with this contract as the invoker, so they can move any token it holds
+    pub fn multicall(env: Env, calls: Vec<CallData>) -> Result<Vec<Val>, BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        Multicall::multicall(&env, calls)
+    }
+
+    /// Execute contract calls independently, recording per-call success or failure (admin only)
+    pub fn multicall_safe(env: Env, calls: Vec<CallData>) -> Result<Vec<CallResult>, BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        Multicall::multicall_safe(&env, calls)
+    }
+
+    /// Enable or disable multicall (admin only)
+    pub fn set_multicall_enabled(env: Env, enabled: bool) -> Result<(), BatcherError> {
+        AccessControl::require_role(&env, Role::Admin)?;
+
+        Multicall::init_multicall(&env, enabled)
+    }
+
+    /// Check if multicall is enabled
+    pub fn is_multicall_enabled(env: Env) -> bool {
+        Multicall::is_enabled(&env)
+    }
+
+    /// Pause deposits, order submission and batch execution (pauser only)
+    /// Queued deposits and orders can still be reclaimed, and settled payouts withdrawn
+    pub fn pause(env: Env) -> Result<(), BatcherError> {
+        Self::set_paused(&env, true)
+    }
+
+    /// Resume normal operation after `pause` (pauser only)
+    pub fn unpause(env: Env) -> Result<(), BatcherError> {
+        Self::set_paused(&env, false)
+    }
+
+    /// Check if the contract is paused
+    pub fn is_paused(env: Env) -> bool {
+        helpers::is_paused(&env)
+    }
+
     /// Check if CoinJoin is enabled
     pub fn is_coinjoin_enabled(env: Env) -> bool {
         env.storage().instance()
@@ -559,13 +1056,49 @@ impl SoroSwapBatcher {
 
     /// Get contract owner
     pub fn get_owner(env: Env) -> Result<Address, BatcherError> {
-        if !helpers::is_initialized(&env) {
-            return Err(BatcherError::NotInitialized);
-        }
+        AccessControl::get_owner(&env)
+    }
 
-        env.storage().instance()
-            .get(&DataKey::Owner)
-            .ok_or(BatcherError::NotInitialized)
+    /// Nominate a new owner, who must call `accept_owner` to take over (owner only)
+    pub fn propose_owner(env: Env, pending_owner: Address) -> Result<(), BatcherError> {
+        AccessControl::propose_owner(&env, pending_owner)
+    }
+
+    /// Accept a pending ownership transfer (pending owner only)
+    pub fn accept_owner(env: Env) -> Result<(), BatcherError> {
+        AccessControl::accept_owner(&env)
+    }
+
+    /// Get the owner nominated by `propose_owner`, if any
+    pub fn get_pending_owner(env: Env) -> Option<Address> {
+        AccessControl::get_pending_owner(&env)
+    }
+
+    /// Grant a role to an account, replacing its previous holder (owner only)
+    pub fn set_role(env: Env, role: Role, account: Address) -> Result<(), BatcherError> {
+        AccessControl::set_role(&env, role, account)
+    }
+
+    /// Get the account holding a role; roles never granted are held by the owner
+    pub fn get_role(env: Env, role: Role) -> Result<Address, BatcherError> {
+        AccessControl::get_role(&env, role)
+    }
+
+    /// Replace the contract code with an uploaded Wasm (owner only)
+    /// Storage and balances are kept; call `migrate` once the new code is live
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), BatcherError> {
+        Upgrade::upgrade(&env, new_wasm_hash)
+    }
+
+    /// Migrate stored state to the schema of the running code (owner only)
+    /// Returns the schema version after migration
+    pub fn migrate(env: Env) -> Result<u32, BatcherError> {
+        Upgrade::migrate(&env)
+    }
+
+    /// Get the schema version of the stored state
+    pub fn get_schema_version(env: Env) -> u32 {
+        Upgrade::get_schema_version(&env)
     }
 
     /// Get router address
@@ -592,14 +1125,26 @@ impl SoroSwapBatcher {
 
     // Helper Functions
 
+    fn set_paused(env: &Env, paused: bool) -> Result<(), BatcherError> {
+        AccessControl::require_role(env, Role::Pauser)?;
+
+        env.storage().instance().set(&DataKey::Paused, &paused);
+        PauseStatusEvent {
+            paused,
+            timestamp: env.ledger().timestamp(),
+        }
+        .publish(env);
+        Ok(())
+    }
+
     fn symbol_to_denomination(symbol: &Symbol) -> Result<Denomination, BatcherError> {
-        if *symbol == Symbol::short("10") {
+        if *symbol == symbol_short!("10") {
             Ok(Denomination::Small)
-        } else if *symbol == Symbol::short("100") {
+        } else if *symbol == symbol_short!("100") {
             Ok(Denomination::Medium)
-        } else if *symbol == Symbol::short("1K") {
+        } else if *symbol == symbol_short!("1K") {
             Ok(Denomination::Large)
-        } else if *symbol == Symbol::short("10K") {
+        } else if *symbol == symbol_short!("10K") {
             Ok(Denomination::ExtraLarge)
         } else {
             Err(BatcherError::InvalidInput)
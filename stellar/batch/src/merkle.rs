@@ -0,0 +1,409 @@
+// src/merkle.rs
+// Incremental Merkle Tree Module - Tornado Cash Inspired
+// Source: https://github.com/tornadocash/tornado-core/blob/master/contracts/MerkleTreeWithHistory.sol
+//
+// Design Principles:
+// - Fixed depth, append-only tree of deposit commitments
+// - Ring buffer of recent roots so proofs built against a slightly stale root stay valid
+// - Insertion only needs the filled subtrees kept in the tree itself, never stored nodes
+// - Every node on an insertion path is persisted so clients can fetch membership paths
+
+use soroban_sdk::{contracttype, Bytes, BytesN, Env, Vec};
+use crate::{coinjoin::DEPOSIT_TTL_LEDGERS, error::BatcherError, DataKey};
+
+/// Tree depth: 2^20 (~1M) leaves per pool, same as Tornado Cash
+pub const MERKLE_TREE_DEPTH: u32 = 20;
+
+/// Number of historical roots accepted for membership proofs
+pub const ROOT_HISTORY_SIZE: u32 = 30;
+
+/// TTL for persisted tree nodes, refreshed on every write or path read
+/// Matches pending deposits so a deposit's path stays readable for as long as the deposit
+const NODE_TTL_LEDGERS: u32 = DEPOSIT_TTL_LEDGERS;
+
+/// Empty-subtree hashes per level: level 0 is the all-zero leaf, each next level is
+/// sha256(zero || zero) of the one below, and the last entry is the empty tree root
+const ZERO_HASHES: [[u8; 32]; MERKLE_TREE_DEPTH as usize + 1] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0xf5, 0xa5, 0xfd, 0x42, 0xd1, 0x6a, 0x20, 0x30, 0x27, 0x98, 0xef, 0x6e, 0xd3, 0x09, 0x97, 0x9b, 0x43, 0x00, 0x3d, 0x23, 0x20, 0xd9, 0xf0, 0xe8, 0xea, 0x98, 0x31, 0xa9, 0x27, 0x59, 0xfb, 0x4b],
+    [0xdb, 0x56, 0x11, 0x4e, 0x00, 0xfd, 0xd4, 0xc1, 0xf8, 0x5c, 0x89, 0x2b, 0xf3, 0x5a, 0xc9, 0xa8, 0x92, 0x89, 0xaa, 0xec, 0xb1, 0xeb, 0xd0, 0xa9, 0x6c, 0xde, 0x60, 0x6a, 0x74, 0x8b, 0x5d, 0x71],
+    [0xc7, 0x80, 0x09, 0xfd, 0xf0, 0x7f, 0xc5, 0x6a, 0x11, 0xf1, 0x22, 0x37, 0x06, 0x58, 0xa3, 0x53, 0xaa, 0xa5, 0x42, 0xed, 0x63, 0xe4, 0x4c, 0x4b, 0xc1, 0x5f, 0xf4, 0xcd, 0x10, 0x5a, 0xb3, 0x3c],
+    [0x53, 0x6d, 0x98, 0x83, 0x7f, 0x2d, 0xd1, 0x65, 0xa5, 0x5d, 0x5e, 0xea, 0xe9, 0x14, 0x85, 0x95, 0x44, 0x72, 0xd5, 0x6f, 0x24, 0x6d, 0xf2, 0x56, 0xbf, 0x3c, 0xae, 0x19, 0x35, 0x2a, 0x12, 0x3c],
+    [0x9e, 0xfd, 0xe0, 0x52, 0xaa, 0x15, 0x42, 0x9f, 0xae, 0x05, 0xba, 0xd4, 0xd0, 0xb1, 0xd7, 0xc6, 0x4d, 0xa6, 0x4d, 0x03, 0xd7, 0xa1, 0x85, 0x4a, 0x58, 0x8c, 0x2c, 0xb8, 0x43, 0x0c, 0x0d, 0x30],
+    [0xd8, 0x8d, 0xdf, 0xee, 0xd4, 0x00, 0xa8, 0x75, 0x55, 0x96, 0xb2, 0x19, 0x42, 0xc1, 0x49, 0x7e, 0x11, 0x4c, 0x30, 0x2e, 0x61, 0x18, 0x29, 0x0f, 0x91, 0xe6, 0x77, 0x29, 0x76, 0x04, 0x1f, 0xa1],
+    [0x87, 0xeb, 0x0d, 0xdb, 0xa5, 0x7e, 0x35, 0xf6, 0xd2, 0x86, 0x67, 0x38, 0x02, 0xa4, 0xaf, 0x59, 0x75, 0xe2, 0x25, 0x06, 0xc7, 0xcf, 0x4c, 0x64, 0xbb, 0x6b, 0xe5, 0xee, 0x11, 0x52, 0x7f, 0x2c],
+    [0x26, 0x84, 0x64, 0x76, 0xfd, 0x5f, 0xc5, 0x4a, 0x5d, 0x43, 0x38, 0x51, 0x67, 0xc9, 0x51, 0x44, 0xf2, 0x64, 0x3f, 0x53, 0x3c, 0xc8, 0x5b, 0xb9, 0xd1, 0x6b, 0x78, 0x2f, 0x8d, 0x7d, 0xb1, 0x93],
+    [0x50, 0x6d, 0x86, 0x58, 0x2d, 0x25, 0x24, 0x05, 0xb8, 0x40, 0x01, 0x87, 0x92, 0xca, 0xd2, 0xbf, 0x12, 0x59, 0xf1, 0xef, 0x5a, 0xa5, 0xf8, 0x87, 0xe1, 0x3c, 0xb2, 0xf0, 0x09, 0x4f, 0x51, 0xe1],
+    [0xff, 0xff, 0x0a, 0xd7, 0xe6, 0x59, 0x77, 0x2f, 0x95, 0x34, 0xc1, 0x95, 0xc8, 0x15, 0xef, 0xc4, 0x01, 0x4e, 0xf1, 0xe1, 0xda, 0xed, 0x44, 0x04, 0xc0, 0x63, 0x85, 0xd1, 0x11, 0x92, 0xe9, 0x2b],
+    [0x6c, 0xf0, 0x41, 0x27, 0xdb, 0x05, 0x44, 0x1c, 0xd8, 0x33, 0x10, 0x7a, 0x52, 0xbe, 0x85, 0x28, 0x68, 0x89, 0x0e, 0x43, 0x17, 0xe6, 0xa0, 0x2a, 0xb4, 0x76, 0x83, 0xaa, 0x75, 0x96, 0x42, 0x20],
+    [0xb7, 0xd0, 0x5f, 0x87, 0x5f, 0x14, 0x00, 0x27, 0xef, 0x51, 0x18, 0xa2, 0x24, 0x7b, 0xbb, 0x84, 0xce, 0x8f, 0x2f, 0x0f, 0x11, 0x23, 0x62, 0x30, 0x85, 0xda, 0xf7, 0x96, 0x0c, 0x32, 0x9f, 0x5f],
+    [0xdf, 0x6a, 0xf5, 0xf5, 0xbb, 0xdb, 0x6b, 0xe9, 0xef, 0x8a, 0xa6, 0x18, 0xe4, 0xbf, 0x80, 0x73, 0x96, 0x08, 0x67, 0x17, 0x1e, 0x29, 0x67, 0x6f, 0x8b, 0x28, 0x4d, 0xea, 0x6a, 0x08, 0xa8, 0x5e],
+    [0xb5, 0x8d, 0x90, 0x0f, 0x5e, 0x18, 0x2e, 0x3c, 0x50, 0xef, 0x74, 0x96, 0x9e, 0xa1, 0x6c, 0x77, 0x26, 0xc5, 0x49, 0x75, 0x7c, 0xc2, 0x35, 0x23, 0xc3, 0x69, 0x58, 0x7d, 0xa7, 0x29, 0x37, 0x84],
+    [0xd4, 0x9a, 0x75, 0x02, 0xff, 0xcf, 0xb0, 0x34, 0x0b, 0x1d, 0x78, 0x85, 0x68, 0x85, 0x00, 0xca, 0x30, 0x81, 0x61, 0xa7, 0xf9, 0x6b, 0x62, 0xdf, 0x9d, 0x08, 0x3b, 0x71, 0xfc, 0xc8, 0xf2, 0xbb],
+    [0x8f, 0xe6, 0xb1, 0x68, 0x92, 0x56, 0xc0, 0xd3, 0x85, 0xf4, 0x2f, 0x5b, 0xbe, 0x20, 0x27, 0xa2, 0x2c, 0x19, 0x96, 0xe1, 0x10, 0xba, 0x97, 0xc1, 0x71, 0xd3, 0xe5, 0x94, 0x8d, 0xe9, 0x2b, 0xeb],
+    [0x8d, 0x0d, 0x63, 0xc3, 0x9e, 0xba, 0xde, 0x85, 0x09, 0xe0, 0xae, 0x3c, 0x9c, 0x38, 0x76, 0xfb, 0x5f, 0xa1, 0x12, 0xbe, 0x18, 0xf9, 0x05, 0xec, 0xac, 0xfe, 0xcb, 0x92, 0x05, 0x76, 0x03, 0xab],
+    [0x95, 0xee, 0xc8, 0xb2, 0xe5, 0x41, 0xca, 0xd4, 0xe9, 0x1d, 0xe3, 0x83, 0x85, 0xf2, 0xe0, 0x46, 0x61, 0x9f, 0x54, 0x49, 0x6c, 0x23, 0x82, 0xcb, 0x6c, 0xac, 0xd5, 0xb9, 0x8c, 0x26, 0xf5, 0xa4],
+    [0xf8, 0x93, 0xe9, 0x08, 0x91, 0x77, 0x75, 0xb6, 0x2b, 0xff, 0x23, 0x29, 0x4d, 0xbb, 0xe3, 0xa1, 0xcd, 0x8e, 0x6c, 0xc1, 0xc3, 0x5b, 0x48, 0x01, 0x88, 0x7b, 0x64, 0x6a, 0x6f, 0x81, 0xf1, 0x7f],
+    [0xcd, 0xdb, 0xa7, 0xb5, 0x92, 0xe3, 0x13, 0x33, 0x93, 0xc1, 0x61, 0x94, 0xfa, 0xc7, 0x43, 0x1a, 0xbf, 0x2f, 0x54, 0x85, 0xed, 0x71, 0x1d, 0xb2, 0x82, 0x18, 0x3c, 0x81, 0x9e, 0x08, 0xeb, 0xaa],
+];
+
+/// Incremental tree state kept alongside each CoinJoin pool
+/// `filled_subtrees[i]` is the most recent left child hashed at level `i`, as in Tornado Cash
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct MerkleTree {
+    pub next_index: u32,
+    pub current_root_index: u32,
+    pub roots: Vec<BytesN<32>>,
+    pub filled_subtrees: Vec<BytesN<32>>,
+}
+
+/// Membership path for a single leaf against the current root
+/// `siblings[i]` is the sibling node at level `i`; bit `i` of `leaf_index` tells
+/// whether the running hash is the right (1) or left (0) child at that level
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct MerklePath {
+    pub leaf: BytesN<32>,
+    pub leaf_index: u32,
+    pub siblings: Vec<BytesN<32>>,
+    pub root: BytesN<32>,
+}
+
+impl MerkleTree {
+    /// Create an empty tree whose only known root is the all-zero tree root
+    pub fn new(env: &Env) -> Self {
+        let mut roots = Vec::new(env);
+        roots.push_back(Self::zero_root(env));
+
+        let mut filled_subtrees = Vec::new(env);
+        for level in 0..MERKLE_TREE_DEPTH {
+            filled_subtrees.push_back(Self::zero_hash(env, level));
+        }
+
+        MerkleTree {
+            next_index: 0,
+            current_root_index: 0,
+            roots,
+            filled_subtrees,
+        }
+    }
+
+    /// Rebuild a tree persisted before `filled_subtrees` was tracked
+    /// At each level the filled subtree is the stored left node on the newest leaf's path
+    pub fn from_stored_nodes(
+        env: &Env,
+        tree_id: u32,
+        next_index: u32,
+        current_root_index: u32,
+        roots: Vec<BytesN<32>>,
+    ) -> Self {
+        let mut filled_subtrees = Vec::new(env);
+        for level in 0..MERKLE_TREE_DEPTH {
+            let filled = match next_index.checked_sub(1) {
+                Some(last_index) => Self::get_node(env, tree_id, level, (last_index >> level) & !1),
+                None => None,
+            };
+            filled_subtrees.push_back(filled.unwrap_or_else(|| Self::zero_hash(env, level)));
+        }
+
+        MerkleTree {
+            next_index,
+            current_root_index,
+            roots,
+            filled_subtrees,
+        }
+    }
+
+    /// Current root of the tree
+    pub fn root(&self) -> BytesN<32> {
+        self.roots.get(self.current_root_index).unwrap()
+    }
+
+    /// Check whether `root` is the current root or one of the recent historical roots
+    pub fn is_known_root(&self, root: &BytesN<32>) -> bool {
+        self.roots.iter().any(|known| known == *root)
+    }
+
+    /// Historical roots, most recent first
+    pub fn root_history(&self, env: &Env) -> Vec<BytesN<32>> {
+        let mut history = Vec::new(env);
+        let len = self.roots.len();
+        for offset in 0..len {
+            let index = (self.current_root_index + len - offset) % len;
+            history.push_back(self.roots.get(index).unwrap());
+        }
+        history
+    }
+
+    /// Append a leaf, persist every node on its path and record the new root
+    /// Siblings come from `filled_subtrees` and the zero hashes, so no stored node is read
+    /// Returns the index assigned to the leaf
+    pub fn insert(&mut self, env: &Env, tree_id: u32, leaf: &BytesN<32>) -> Result<u32, BatcherError> {
+        if self.next_index >= (1u32 << MERKLE_TREE_DEPTH) {
+            return Err(BatcherError::MerkleTreeFull);
+        }
+
+        let leaf_index = self.next_index;
+        let mut index = leaf_index;
+        let mut current = leaf.clone();
+        for level in 0..MERKLE_TREE_DEPTH {
+            Self::set_node(env, tree_id, level, index, &current);
+
+            current = if index.is_multiple_of(2) {
+                self.filled_subtrees.set(level, current.clone());
+                Self::hash_left_right(env, &current, &Self::zero_hash(env, level))
+            } else {
+                let left = self.filled_subtrees.get(level).unwrap();
+                Self::hash_left_right(env, &left, &current)
+            };
+            index /= 2;
+        }
+
+        // Record new root in the ring buffer
+        let next_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE;
+        if next_root_index < self.roots.len() {
+            self.roots.set(next_root_index, current);
+        } else {
+            self.roots.push_back(current);
+        }
+        self.current_root_index = next_root_index;
+        self.next_index = leaf_index + 1;
+
+        Ok(leaf_index)
+    }
+
+    /// Build the membership path of an inserted leaf against the current root
+    pub fn get_path(&self, env: &Env, tree_id: u32, leaf_index: u32) -> Result<MerklePath, BatcherError> {
+        if leaf_index >= self.next_index {
+            return Err(BatcherError::InvalidInput);
+        }
+
+        let leaf = Self::get_node(env, tree_id, 0, leaf_index).ok_or(BatcherError::InternalError)?;
+
+        let mut siblings = Vec::new(env);
+        let mut index = leaf_index;
+        for level in 0..MERKLE_TREE_DEPTH {
+            let sibling = Self::get_node(env, tree_id, level, index ^ 1)
+                .unwrap_or_else(|| Self::zero_hash(env, level));
+            siblings.push_back(sibling);
+            index /= 2;
+        }
+
+        Ok(MerklePath {
+            leaf,
+            leaf_index,
+            siblings,
+            root: self.root(),
+        })
+    }
+
+    /// Recompute the root implied by a membership path
+    pub fn compute_root(env: &Env, leaf: &BytesN<32>, leaf_index: u32, siblings: &Vec<BytesN<32>>) -> BytesN<32> {
+        let mut current = leaf.clone();
+        let mut index = leaf_index;
+        for sibling in siblings.iter() {
+            current = if index.is_multiple_of(2) {
+                Self::hash_left_right(env, &current, &sibling)
+            } else {
+                Self::hash_left_right(env, &sibling, &current)
+            };
+            index /= 2;
+        }
+        current
+    }
+
+    /// Compute the root of a standalone tree holding `leaves` (zero-padded to full depth),
+    /// together with the sibling path of `leaf_index`
+    /// Used for per-batch settlement trees that are small enough to rebuild in memory
+    pub fn root_and_path(
+        env: &Env,
+        leaves: &Vec<BytesN<32>>,
+        leaf_index: u32,
+    ) -> Result<(BytesN<32>, Vec<BytesN<32>>), BatcherError> {
+        if leaves.is_empty() || leaf_index >= leaves.len() {
+            return Err(BatcherError::InvalidInput);
+        }
+
+        let mut level_nodes = leaves.clone();
+        let mut siblings = Vec::new(env);
+        let mut index = leaf_index;
+
+        for level in 0..MERKLE_TREE_DEPTH {
+            let zero = Self::zero_hash(env, level);
+            siblings.push_back(level_nodes.get(index ^ 1).unwrap_or_else(|| zero.clone()));
+
+            let mut parents = Vec::new(env);
+            let mut i = 0;
+            while i < level_nodes.len() {
+                let left = level_nodes.get(i).unwrap();
+                let right = level_nodes.get(i + 1).unwrap_or_else(|| zero.clone());
+                parents.push_back(Self::hash_left_right(env, &left, &right));
+                i += 2;
+            }
+            level_nodes = parents;
+            index /= 2;
+        }
+
+        Ok((level_nodes.get(0).unwrap(), siblings))
+    }
+
+    /// Hash two child nodes into their parent: sha256(left || right)
+    pub fn hash_left_right(env: &Env, left: &BytesN<32>, right: &BytesN<32>) -> BytesN<32> {
+        let mut preimage = Bytes::from(left);
+        preimage.append(&Bytes::from(right));
+        env.crypto().sha256(&preimage).into()
+    }
+
+    /// Hash of an empty subtree at `level`
+    fn zero_hash(env: &Env, level: u32) -> BytesN<32> {
+        BytesN::from_array(env, &ZERO_HASHES[level as usize])
+    }
+
+    /// Root of a tree with no leaves
+    fn zero_root(env: &Env) -> BytesN<32> {
+        Self::zero_hash(env, MERKLE_TREE_DEPTH)
+    }
+
+    fn get_node(env: &Env, tree_id: u32, level: u32, index: u32) -> Option<BytesN<32>> {
+        let key = DataKey::MerkleNode(tree_id, level, index);
+        let node = env.storage().persistent().get(&key)?;
+        env.storage().persistent().extend_ttl(&key, NODE_TTL_LEDGERS, NODE_TTL_LEDGERS);
+        Some(node)
+    }
+
+    fn set_node(env: &Env, tree_id: u32, level: u32, index: u32, node: &BytesN<32>) {
+        let key = DataKey::MerkleNode(tree_id, level, index);
+        env.storage().persistent().set(&key, node);
+        env.storage().persistent().extend_ttl(&key, NODE_TTL_LEDGERS, NODE_TTL_LEDGERS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SoroSwapBatcher;
+
+    fn leaf(env: &Env, seed: u8) -> BytesN<32> {
+        BytesN::from_array(env, &[seed; 32])
+    }
+
+    #[test]
+    fn test_zero_hashes_chain_from_zero_leaf() {
+        let env = Env::default();
+        let mut current = BytesN::from_array(&env, &[0u8; 32]);
+        for level in 0..=MERKLE_TREE_DEPTH {
+            assert_eq!(MerkleTree::zero_hash(&env, level), current);
+            current = MerkleTree::hash_left_right(&env, &current, &current);
+        }
+    }
+
+    #[test]
+    fn test_empty_tree_root_matches_zero_root() {
+        let env = Env::default();
+        let tree = MerkleTree::new(&env);
+        assert_eq!(tree.root(), MerkleTree::zero_root(&env));
+        assert_eq!(tree.next_index, 0);
+    }
+
+    #[test]
+    fn test_paths_verify_against_current_root() {
+        let env = Env::default();
+        let contract_id = env.register(SoroSwapBatcher, ());
+        let tree_id = 0u32;
+
+        env.as_contract(&contract_id, || {
+            let mut tree = MerkleTree::new(&env);
+            for seed in 1..=5u8 {
+                let index = tree.insert(&env, tree_id, &leaf(&env, seed)).unwrap();
+                assert_eq!(index, (seed - 1) as u32);
+            }
+
+            for index in 0..5u32 {
+                let path = tree.get_path(&env, tree_id, index).unwrap();
+                assert_eq!(path.leaf, leaf(&env, index as u8 + 1));
+                assert_eq!(path.siblings.len(), MERKLE_TREE_DEPTH);
+                assert_eq!(MerkleTree::compute_root(&env, &path.leaf, index, &path.siblings), tree.root());
+            }
+
+            assert!(tree.get_path(&env, tree_id, 5).is_err());
+        });
+    }
+
+    #[test]
+    fn test_tree_rebuilt_from_stored_nodes_continues_inserting() {
+        let env = Env::default();
+        let contract_id = env.register(SoroSwapBatcher, ());
+        let tree_id = 0u32;
+
+        env.as_contract(&contract_id, || {
+            let mut tree = MerkleTree::new(&env);
+            for seed in 1..=6u8 {
+                tree.insert(&env, tree_id, &leaf(&env, seed)).unwrap();
+
+                let mut rebuilt = MerkleTree::from_stored_nodes(
+                    &env,
+                    tree_id,
+                    tree.next_index,
+                    tree.current_root_index,
+                    tree.roots.clone(),
+                );
+                assert_eq!(rebuilt.filled_subtrees, tree.filled_subtrees);
+
+                let mut next = tree.clone();
+                rebuilt.insert(&env, tree_id + 1, &leaf(&env, 100)).unwrap();
+                next.insert(&env, tree_id + 2, &leaf(&env, 100)).unwrap();
+                assert_eq!(rebuilt.root(), next.root());
+            }
+        });
+    }
+
+    #[test]
+    fn test_root_and_path_matches_incremental_tree() {
+        let env = Env::default();
+        let contract_id = env.register(SoroSwapBatcher, ());
+        let tree_id = 0u32;
+
+        env.as_contract(&contract_id, || {
+            let mut tree = MerkleTree::new(&env);
+            let mut leaves = Vec::new(&env);
+            for seed in 1..=3u8 {
+                tree.insert(&env, tree_id, &leaf(&env, seed)).unwrap();
+                leaves.push_back(leaf(&env, seed));
+            }
+
+            for index in 0..3u32 {
+                let (root, siblings) = MerkleTree::root_and_path(&env, &leaves, index).unwrap();
+                assert_eq!(root, tree.root());
+                assert_eq!(siblings, tree.get_path(&env, tree_id, index).unwrap().siblings);
+            }
+
+            assert!(MerkleTree::root_and_path(&env, &leaves, 3).is_err());
+        });
+    }
+
+    #[test]
+    fn test_root_history_is_bounded_ring() {
+        let env = Env::default();
+        let contract_id = env.register(SoroSwapBatcher, ());
+        let tree_id = 0u32;
+
+        env.as_contract(&contract_id, || {
+            let mut tree = MerkleTree::new(&env);
+            let empty_root = tree.root();
+
+            tree.insert(&env, tree_id, &leaf(&env, 1)).unwrap();
+            let first_root = tree.root();
+            assert!(tree.is_known_root(&empty_root));
+
+            for seed in 2..=(ROOT_HISTORY_SIZE as u8 + 1) {
+                tree.insert(&env, tree_id, &leaf(&env, seed)).unwrap();
+            }
+
+            assert_eq!(tree.roots.len(), ROOT_HISTORY_SIZE);
+            assert!(!tree.is_known_root(&empty_root));
+            assert!(!tree.is_known_root(&first_root));
+            assert_eq!(tree.root_history(&env).get(0).unwrap(), tree.root());
+        });
+    }
+}
@@ -6,16 +6,19 @@
 // - Gas Optimization: Multiple operations in single transaction reduce overhead
 // - Atomic Execution: All calls succeed or fail together, maintaining consistency
 // - Composability: Enables complex DeFi strategies across multiple contracts
-// - Error Isolation: Enhanced error handling prevents cascading failures
-// - Predictable Gas Costs: Estimation capabilities for better UX
-// - MEV Protection: Batched execution reduces front-running opportunities
+// - Error Isolation: `multicall_safe` records failures instead of aborting the batch
+//
+// Soroban forbids contract re-entry, so calls cannot target this contract itself.
+// Calls run with this contract as the direct invoker and pass any `require_auth` on it,
+// so both entry points are admin-only and multicall starts disabled.
 
-use soroban_sdk::{Env, Address, Symbol, Val, Vec, IntoVal, BytesN};
+use soroban_sdk::{contracttype, Address, Env, InvokeError, Symbol, Val, Vec};
+use crate::{error::BatcherError, helpers, DataKey, MulticallCompletedEvent};
 
 /// Call data structure for contract invocation
 /// Based on Uniswap V3 multicall pattern adapted for Soroban
 #[derive(Clone, Debug)]
-#[soroban_sdk::contracttype]
+#[contracttype]
 pub struct CallData {
     pub contract_id: Address,
     pub function_name: Symbol,
@@ -24,11 +27,13 @@ pub struct CallData {
 
 /// Result from individual contract call
 #[derive(Clone, Debug)]
+#[contracttype]
 pub struct CallResult {
     pub success: bool,
-    pub result: Val,
-    pub error_message: Option<Symbol>,
-    pub gas_used: u64,
+    /// Return value of the call as a single element; empty when it failed
+    pub result: Vec<Val>,
+    /// Contract error code when the callee returned a `contracterror`
+    pub error_code: Option<u32>,
 }
 
 /// Enhanced multicall module for the batch processor
@@ -36,423 +41,103 @@ pub struct Multicall;
 
 impl Multicall {
     /// Initialize multicall functionality within the batch processor
-    pub fn init_multicall(
-        env: &Env,
-        enabled: bool,
-    ) -> Result<(), crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
+    pub fn init_multicall(env: &Env, enabled: bool) -> Result<(), BatcherError> {
+        if !helpers::is_initialized(env) {
+            return Err(BatcherError::NotInitialized);
         }
 
-        env.storage().instance().set(&crate::DataKey::MULTICALL_ENABLED, &enabled);
+        env.storage().instance().set(&DataKey::MulticallEnabled, &enabled);
         Ok(())
     }
 
     /// Execute multiple contract calls in a single transaction
-    /// Core Uniswap V3 multicall functionality
-    pub fn multicall(
-        env: &Env,
-        calls: Vec<CallData>,
-    ) -> Result<Vec<Val>, crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
-        }
-
-        let multicall_enabled: bool = env.storage().instance()
-            .get(&crate::DataKey::MULTICALL_ENABLED)
-            .unwrap_or(false);
-
-        if !multicall_enabled {
-            return Err(crate::error::BatcherError::InvalidInput);
-        }
-
-        if calls.is_empty() {
-            return Err(crate::error::BatcherError::InvalidInput);
-        }
+    /// Any failing call aborts the whole invocation, so either every call's effects
+    /// are applied or none are. Returns each call's return value in order.
+    pub fn multicall(env: &Env, calls: Vec<CallData>) -> Result<Vec<Val>, BatcherError> {
+        Self::validate_calls(env, &calls)?;
 
         let mut results = Vec::new(env);
-        let mut total_gas_used = 0u64;
-
         for call in calls.iter() {
-            let call_result = Self::execute_single_call(env, &call);
-            total_gas_used += call_result.gas_used;
-            results.push_back(call_result.result);
+            let result: Val = env.invoke_contract(&call.contract_id, &call.function_name, call.args);
+            results.push_back(result);
         }
 
-        // Emit completion event (simplified - all calls assumed successful)
-        let event = crate::MulticallCompletedEvent {
-            calls_count: calls.len() as u32,
-            success_count: calls.len() as u32, // All calls successful
-            total_gas_used,
-        };
-        event.publish(env);
-
+        Self::publish_completed(env, calls.len(), calls.len());
         Ok(results)
     }
 
-    /// Execute a single contract call with proper error handling
-    fn execute_single_call(
-        env: &Env,
-        call: &CallData,
-    ) -> CallResult {
-        // Record initial gas for estimation (simplified)
-        let initial_gas = env.ledger().sequence();
-
-        // Execute the contract call using Soroban's invoke_contract
-        let result = env.invoke_contract(
-            &call.contract_id,
-            &call.function_name,
-            call.args.clone()
-        );
-
-        // Calculate gas used (simplified approximation)
-        let final_gas = env.ledger().sequence();
-        let gas_used = (final_gas - initial_gas) as u64;
-
-        // For now, assume all calls succeed
-        // In production, we'd need more sophisticated error detection
-        CallResult {
-            success: true,
-            result,
-            error_message: None,
-            gas_used,
-        }
-    }
-
     /// Execute calls with error isolation - continues even if some calls fail
-    /// Enhanced version for production resilience
-    pub fn multicall_safe(
-        env: &Env,
-        calls: Vec<CallData>,
-    ) -> Vec<Val> {
-        let mut results = Vec::new(env);
+    /// A failed call's own state changes are rolled back by the host; successful calls
+    /// are kept. Returns a `CallResult` per call in order.
+    pub fn multicall_safe(env: &Env, calls: Vec<CallData>) -> Result<Vec<CallResult>, BatcherError> {
+        Self::validate_calls(env, &calls)?;
 
+        let mut results = Vec::new(env);
+        let mut success_count = 0u32;
         for call in calls.iter() {
-            let call_result = Self::execute_single_call(env, &call);
-            results.push_back(call_result.result);
+            let call_result = Self::try_single_call(env, &call);
+            if call_result.success {
+                success_count += 1;
+            }
+            results.push_back(call_result);
         }
 
-        results
-    }
-
-    /// Execute multicall and return aggregated results with gas usage
-    pub fn aggregate_multicall_results(
-        env: &Env,
-        calls: Vec<CallData>,
-    ) -> Result<(Vec<Val>, u64), crate::error::BatcherError> {
-        let call_results = Self::multicall(env, calls)?;
-        let total_gas = call_results.iter().map(|_| 0u64).sum(); // Simplified gas tracking
-
-        Ok((call_results, total_gas))
-    }
-
-    /// Execute multicall with continue-on-error behavior
-    pub fn try_multicall_continue(
-        env: &Env,
-        calls: Vec<CallData>,
-    ) -> Result<Vec<Val>, crate::error::BatcherError> {
-        // For now, same as multicall - could be enhanced with error recovery
-        Self::multicall(env, calls)
-    }
-
-    /// Estimate gas for multicall operations
-    /// Useful for frontend gas estimation
-    pub fn estimate_multicall_gas_cost(
-        _env: &Env,
-        call_count: u32,
-    ) -> u64 {
-        // Base costs for multicall execution using Soroban gas model
-        let base_cost = 12_000u64;
-        let per_call_cost = 6_000u64;
-        let stellar_overhead = 2_000u64;
-
-        // Simple calculation with safety margin
-        let total_cost = base_cost
-            + (call_count as u64 * per_call_cost)
-            + stellar_overhead;
-
-        // Cap at reasonable maximum for Stellar network
-        total_cost.min(1_000_000u64)
-    }
-
-    /// Get multicall statistics
-    pub fn get_multicall_stats(env: &Env) -> Result<(u64, u64), crate::error::BatcherError> {
-        if !crate::helpers::is_initialized(env) {
-            return Err(crate::error::BatcherError::NotInitialized);
-        }
-
-        // For now, return simplified stats
-        // In production, these would track actual multicall usage
-        Ok((0u64, 0u64))
-    }
-
-    /// Validate multicall configuration
-    pub fn validate_multicall_config(
-        enabled: bool,
-    ) -> Result<(), crate::error::BatcherError> {
-        // Simple validation for Phase 1
-        if enabled {
-            Ok(())
-        } else {
-            // Disabled multicall is valid
-            Ok(())
-        }
+        Self::publish_completed(env, calls.len(), success_count);
+        Ok(results)
     }
 
-    /// Create CallData for amount randomization
-    pub fn create_amount_randomization_call(
-        env: &Env,
-        contract_address: &Address,
-        amount: i128,
-        min_splits: u32,
-        max_splits: u32,
-        max_amount_per_split: i128,
-    ) -> CallData {
-        CallData {
-            contract_id: contract_address.clone(),
-            function_name: Symbol::new(env, "randomize_amount"),
-            args: Vec::from_array(env, [
-                amount.into_val(env),
-                min_splits.into_val(env),
-                max_splits.into_val(env),
-                max_amount_per_split.into_val(env),
-            ]),
-        }
+    /// Check whether multicall is enabled
+    pub fn is_enabled(env: &Env) -> bool {
+        env.storage().instance()
+            .get(&DataKey::MulticallEnabled)
+            .unwrap_or(false)
     }
 
-    /// Create CallData for time delay order submission
-    pub fn create_time_delay_call(
-        env: &Env,
-        contract_address: &Address,
-        token_in: &Address,
-        token_out: &Address,
-        amount_in: i128,
-        amount_out_min: i128,
-        pair_address: &Address,
-    ) -> CallData {
-        CallData {
-            contract_id: contract_address.clone(),
-            function_name: Symbol::new(env, "submit_delayed_order"),
-            args: Vec::from_array(env, [
-                token_in.into_val(env),
-                token_out.into_val(env),
-                amount_in.into_val(env),
-                amount_out_min.into_val(env),
-                pair_address.into_val(env),
-            ]),
-        }
-    }
+    // Internal helpers
 
-    /// Create CallData for batch execution
-    pub fn create_batch_execution_call(
-        env: &Env,
-        contract_address: &Address,
-        order_ids: Vec<u64>,
-        target_token: &Address,
-    ) -> CallData {
-        CallData {
-            contract_id: contract_address.clone(),
-            function_name: Symbol::new(env, "execute_batch"),
-            args: Vec::from_array(env, [
-                order_ids.into_val(env),
-                target_token.into_val(env),
-            ]),
+    fn validate_calls(env: &Env, calls: &Vec<CallData>) -> Result<(), BatcherError> {
+        if !helpers::is_initialized(env) {
+            return Err(BatcherError::NotInitialized);
         }
-    }
-
-    /// Create CallData for ephemeral order creation
-    pub fn create_ephemeral_order_call(
-        env: &Env,
-        contract_address: &Address,
-        token_in: &Address,
-        token_out: &Address,
-        amount: i128,
-        min_amount_out: i128,
-        privacy_level: u32,
-        expiry_block: u32,
-    ) -> CallData {
-        CallData {
-            contract_id: contract_address.clone(),
-            function_name: Symbol::new(env, "create_ephemeral_order"),
-            args: Vec::from_array(env, [
-                token_in.into_val(env),
-                token_out.into_val(env),
-                amount.into_val(env),
-                min_amount_out.into_val(env),
-                privacy_level.into_val(env),
-                expiry_block.into_val(env),
-            ]),
+        if !Self::is_enabled(env) || calls.is_empty() {
+            return Err(BatcherError::InvalidInput);
         }
-    }
-
-    // ==================== COINJOIN MULTICALL INTEGRATION ====================
 
-    /// Create CallData for CoinJoin private swap - single transaction privacy
-    pub fn create_coinjoin_private_swap_call(
-        env: &Env,
-        contract_address: &Address,
-        token_in: &Address,
-        token_out: &Address,
-        amount_in: i128,
-        min_amount_out: i128,
-        user_address: &Address,
-        receiving_address: &Address,
-    ) -> CallData {
-        CallData {
-            contract_id: contract_address.clone(),
-            function_name: Symbol::new(env, "private_swap"),
-            args: Vec::from_array(env, [
-                token_in.into_val(env),
-                token_out.into_val(env),
-                amount_in.into_val(env),
-                min_amount_out.into_val(env),
-                user_address.into_val(env),
-                receiving_address.into_val(env),
-            ]),
+        let this = env.current_contract_address();
+        if calls.iter().any(|call| call.contract_id == this) {
+            return Err(BatcherError::InvalidInput);
         }
-    }
-
-    /// Create CallData for CoinJoin mixing execution
-    pub fn create_coinjoin_mixing_call(
-        env: &Env,
-        contract_address: &Address,
-        denomination_symbol: Symbol,
-        max_deposits: Option<u32>,
-    ) -> CallData {
-        let args = if let Some(max) = max_deposits {
-            Vec::from_array(env, [
-                denomination_symbol.into_val(env),
-                max.into_val(env),
-            ])
-        } else {
-            Vec::from_array(env, [
-                denomination_symbol.into_val(env),
-            ])
-        };
 
-        CallData {
-            contract_id: contract_address.clone(),
-            function_name: Symbol::new(env, "execute_coinjoin_mixing"),
-            args,
-        }
+        Ok(())
     }
 
-    /// Create CallData for getting CoinJoin pool statistics
-    pub fn create_coinjoin_stats_call(
-        env: &Env,
-        contract_address: &Address,
-        denomination_symbol: Symbol,
-    ) -> CallData {
-        CallData {
-            contract_id: contract_address.clone(),
-            function_name: Symbol::new(env, "get_coinjoin_stats"),
-            args: Vec::from_array(env, [
-                denomination_symbol.into_val(env),
-            ]),
+    /// Execute a single contract call, capturing failure instead of aborting
+    fn try_single_call(env: &Env, call: &CallData) -> CallResult {
+        match env.try_invoke_contract::<Val, InvokeError>(&call.contract_id, &call.function_name, call.args.clone()) {
+            Ok(Ok(result)) => CallResult {
+                success: true,
+                result: Vec::from_array(env, [result]),
+                error_code: None,
+            },
+            Ok(Err(_)) | Err(Err(_)) | Err(Ok(InvokeError::Abort)) => CallResult {
+                success: false,
+                result: Vec::new(env),
+                error_code: None,
+            },
+            Err(Ok(InvokeError::Contract(code))) => CallResult {
+                success: false,
+                result: Vec::new(env),
+                error_code: Some(code),
+            },
         }
     }
 
-    /// Execute complete CoinJoin workflow in single multicall:
-    /// Deposit -> Mix -> (Optional Swap) -> Withdrawal
-    pub fn create_coinjoin_workflow_call(
-        env: &Env,
-        contract_address: &Address,
-        token_in: &Address,
-        token_out: &Address,
-        amount_in: i128,
-        min_amount_out: i128,
-        user_address: &Address,
-        receiving_address: &Address,
-        denomination_symbol: Symbol,
-        include_mixing: bool,
-    ) -> Vec<CallData> {
-        let mut calls = Vec::new(env);
-
-        // 1. Private swap (deposit to CoinJoin pool)
-        calls.push_back(Self::create_coinjoin_private_swap_call(
-            env,
-            contract_address,
-            token_in,
-            token_out,
-            amount_in,
-            min_amount_out,
-            user_address,
-            receiving_address,
-        ));
-
-        // 2. Optionally execute mixing if pool is ready
-        if include_mixing {
-            calls.push_back(Self::create_coinjoin_mixing_call(
-                env,
-                contract_address,
-                denomination_symbol,
-                None, // Use default max deposits
-            ));
+    fn publish_completed(env: &Env, call_count: u32, success_count: u32) {
+        MulticallCompletedEvent {
+            call_count,
+            success_count,
+            timestamp: env.ledger().timestamp(),
         }
-
-        calls
+        .publish(env);
     }
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use soroban_sdk::{Env, Address, Symbol};
-
-    #[test]
-    fn test_estimate_multicall_gas_cost() {
-        let env = Env::default();
-        let gas_estimate = Multicall::estimate_multicall_gas_cost(&env, 5);
-
-        // Should be: 12_000 + (5 * 6_000) + 2_000 = 44_000
-        assert_eq!(gas_estimate, 44000);
-    }
-
-    #[test]
-    fn test_validate_multicall_config() {
-        assert!(Multicall::validate_multicall_config(true).is_ok());
-        assert!(Multicall::validate_multicall_config(false).is_ok());
-    }
-
-    #[test]
-    fn test_create_amount_randomization_call() {
-        let env = Env::default();
-        let contract_address = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-
-        let call_data = Multicall::create_amount_randomization_call(
-            &env,
-            &contract_address,
-            1000000,
-            3,
-            7,
-            500000,
-        );
-
-        assert_eq!(call_data.function_name, Symbol::new(&env, "randomize_amount"));
-        assert_eq!(call_data.contract_id, contract_address);
-        assert_eq!(call_data.args.len(), 4);
-    }
-
-    #[test]
-    fn test_create_time_delay_call() {
-        let env = Env::default();
-        let contract_address = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-        let token_in = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-        let token_out = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-        let pair_address = Address::from_str(&env, "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF");
-
-        let call_data = Multicall::create_time_delay_call(
-            &env,
-            &contract_address,
-            &token_in,
-            &token_out,
-            1000000,
-            950000,
-            &pair_address,
-        );
-
-        assert_eq!(call_data.function_name, Symbol::new(&env, "submit_delayed_order"));
-        assert_eq!(call_data.contract_id, contract_address);
-        assert_eq!(call_data.args.len(), 5);
-    }
-}
\ No newline at end of file
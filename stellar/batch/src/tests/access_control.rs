@@ -0,0 +1,114 @@
+//! Two-step ownership transfer and the roles that gate each admin function.
+
+use soroban_sdk::{
+    symbol_short,
+    testutils::{Address as _, MockAuth, MockAuthInvoke},
+    vec, Address, Env, IntoVal,
+};
+
+use crate::{BatcherError, Role, SoroSwapBatcher, SoroSwapBatcherClient};
+
+struct Setup {
+    env: Env,
+    client: SoroSwapBatcherClient<'static>,
+    owner: Address,
+}
+
+fn setup() -> Setup {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let contract_id = env.register(SoroSwapBatcher, ());
+    let client = SoroSwapBatcherClient::new(&env, &contract_id);
+    client.initialize(&owner, &Address::generate(&env), &Address::generate(&env));
+
+    Setup { env, client, owner }
+}
+
+/// Address that authorized the most recent invocation
+fn authorizer(env: &Env) -> Address {
+    env.auths()[0].0.clone()
+}
+
+#[test]
+fn roles_default_to_owner_until_granted() {
+    let s = setup();
+    for role in [Role::Admin, Role::Keeper, Role::FeeManager, Role::Pauser] {
+        assert_eq!(s.client.get_role(&role), s.owner);
+    }
+
+    let admin = Address::generate(&s.env);
+    let fee_manager = Address::generate(&s.env);
+    let pauser = Address::generate(&s.env);
+    s.client.set_role(&Role::Admin, &admin);
+    assert_eq!(authorizer(&s.env), s.owner);
+    s.client.set_role(&Role::FeeManager, &fee_manager);
+    s.client.set_role(&Role::Pauser, &pauser);
+    assert_eq!(s.client.get_role(&Role::Keeper), s.owner);
+
+    s.client.set_verifier(&Address::generate(&s.env));
+    assert_eq!(authorizer(&s.env), admin);
+    let pool = s.client.create_pool(&Address::generate(&s.env), &Address::generate(&s.env), &symbol_short!("10"));
+    assert_eq!(authorizer(&s.env), admin);
+    s.client.set_pool_size_limits(&pool, &2, &5);
+    assert_eq!(authorizer(&s.env), admin);
+
+    s.client.set_pool_fee(&pool, &20);
+    assert_eq!(authorizer(&s.env), fee_manager);
+    s.client.set_fee_recipient(&Address::generate(&s.env));
+    assert_eq!(authorizer(&s.env), fee_manager);
+
+    s.client.pause();
+    assert_eq!(authorizer(&s.env), pauser);
+    s.client.unpause();
+    assert_eq!(authorizer(&s.env), pauser);
+}
+
+#[test]
+fn ownership_moves_only_when_accepted() {
+    let s = setup();
+    let successor = Address::generate(&s.env);
+    assert_eq!(s.client.try_accept_owner(), Err(Ok(BatcherError::Unauthorized)));
+
+    s.client.propose_owner(&successor);
+    assert_eq!(authorizer(&s.env), s.owner);
+    assert_eq!(s.client.get_pending_owner(), Some(successor.clone()));
+    assert_eq!(s.client.get_owner(), s.owner);
+
+    s.client.accept_owner();
+    assert_eq!(authorizer(&s.env), successor);
+    assert_eq!(s.client.get_owner(), successor);
+    assert_eq!(s.client.get_pending_owner(), None);
+
+    // Roles that were never granted follow the new owner
+    assert_eq!(s.client.get_role(&Role::Admin), successor);
+    s.client.set_role(&Role::Keeper, &Address::generate(&s.env));
+    assert_eq!(authorizer(&s.env), successor);
+}
+
+#[test]
+fn only_the_keeper_executes_batches() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+    s.client.set_role(&Role::Keeper, &keeper);
+
+    let order_ids = vec![&s.env, 0u64];
+    let execute_as = |signer: &Address| {
+        s.env.mock_auths(&[MockAuth {
+            address: signer,
+            invoke: &MockAuthInvoke {
+                contract: &s.client.address,
+                fn_name: "execute_batch",
+                args: (order_ids.clone(),).into_val(&s.env),
+                sub_invokes: &[],
+            },
+        }]);
+        s.client.try_execute_batch(&order_ids)
+    };
+
+    // The owner no longer holds the role once it is granted; a missing auth is a host error
+    assert!(matches!(execute_as(&s.owner), Err(Err(_))));
+    // The keeper gets past the role check and fails on the unknown order instead
+    assert!(matches!(execute_as(&keeper), Err(Ok(_))));
+}
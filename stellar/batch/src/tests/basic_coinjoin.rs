@@ -1,7 +1,7 @@
 //! Basic contract tests focused on initialization and CoinJoin wiring.
 
 use soroban_sdk::{testutils::Address as _, Address, Env, Symbol};
-use crate::{BatcherError, SoroSwapBatcher};
+use crate::{coinjoin::WAIT_TIME_UNKNOWN, BatcherError, SoroSwapBatcher};
 
 #[test]
 fn initialize_sets_core_state_and_enables_coinjoin() {
@@ -53,25 +53,46 @@ fn double_initialize_fails() {
 }
 
 #[test]
-fn coinjoin_stats_available_post_init() {
+fn coinjoin_stats_available_after_pool_creation() {
     let env = Env::default();
+    env.mock_all_auths();
     let contract_id = env.register(SoroSwapBatcher, ());
 
     let owner = Address::generate(&env);
     let factory = Address::generate(&env);
     let router = Address::generate(&env);
+    let token_in = Address::generate(&env);
+    let token_out = Address::generate(&env);
+
+    let denom = Symbol::new(&env, "10");
+    let create_pool = || {
+        env.as_contract(&contract_id, || {
+            SoroSwapBatcher::create_pool(env.clone(), token_in.clone(), token_out.clone(), denom.clone())
+        })
+    };
 
     env.as_contract(&contract_id, || {
         SoroSwapBatcher::initialize(env.clone(), owner, factory, router).unwrap();
 
+        // Pools exist per token pair, created by an admin
+        assert!(SoroSwapBatcher::get_coinjoin_stats(env.clone(), token_in.clone(), token_out.clone(), denom.clone()).is_err());
+    });
+    assert_eq!(create_pool(), Ok(0));
+    assert_eq!(create_pool(), Err(BatcherError::InvalidInput));
+
+    env.as_contract(&contract_id, || {
+        assert_eq!(SoroSwapBatcher::get_pool_count(env.clone()), 1);
+
         let (pool_size, fee_bps, wait_time) = SoroSwapBatcher::get_coinjoin_stats(
             env.clone(),
-            Symbol::new(&env, "10"),
+            token_in.clone(),
+            token_out.clone(),
+            denom.clone(),
         )
         .unwrap();
 
         assert_eq!(pool_size, 0);
-        assert_eq!(fee_bps, 10); // default fee basis points from CoinJoin init
-        assert_eq!(wait_time, 15); // minimum_pool_size(3) * 5 blocks wait
+        assert_eq!(fee_bps, 10); // default fee basis points for new pools
+        assert_eq!(wait_time, WAIT_TIME_UNKNOWN); // no arrival history yet
     });
 }
@@ -0,0 +1,267 @@
+//! CowSwap-style batch executor tests: persisted orders, uniform clearing price
+//! and settlement through the mock pair.
+
+use soroban_sdk::{
+    testutils::{Address as _, Ledger},
+    token::{Client as TokenClient, StellarAssetClient},
+    vec, Address, Env, Vec,
+};
+
+use super::coinjoin_e2e::{MockFactory, MockPair};
+use crate::{
+    batch_executor::{OrderStatus, PRICE_SCALE},
+    helpers, BatcherError, SoroSwapBatcher,
+};
+
+const RESERVE: i128 = 1_000_000_000_000;
+const EXPIRY: u64 = 3_600;
+
+struct Setup {
+    env: Env,
+    contract_id: Address,
+    pair: Address,
+    token_in: Address,
+    token_out: Address,
+}
+
+fn setup() -> Setup {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let token_in = env.register_stellar_asset_contract_v2(admin.clone()).address();
+    let token_out = env.register_stellar_asset_contract_v2(admin).address();
+
+    let pair = env.register(MockPair, ());
+    env.as_contract(&pair, || {
+        MockPair::__init(env.clone(), token_in.clone(), token_out.clone(), RESERVE, RESERVE);
+    });
+    StellarAssetClient::new(&env, &token_in).mint(&pair, &RESERVE);
+    StellarAssetClient::new(&env, &token_out).mint(&pair, &RESERVE);
+
+    let factory = env.register(MockFactory, ());
+    env.as_contract(&factory, || {
+        MockFactory::__init(env.clone(), pair.clone());
+    });
+
+    let contract_id = env.register(SoroSwapBatcher, ());
+    env.as_contract(&contract_id, || {
+        SoroSwapBatcher::initialize(env.clone(), Address::generate(&env), factory, Address::generate(&env)).unwrap();
+    });
+
+    Setup { env, contract_id, pair, token_in, token_out }
+}
+
+fn submit(s: &Setup, user: &Address, amount_in: i128, min_out: i128) -> Result<u64, BatcherError> {
+    submit_pair(s, user, &s.token_in, &s.token_out, amount_in, min_out)
+}
+
+/// Submit an order selling `token_out` for `token_in` (the opposite direction).
+fn submit_reverse(s: &Setup, user: &Address, amount_in: i128, min_out: i128) -> Result<u64, BatcherError> {
+    submit_pair(s, user, &s.token_out, &s.token_in, amount_in, min_out)
+}
+
+fn submit_pair(
+    s: &Setup,
+    user: &Address,
+    token_in: &Address,
+    token_out: &Address,
+    amount_in: i128,
+    min_out: i128,
+) -> Result<u64, BatcherError> {
+    StellarAssetClient::new(&s.env, token_in).mint(user, &amount_in);
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::submit_order_to_batch(
+            s.env.clone(),
+            user.clone(),
+            token_in.clone(),
+            token_out.clone(),
+            amount_in,
+            min_out,
+            EXPIRY,
+        )
+    })
+}
+
+fn execute(s: &Setup, order_ids: Vec<u64>) -> Result<(u32, i128, Vec<u64>), BatcherError> {
+    s.env.as_contract(&s.contract_id, || SoroSwapBatcher::execute_batch(s.env.clone(), order_ids))
+}
+
+fn order_status(s: &Setup, order_id: u64) -> OrderStatus {
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_order(s.env.clone(), order_id).unwrap().status
+    })
+}
+
+#[test]
+fn orders_settle_at_uniform_clearing_price() {
+    let s = setup();
+    let users = [Address::generate(&s.env), Address::generate(&s.env), Address::generate(&s.env)];
+    let amounts = [10_000_000i128, 30_000_000, 60_000_000];
+
+    let mut order_ids = Vec::new(&s.env);
+    for (user, amount) in users.iter().zip(amounts) {
+        order_ids.push_back(submit(&s, user, amount, amount * 9 / 10).unwrap());
+    }
+
+    let token_in = TokenClient::new(&s.env, &s.token_in);
+    assert_eq!(token_in.balance(&s.contract_id), 100_000_000);
+
+    let (count, volume, executed) = execute(&s, order_ids.clone()).unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(volume, 100_000_000);
+    assert_eq!(executed, order_ids);
+    assert_eq!(token_in.balance(&s.contract_id), 0);
+
+    let total_out = helpers::get_amount_out(100_000_000, RESERVE, RESERVE);
+    let token_out = TokenClient::new(&s.env, &s.token_out);
+    for ((user, amount), order_id) in users.iter().zip(amounts).zip(order_ids.iter()) {
+        let expected = amount * total_out / 100_000_000;
+        assert_eq!(token_out.balance(user), expected);
+
+        let order = s.env.as_contract(&s.contract_id, || SoroSwapBatcher::get_order(s.env.clone(), order_id).unwrap());
+        assert_eq!(order.status, OrderStatus::Executed);
+        assert_eq!(order.amount_out, expected);
+    }
+
+    s.env.as_contract(&s.contract_id, || {
+        let (enabled, _, processed, price) = SoroSwapBatcher::get_batch_statistics(s.env.clone()).unwrap();
+        assert!(enabled);
+        assert_eq!(processed, 1);
+        assert_eq!(price, Some(total_out * PRICE_SCALE / 100_000_000));
+    });
+}
+
+#[test]
+fn order_with_unmet_limit_stays_pending_and_can_be_cancelled() {
+    let s = setup();
+    let patient = Address::generate(&s.env);
+    let greedy = Address::generate(&s.env);
+
+    let ok_id = submit(&s, &patient, 10_000_000, 9_000_000).unwrap();
+    let greedy_id = submit(&s, &greedy, 10_000_000, 10_000_000).unwrap();
+
+    let (count, volume, executed) = execute(&s, vec![&s.env, ok_id, greedy_id]).unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(volume, 10_000_000);
+    assert_eq!(executed, vec![&s.env, ok_id]);
+    assert_eq!(order_status(&s, greedy_id), OrderStatus::Pending);
+
+    let refunded = s.env.as_contract(&s.contract_id, || SoroSwapBatcher::cancel_order(s.env.clone(), greedy_id)).unwrap();
+    assert_eq!(refunded, 10_000_000);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&greedy), 10_000_000);
+    assert_eq!(order_status(&s, greedy_id), OrderStatus::Cancelled);
+
+    for order_id in [ok_id, greedy_id] {
+        let err = s.env.as_contract(&s.contract_id, || SoroSwapBatcher::cancel_order(s.env.clone(), order_id))
+            .expect_err("settled orders cannot be cancelled");
+        assert_eq!(err, BatcherError::OrderNotPending);
+    }
+}
+
+#[test]
+fn expired_and_unknown_orders_are_not_executed() {
+    let s = setup();
+    let order_id = submit(&s, &Address::generate(&s.env), 10_000_000, 9_000_000).unwrap();
+
+    assert_eq!(execute(&s, vec![&s.env, 99]), Err(BatcherError::OrderNotFound));
+
+    s.env.ledger().with_mut(|li| li.timestamp = EXPIRY);
+    assert_eq!(execute(&s, vec![&s.env, order_id]), Err(BatcherError::NoExecutableOrders));
+    assert_eq!(order_status(&s, order_id), OrderStatus::Pending);
+
+    let err = submit(&s, &Address::generate(&s.env), 10_000_000, 9_000_000).expect_err("expired on submission");
+    assert_eq!(err, BatcherError::OrderTooLate);
+}
+
+#[test]
+fn batch_rejects_mixed_pairs_and_disabled_executor() {
+    let s = setup();
+    let first = submit(&s, &Address::generate(&s.env), 10_000_000, 9_000_000).unwrap();
+
+    let other_token = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+    let second = submit_pair(&s, &Address::generate(&s.env), &s.token_in, &other_token, 10_000_000, 9_000_000).unwrap();
+
+    assert_eq!(execute(&s, vec![&s.env, first, second]), Err(BatcherError::InvalidInput));
+
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::configure_batch_executor(s.env.clone(), false, 10).unwrap();
+    });
+    assert_eq!(execute(&s, vec![&s.env, first]), Err(BatcherError::InvalidInput));
+    assert_eq!(submit(&s, &Address::generate(&s.env), 10_000_000, 9_000_000), Err(BatcherError::InvalidInput));
+}
+
+#[test]
+fn balanced_opposite_orders_net_without_touching_the_pair() {
+    let s = setup();
+    let seller = Address::generate(&s.env);
+    let buyer = Address::generate(&s.env);
+
+    let sell_id = submit(&s, &seller, 10_000_000, 10_000_000).unwrap();
+    let buy_id = submit_reverse(&s, &buyer, 10_000_000, 10_000_000).unwrap();
+
+    let (count, volume, _) = execute(&s, vec![&s.env, sell_id, buy_id]).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(volume, 20_000_000);
+
+    // Both limits ask for more than the pair could pay after its 0.3% fee
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&seller), 10_000_000);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&buyer), 10_000_000);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&s.pair), RESERVE);
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&s.pair), RESERVE);
+
+    s.env.as_contract(&s.contract_id, || {
+        let (_, _, _, price) = SoroSwapBatcher::get_batch_statistics(s.env.clone()).unwrap();
+        assert_eq!(price, Some(PRICE_SCALE));
+    });
+}
+
+#[test]
+fn imbalanced_batch_routes_only_the_residual_at_one_price() {
+    let s = setup();
+    let sellers = [Address::generate(&s.env), Address::generate(&s.env)];
+    let buyer = Address::generate(&s.env);
+
+    let mut order_ids = Vec::new(&s.env);
+    order_ids.push_back(submit(&s, &sellers[0], 10_000_000, 9_000_000).unwrap());
+    order_ids.push_back(submit(&s, &sellers[1], 20_000_000, 18_000_000).unwrap());
+    order_ids.push_back(submit_reverse(&s, &buyer, 10_000_000, 9_000_000).unwrap());
+
+    let (count, _, _) = execute(&s, order_ids).unwrap();
+    assert_eq!(count, 3);
+
+    let token_in = TokenClient::new(&s.env, &s.token_in);
+    let token_out = TokenClient::new(&s.env, &s.token_out);
+
+    // Only the imbalance, not the gross 30M, reaches the pair
+    let routed = token_in.balance(&s.pair) - RESERVE;
+    assert!(routed > 0 && routed < 20_000_000);
+
+    let seller_out = token_out.balance(&sellers[0]) + token_out.balance(&sellers[1]);
+    assert!(seller_out > helpers::get_amount_out(30_000_000, RESERVE, RESERVE));
+    assert_eq!(token_out.balance(&sellers[1]), token_out.balance(&sellers[0]) * 2);
+
+    // Sellers and the buyer trade at the same clearing price
+    let price = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_batch_statistics(s.env.clone()).unwrap().3.unwrap()
+    });
+    assert_eq!(seller_out, 30_000_000 * price / PRICE_SCALE);
+    let buyer_out = token_in.balance(&buyer);
+    assert!((buyer_out - 10_000_000 * PRICE_SCALE / price).abs() <= 10);
+    assert_eq!(buyer_out, 30_000_000 - routed);
+}
+
+#[test]
+fn paused_contract_rejects_orders_but_allows_cancellation() {
+    let s = setup();
+    let user = Address::generate(&s.env);
+    let order_id = submit(&s, &user, 10_000_000, 9_000_000).unwrap();
+
+    s.env.as_contract(&s.contract_id, || SoroSwapBatcher::pause(s.env.clone()).unwrap());
+    assert_eq!(submit(&s, &user, 10_000_000, 9_000_000), Err(BatcherError::ContractPaused));
+    assert_eq!(execute(&s, vec![&s.env, order_id]), Err(BatcherError::ContractPaused));
+
+    let refunded = s.env.as_contract(&s.contract_id, || SoroSwapBatcher::cancel_order(s.env.clone(), order_id));
+    assert_eq!(refunded, Ok(10_000_000));
+    assert_eq!(order_status(&s, order_id), OrderStatus::Cancelled);
+}
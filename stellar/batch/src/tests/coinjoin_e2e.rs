@@ -3,7 +3,8 @@
 use soroban_sdk::{
     contract, contractimpl,
     testutils::Address as _,
-    Address, BytesN, Env, Symbol,
+    token::{Client as TokenClient, StellarAssetClient},
+    vec, Address, BytesN, Env, Symbol,
 };
 
 use crate::{
@@ -11,7 +12,8 @@ use crate::{
     DataKey, SoroSwapBatcher,
 };
 
-/// Minimal mock pair contract that returns static reserves/token addresses.
+/// Minimal mock pair contract that returns static reserves/token addresses
+/// and pays requested outputs from its own token balance.
 #[contract]
 pub struct MockPair;
 
@@ -24,8 +26,20 @@ impl MockPair {
         env.storage().instance().set(&Symbol::new(&env, "reserve_1"), &reserve_1);
     }
 
-    pub fn swap(_env: Env, _amount_0_out: i128, _amount_1_out: i128, _to: Address) {
-        // No-op for tests; just satisfies interface.
+    pub fn swap(env: Env, amount_0_out: i128, amount_1_out: i128, to: Address) {
+        let pair = env.current_contract_address();
+        let shortfall: i128 = env.storage().instance().get(&Symbol::new(&env, "shortfall")).unwrap_or(0);
+        if amount_0_out > 0 {
+            TokenClient::new(&env, &Self::token_0(env.clone())).transfer(&pair, &to, &(amount_0_out - shortfall));
+        }
+        if amount_1_out > 0 {
+            TokenClient::new(&env, &Self::token_1(env.clone())).transfer(&pair, &to, &(amount_1_out - shortfall));
+        }
+    }
+
+    /// Pay `shortfall` less than requested on every swap, like a fee-on-transfer token
+    pub fn set_shortfall(env: Env, shortfall: i128) {
+        env.storage().instance().set(&Symbol::new(&env, "shortfall"), &shortfall);
     }
 
     pub fn token_0(env: Env) -> Address {
@@ -43,7 +57,8 @@ impl MockPair {
     }
 }
 
-/// Minimal mock factory contract that always returns a preset pair address.
+/// Minimal mock factory contract that returns a preset pair address, unless a
+/// pair was registered for the specific tokens with `add_pair`.
 #[contract]
 pub struct MockFactory;
 
@@ -53,22 +68,31 @@ impl MockFactory {
         env.storage().instance().set(&Symbol::new(&env, "pair"), &pair);
     }
 
-    pub fn get_pair(env: Env, _token_a: Address, _token_b: Address) -> Address {
-        env.storage().instance().get(&Symbol::new(&env, "pair")).unwrap()
+    pub fn add_pair(env: Env, token_a: Address, token_b: Address, pair: Address) {
+        env.storage().instance().set(&(token_a.clone(), token_b.clone()), &pair);
+        env.storage().instance().set(&(token_b, token_a), &pair);
+    }
+
+    pub fn get_pair(env: Env, token_a: Address, token_b: Address) -> Address {
+        env.storage().instance()
+            .get(&(token_a, token_b))
+            .unwrap_or_else(|| env.storage().instance().get(&Symbol::new(&env, "pair")).unwrap())
     }
 }
 
 #[test]
 fn coinjoin_flow_mixes_three_participants() {
     let env = Env::default();
+    env.mock_all_auths();
 
-    // Register mock pair and factory.
-    let token_a = Address::generate(&env);
-    let token_b = Address::generate(&env);
+    // Register tokens, mock pair and factory.
+    let token_a = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let token_b = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
     let pair = env.register(MockPair, ());
     env.as_contract(&pair, || {
         MockPair::__init(env.clone(), token_a.clone(), token_b.clone(), 1_000_000_000, 1_000_000_000);
     });
+    StellarAssetClient::new(&env, &token_b).mint(&pair, &1_000_000_000);
 
     let factory = env.register(MockFactory, ());
     env.as_contract(&factory, || {
@@ -84,46 +108,46 @@ fn coinjoin_flow_mixes_three_participants() {
         SoroSwapBatcher::initialize(env.clone(), owner, factory.clone(), router).unwrap();
     });
 
-    // Prepare three deposits for the smallest denomination.
+    // Prepare three deposits for the smallest denomination; their input is already held by the contract.
     let denom = Denomination::Small;
-    let receivers: [Address; 3] = [
-        Address::generate(&env),
-        Address::generate(&env),
-        Address::generate(&env),
-    ];
     let senders: [Address; 3] = [
         Address::generate(&env),
         Address::generate(&env),
         Address::generate(&env),
     ];
+    StellarAssetClient::new(&env, &token_a).mint(&contract_id, &(3 * denom.value()));
 
-    env.as_contract(&contract_id, || {
-        for i in 0..3 {
-            let commitment = BytesN::from_array(&env, &[i as u8; 32]);
-            let nullifier = BytesN::from_array(&env, &[(i + 10) as u8; 32]);
+    let pool_id = env.as_contract(&contract_id, || {
+        let pool_id = CoinJoinMixer::create_pool(&env, token_a.clone(), token_b.clone(), denom).unwrap();
+        for (i, sender) in senders.iter().enumerate() {
+            let commitment = BytesN::from_array(&env, &[(i + 1) as u8; 32]);
             CoinJoinMixer::deposit(
                 &env,
-                denom,
+                pool_id,
                 commitment,
-                nullifier,
-                senders[i].clone(),
-                receivers[i].clone(),
+                sender.clone(),
                 50, // max slippage bps
-                token_a.clone(),
-                token_b.clone(),
-                denom.value(), // min_amount_out placeholder
+                vec![&env, token_a.clone(), token_b.clone()],
+                9_000_000, // min_amount_out
             )
             .unwrap();
         }
+        pool_id
+    });
 
-        // Execute mixing now that minimum participants are present.
-        let mix_result = CoinJoinMixer::execute_mixing(&env, denom, Some(3)).unwrap();
-        assert!(mix_result.success);
-        assert_eq!(mix_result.anonymity_set_size, 3);
+    // Execute the aggregated swap now that minimum participants are present.
+    let keeper = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        let batch_id = SoroSwapBatcher::execute_pool(env.clone(), pool_id, keeper.clone(), u64::MAX).unwrap();
+        assert_eq!(batch_id, 0);
 
-        // Pool size should now be zero for this denomination.
-        let pool_stats = CoinJoinMixer::get_pool_stats(&env, denom).unwrap();
+        // Pool size should now be zero, and the swapped output awaits withdrawal.
+        let pool_stats = CoinJoinMixer::get_pool_stats(&env, pool_id).unwrap();
         assert_eq!(pool_stats.current_pool_size, 0);
+        let batch = SoroSwapBatcher::get_settled_batch(env.clone(), pool_id, 0).unwrap();
+        assert_eq!(batch.remaining_claims, 3);
+        let received = TokenClient::new(&env, &token_b).balance(&contract_id);
+        assert_eq!(batch.payout_amount, received / 3);
     });
 
     // Ensure factory address persisted.
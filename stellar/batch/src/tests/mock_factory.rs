@@ -3,8 +3,6 @@
 
 use soroban_sdk::{contract, contractimpl, Address, Env, Map, Symbol, testutils::Address as _};
 
-pub struct SoroswapFactoryContract;
-
 #[contract]
 pub struct SoroswapFactory;
 
@@ -0,0 +1,74 @@
+//! Mock Soroswap router.
+//! Quotes and swaps along a path through the mock factory's pairs, enforcing
+//! `amount_out_min` and the deadline the way the real router does.
+
+use soroban_sdk::{contract, contractimpl, symbol_short, token::Client as TokenClient, Address, Env, Vec};
+
+use super::coinjoin_e2e::{MockFactoryClient, MockPairClient};
+use crate::helpers;
+
+#[contract]
+pub struct MockRouter;
+
+#[contractimpl]
+impl MockRouter {
+    pub fn __init(env: Env, factory: Address) {
+        env.storage().instance().set(&symbol_short!("factory"), &factory);
+    }
+
+    pub fn swap_exact_tokens_for_tokens(
+        env: Env,
+        amount_in: i128,
+        amount_out_min: i128,
+        path: Vec<Address>,
+        to: Address,
+        deadline: u64,
+    ) -> Vec<i128> {
+        to.require_auth();
+        if env.ledger().timestamp() >= deadline {
+            panic!("deadline expired");
+        }
+
+        let factory: Address = env.storage().instance().get(&symbol_short!("factory")).unwrap();
+        let factory = MockFactoryClient::new(&env, &factory);
+
+        let mut pairs = Vec::new(&env);
+        let mut amounts = Vec::from_array(&env, [amount_in]);
+        for i in 1..path.len() {
+            let (token_in, token_out) = (path.get(i - 1).unwrap(), path.get(i).unwrap());
+            let pair = MockPairClient::new(&env, &factory.get_pair(&token_in, &token_out));
+            let (reserve_0, reserve_1) = pair.get_reserves();
+            let (reserve_in, reserve_out) = if pair.token_0() == token_in {
+                (reserve_0, reserve_1)
+            } else {
+                (reserve_1, reserve_0)
+            };
+            amounts.push_back(helpers::get_amount_out(amounts.last().unwrap(), reserve_in, reserve_out));
+            pairs.push_back(pair.address);
+        }
+
+        if amounts.last().unwrap() < amount_out_min {
+            panic!("insufficient output amount");
+        }
+
+        TokenClient::new(&env, &path.get(0).unwrap()).transfer(&to, pairs.get(0).unwrap(), &amount_in);
+        for i in 0..pairs.len() {
+            let pair = MockPairClient::new(&env, &pairs.get(i).unwrap());
+            let amount_out = amounts.get(i + 1).unwrap();
+            let next = pairs.get(i + 1).unwrap_or(to.clone());
+            if pair.token_0() == path.get(i).unwrap() {
+                pair.swap(&0, &amount_out, &next);
+            } else {
+                pair.swap(&amount_out, &0, &next);
+            }
+        }
+
+        let swaps: u32 = env.storage().instance().get(&symbol_short!("swaps")).unwrap_or(0);
+        env.storage().instance().set(&symbol_short!("swaps"), &(swaps + 1));
+        amounts
+    }
+
+    pub fn swap_count(env: Env) -> u32 {
+        env.storage().instance().get(&symbol_short!("swaps")).unwrap_or(0)
+    }
+}
@@ -0,0 +1,38 @@
+//! Mock withdrawal proof verifier.
+//! Stands in for a ZK verifier: the "proof" carries the note preimage and Merkle path
+//! in the clear, and is checked against the same public inputs the circuit would use.
+
+use soroban_sdk::{contract, contractimpl, contracttype, xdr::FromXdr, Bytes, BytesN, Env, Vec};
+
+use crate::{coinjoin::CoinJoinMixer, merkle::MerkleTree};
+
+/// Transparent witness accepted by [`MockVerifier`]
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct MockProof {
+    pub nullifier: BytesN<32>,
+    pub secret: BytesN<32>,
+    pub leaf_index: u32,
+    pub siblings: Vec<BytesN<32>>,
+}
+
+#[contract]
+pub struct MockVerifier;
+
+#[contractimpl]
+impl MockVerifier {
+    /// Public inputs: [root, nullifier_hash, recipient_hash]
+    pub fn verify(env: Env, proof: Bytes, public_inputs: Vec<BytesN<32>>) -> bool {
+        let proof = match MockProof::from_xdr(&env, &proof) {
+            Ok(proof) => proof,
+            Err(_) => return false,
+        };
+        let (Some(root), Some(nullifier_hash)) = (public_inputs.get(0), public_inputs.get(1)) else {
+            return false;
+        };
+
+        let commitment = CoinJoinMixer::compute_commitment(&env, &proof.nullifier, &proof.secret);
+        CoinJoinMixer::compute_nullifier_hash(&env, &proof.nullifier) == nullifier_hash
+            && MerkleTree::compute_root(&env, &commitment, proof.leaf_index, &proof.siblings) == root
+    }
+}
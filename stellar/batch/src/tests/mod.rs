@@ -3,5 +3,13 @@
 
 pub mod basic_coinjoin;
 pub mod mock_factory;
+pub mod mock_router;
+pub mod mock_verifier;
 pub mod test_mock_factory;
 pub mod coinjoin_e2e;
+pub mod private_swap;
+pub mod batch_executor;
+pub mod multicall;
+pub mod pool_config;
+pub mod access_control;
+pub mod upgrade;
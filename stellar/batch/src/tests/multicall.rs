@@ -0,0 +1,182 @@
+//! Multicall tests: atomic execution with typed results and per-call failure capture.
+
+use soroban_sdk::{
+    contract, contracterror, contractimpl, panic_with_error, symbol_short,
+    testutils::{Address as _, MockAuth, MockAuthInvoke},
+    token::{Client as TokenClient, StellarAssetClient},
+    Address, Env, IntoVal, Symbol, TryFromVal, Val, Vec,
+};
+
+use crate::{
+    multicall::CallData, BatcherError, SoroSwapBatcher, SoroSwapBatcherClient,
+};
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TargetError {
+    Rejected = 7,
+}
+
+/// Target contract with a stateful call, a pure call and a failing call.
+#[contract]
+pub struct MockTarget;
+
+#[contractimpl]
+impl MockTarget {
+    pub fn bump(env: Env, by: u32) -> u32 {
+        let value = Self::value(env.clone()) + by;
+        env.storage().instance().set(&symbol_short!("value"), &value);
+        value
+    }
+
+    pub fn value(env: Env) -> u32 {
+        env.storage().instance().get(&symbol_short!("value")).unwrap_or(0)
+    }
+
+    pub fn reject(env: Env) -> u32 {
+        panic_with_error!(&env, TargetError::Rejected)
+    }
+}
+
+struct Setup {
+    env: Env,
+    client: SoroSwapBatcherClient<'static>,
+    target: Address,
+}
+
+fn setup() -> Setup {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(SoroSwapBatcher, ());
+    let client = SoroSwapBatcherClient::new(&env, &contract_id);
+    client.initialize(&Address::generate(&env), &Address::generate(&env), &Address::generate(&env));
+    assert!(!client.is_multicall_enabled());
+    client.set_multicall_enabled(&true);
+
+    let target = env.register(MockTarget, ());
+    Setup { env, client, target }
+}
+
+fn call(s: &Setup, function: &str, args: Vec<Val>) -> CallData {
+    CallData {
+        contract_id: s.target.clone(),
+        function_name: Symbol::new(&s.env, function),
+        args,
+    }
+}
+
+fn value(s: &Setup) -> u32 {
+    s.env.as_contract(&s.target, || MockTarget::value(s.env.clone()))
+}
+
+#[test]
+fn multicall_returns_every_result_in_order() {
+    let s = setup();
+    let calls = Vec::from_array(&s.env, [
+        call(&s, "bump", (2u32,).into_val(&s.env)),
+        call(&s, "bump", (3u32,).into_val(&s.env)),
+        call(&s, "value", Vec::new(&s.env)),
+    ]);
+
+    let results = s.client.multicall(&calls);
+    let values: [u32; 3] = core::array::from_fn(|i| u32::try_from_val(&s.env, &results.get(i as u32).unwrap()).unwrap());
+    assert_eq!(values, [2, 5, 5]);
+}
+
+#[test]
+fn multicall_reverts_every_call_when_one_fails() {
+    let s = setup();
+    let calls = Vec::from_array(&s.env, [
+        call(&s, "bump", (2u32,).into_val(&s.env)),
+        call(&s, "reject", Vec::new(&s.env)),
+    ]);
+
+    assert!(s.client.try_multicall(&calls).is_err());
+    assert_eq!(value(&s), 0);
+}
+
+#[test]
+fn multicall_safe_records_failures_and_keeps_successes() {
+    let s = setup();
+    let calls = Vec::from_array(&s.env, [
+        call(&s, "bump", (2u32,).into_val(&s.env)),
+        call(&s, "reject", Vec::new(&s.env)),
+        call(&s, "missing", Vec::new(&s.env)),
+        call(&s, "bump", (3u32,).into_val(&s.env)),
+    ]);
+
+    let results = s.client.multicall_safe(&calls);
+    assert_eq!(results.len(), 4);
+
+    let first = results.get(0).unwrap();
+    assert!(first.success);
+    assert_eq!(u32::try_from_val(&s.env, &first.result.get(0).unwrap()).unwrap(), 2);
+
+    let rejected = results.get(1).unwrap();
+    assert!(!rejected.success);
+    assert!(rejected.result.is_empty());
+    assert_eq!(rejected.error_code, Some(TargetError::Rejected as u32));
+
+    let missing = results.get(2).unwrap();
+    assert!(!missing.success);
+    assert_eq!(missing.error_code, None);
+
+    assert!(results.get(3).unwrap().success);
+    assert_eq!(value(&s), 5);
+}
+
+#[test]
+fn multicall_rejects_empty_self_and_disabled_calls() {
+    let s = setup();
+
+    assert_eq!(s.client.try_multicall(&Vec::new(&s.env)), Err(Ok(BatcherError::InvalidInput)));
+
+    let reentrant = Vec::from_array(&s.env, [CallData {
+        contract_id: s.client.address.clone(),
+        function_name: Symbol::new(&s.env, "get_owner"),
+        args: Vec::new(&s.env),
+    }]);
+    assert_eq!(s.client.try_multicall_safe(&reentrant), Err(Ok(BatcherError::InvalidInput)));
+
+    s.client.set_multicall_enabled(&false);
+    assert!(!s.client.is_multicall_enabled());
+    let calls = Vec::from_array(&s.env, [call(&s, "value", Vec::new(&s.env))]);
+    assert_eq!(s.client.try_multicall(&calls), Err(Ok(BatcherError::InvalidInput)));
+}
+
+#[test]
+fn multicall_cannot_move_the_batchers_tokens_without_admin_auth() {
+    let s = setup();
+    let batcher = s.client.address.clone();
+    let attacker = Address::generate(&s.env);
+    let token = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+    StellarAssetClient::new(&s.env, &token).mint(&batcher, &1_000);
+
+    // Only the attacker signs; the batcher is the invoker of the transfer it forwards
+    let calls = Vec::from_array(&s.env, [CallData {
+        contract_id: token.clone(),
+        function_name: Symbol::new(&s.env, "transfer"),
+        args: (batcher.clone(), attacker.clone(), 1_000i128).into_val(&s.env),
+    }]);
+    for fn_name in ["multicall", "multicall_safe"] {
+        s.env.mock_auths(&[MockAuth {
+            address: &attacker,
+            invoke: &MockAuthInvoke {
+                contract: &batcher,
+                fn_name,
+                args: (calls.clone(),).into_val(&s.env),
+                sub_invokes: &[],
+            },
+        }]);
+        if fn_name == "multicall" {
+            assert!(s.client.try_multicall(&calls).is_err());
+        } else {
+            assert!(s.client.try_multicall_safe(&calls).is_err());
+        }
+    }
+
+    let token_client = TokenClient::new(&s.env, &token);
+    assert_eq!(token_client.balance(&batcher), 1_000);
+    assert_eq!(token_client.balance(&attacker), 0);
+}
@@ -0,0 +1,249 @@
+//! Owner-governed pool configuration: validation, events and the effect of
+//! each setting on new deposits.
+
+use soroban_sdk::{
+    symbol_short,
+    testutils::{Address as _, Events, Ledger},
+    token::StellarAssetClient,
+    vec, Address, BytesN, Env, Symbol, TryFromVal,
+};
+
+use super::coinjoin_e2e::{MockFactory, MockPair};
+use crate::{
+    coinjoin::{ExecutionMode, WAIT_TIME_UNKNOWN},
+    BatcherError, SoroSwapBatcher,
+};
+
+const DENOM: i128 = 10_000_000;
+const RESERVE: i128 = 1_000_000_000_000;
+
+struct Setup {
+    env: Env,
+    contract_id: Address,
+    token_in: Address,
+    token_out: Address,
+    pool: u32,
+}
+
+fn setup() -> Setup {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let token_in = env.register_stellar_asset_contract_v2(admin.clone()).address();
+    let token_out = env.register_stellar_asset_contract_v2(admin).address();
+
+    let pair = env.register(MockPair, ());
+    env.as_contract(&pair, || {
+        MockPair::__init(env.clone(), token_in.clone(), token_out.clone(), RESERVE, RESERVE);
+    });
+    StellarAssetClient::new(&env, &token_out).mint(&pair, &RESERVE);
+
+    let factory = env.register(MockFactory, ());
+    env.as_contract(&factory, || {
+        MockFactory::__init(env.clone(), pair);
+    });
+
+    let contract_id = env.register(SoroSwapBatcher, ());
+    env.as_contract(&contract_id, || {
+        SoroSwapBatcher::initialize(env.clone(), Address::generate(&env), factory, Address::generate(&env)).unwrap();
+    });
+    let pool = env.as_contract(&contract_id, || {
+        SoroSwapBatcher::create_pool(env.clone(), token_in.clone(), token_out.clone(), symbol_short!("10")).unwrap()
+    });
+
+    Setup { env, contract_id, token_in, token_out, pool }
+}
+
+fn deposit(s: &Setup, seed: u8) -> Result<u64, BatcherError> {
+    let user = Address::generate(&s.env);
+    StellarAssetClient::new(&s.env, &s.token_in).mint(&user, &DENOM);
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::private_swap(
+            s.env.clone(),
+            vec![&s.env, s.token_in.clone(), s.token_out.clone()],
+            DENOM,
+            9_000_000,
+            100,
+            user.clone(),
+            BytesN::from_array(&s.env, &[seed; 32]),
+        )
+    })
+}
+
+fn config(s: &Setup) -> (u32, u32, u32, u64, u64) {
+    s.env.as_contract(&s.contract_id, || SoroSwapBatcher::get_pool_config(s.env.clone(), s.pool).unwrap())
+}
+
+/// Name of the most recent contract event
+fn last_event_name(env: &Env) -> Symbol {
+    let (_, topics, _) = env.events().all().last().unwrap();
+    Symbol::try_from_val(env, &topics.get(0).unwrap()).unwrap()
+}
+
+#[test]
+fn pool_settings_are_validated() {
+    let s = setup();
+    let call = |f: &dyn Fn(Env) -> Result<(), BatcherError>| s.env.as_contract(&s.contract_id, || f(s.env.clone()));
+
+    assert_eq!(config(&s), (3, 10, 10, 48 * 60 * 60, 0));
+
+    for (min, max) in [(1, 10), (5, 4), (3, 51)] {
+        let result = call(&|env| SoroSwapBatcher::set_pool_size_limits(env, s.pool, min, max));
+        assert_eq!(result, Err(BatcherError::InvalidConfig));
+    }
+    assert_eq!(call(&|env| SoroSwapBatcher::set_pool_fee(env, s.pool, 1_001)), Err(BatcherError::InvalidConfig));
+    for expiry in [59 * 60, 31 * 24 * 60 * 60] {
+        let result = call(&|env| SoroSwapBatcher::set_deposit_expiry(env, s.pool, expiry));
+        assert_eq!(result, Err(BatcherError::InvalidConfig));
+    }
+    for max_wait in [4 * 60, 31 * 24 * 60 * 60] {
+        let result = call(&|env| SoroSwapBatcher::set_max_wait(env, s.pool, max_wait));
+        assert_eq!(result, Err(BatcherError::InvalidConfig));
+    }
+    assert_eq!(call(&|env| SoroSwapBatcher::set_pool_fee(env, 99, 5)), Err(BatcherError::InvalidInput));
+    assert_eq!(config(&s), (3, 10, 10, 48 * 60 * 60, 0));
+
+    call(&|env| SoroSwapBatcher::set_pool_size_limits(env, s.pool, 2, 20)).unwrap();
+    assert_eq!(last_event_name(&s.env), Symbol::new(&s.env, "pool_config_updated_event"));
+    call(&|env| SoroSwapBatcher::set_pool_fee(env, s.pool, 25)).unwrap();
+    assert_eq!(last_event_name(&s.env), Symbol::new(&s.env, "pool_config_updated_event"));
+    call(&|env| SoroSwapBatcher::set_deposit_expiry(env, s.pool, 60 * 60)).unwrap();
+    assert_eq!(last_event_name(&s.env), Symbol::new(&s.env, "pool_config_updated_event"));
+    call(&|env| SoroSwapBatcher::set_execution_mode(env, s.pool, ExecutionMode::Router)).unwrap();
+    assert_eq!(last_event_name(&s.env), Symbol::new(&s.env, "pool_config_updated_event"));
+    call(&|env| SoroSwapBatcher::set_max_wait(env, s.pool, 10 * 60)).unwrap();
+    assert_eq!(last_event_name(&s.env), Symbol::new(&s.env, "pool_config_updated_event"));
+
+    assert_eq!(config(&s), (2, 20, 25, 60 * 60, 10 * 60));
+}
+
+#[test]
+fn new_settings_apply_to_later_deposits() {
+    let s = setup();
+    deposit(&s, 1).unwrap();
+
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_pool_size_limits(s.env.clone(), s.pool, 2, 10).unwrap();
+    });
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_deposit_expiry(s.env.clone(), s.pool, 2 * 60 * 60).unwrap();
+    });
+    s.env.ledger().with_mut(|li| li.timestamp = 100);
+
+    // The second deposit meets the lowered minimum and settles the batch
+    deposit(&s, 2).unwrap();
+    s.env.as_contract(&s.contract_id, || {
+        assert_eq!(SoroSwapBatcher::get_settled_batch_count(s.env.clone(), s.pool).unwrap(), 1);
+    });
+
+    deposit(&s, 3).unwrap();
+    let (_, _, expiry, timestamp, _) = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_deposit_details(s.env.clone(), s.pool, 2).unwrap()
+    });
+    assert_eq!(expiry, timestamp + 2 * 60 * 60);
+}
+
+#[test]
+fn batches_never_exceed_the_maximum_pool_size() {
+    let s = setup();
+    let set_limits = |min: u32, max: u32| s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_pool_size_limits(s.env.clone(), s.pool, min, max).unwrap();
+    });
+
+    // Queue six deposits without reaching the minimum, then lower both limits
+    set_limits(10, 10);
+    for seed in 1..=6 {
+        deposit(&s, seed).unwrap();
+    }
+    set_limits(2, 4);
+
+    let batch_id = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::execute_pool(s.env.clone(), s.pool, Address::generate(&s.env), u64::MAX).unwrap()
+    });
+    s.env.as_contract(&s.contract_id, || {
+        let batch = SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, batch_id).unwrap();
+        assert_eq!(batch.commitments.len(), 4);
+        assert_eq!(SoroSwapBatcher::get_pending_deposits(s.env.clone(), s.pool, 0, 10).unwrap().len(), 2);
+    });
+}
+
+#[test]
+fn unsupported_denomination_rejects_deposits() {
+    let s = setup();
+    let set_supported = |supported: bool| {
+        s.env.as_contract(&s.contract_id, || {
+            SoroSwapBatcher::set_denomination_supported(s.env.clone(), symbol_short!("10"), supported).unwrap();
+        });
+        assert_eq!(last_event_name(&s.env), Symbol::new(&s.env, "denomination_support_event"));
+    };
+
+    set_supported(false);
+    s.env.as_contract(&s.contract_id, || {
+        assert!(!SoroSwapBatcher::is_denomination_supported(s.env.clone(), symbol_short!("10")).unwrap());
+        assert!(SoroSwapBatcher::is_denomination_supported(s.env.clone(), symbol_short!("100")).unwrap());
+    });
+    assert_eq!(deposit(&s, 1), Err(BatcherError::UnsupportedDenomination));
+
+    set_supported(true);
+    deposit(&s, 1).unwrap();
+}
+
+#[test]
+fn max_wait_releases_batches_down_to_privacy_floor() {
+    let s = setup();
+    let batches = || s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_settled_batch_count(s.env.clone(), s.pool).unwrap()
+    });
+    let execute_pool = || s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::execute_pool(s.env.clone(), s.pool, Address::generate(&s.env), u64::MAX)
+    });
+    let at = |timestamp: u64| s.env.ledger().with_mut(|li| li.timestamp = timestamp);
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_max_wait(s.env.clone(), s.pool, 10 * 60).unwrap();
+    });
+
+    deposit(&s, 1).unwrap();
+    at(100);
+    deposit(&s, 2).unwrap();
+    at(10 * 60 - 1);
+    assert_eq!(execute_pool(), Err(BatcherError::PoolNotReady));
+
+    // Once the oldest deposit has waited out the window, two participants are enough
+    at(10 * 60);
+    assert_eq!(execute_pool(), Ok(0));
+    assert_eq!(batches(), 1);
+
+    // A lone deposit never leaves on its own, however long it waits
+    deposit(&s, 3).unwrap();
+    at(30 * 60);
+    assert_eq!(execute_pool(), Err(BatcherError::PoolNotReady));
+
+    // The next arrival reaches the floor and releases the overdue batch immediately
+    deposit(&s, 4).unwrap();
+    assert_eq!(batches(), 2);
+}
+
+#[test]
+fn wait_estimate_follows_arrival_history() {
+    let s = setup();
+    let estimate = || s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_pool_stats(s.env.clone(), s.pool).unwrap().estimated_wait_time
+    });
+
+    deposit(&s, 1).unwrap();
+    assert_eq!(estimate(), WAIT_TIME_UNKNOWN);
+
+    // One more deposit is needed, expected one average interval from now
+    s.env.ledger().with_mut(|li| li.timestamp = 1_000);
+    deposit(&s, 2).unwrap();
+    assert_eq!(estimate(), 1_000);
+
+    // A timed release that comes sooner shortens the estimate
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_max_wait(s.env.clone(), s.pool, 1_200).unwrap();
+    });
+    assert_eq!(estimate(), 200);
+    s.env.ledger().with_mut(|li| li.timestamp = 1_200);
+    assert_eq!(estimate(), 0);
+}
@@ -0,0 +1,957 @@
+//! `private_swap` and `withdraw` tests covering user-supplied commitments,
+//! Merkle membership and nullifier spending.
+
+use soroban_sdk::{
+    testutils::{storage::Persistent as _, Address as _, Events, Ledger},
+    token::{Client as TokenClient, StellarAssetClient},
+    vec,
+    xdr::ToXdr,
+    Address, BytesN, Env, Map, Symbol, TryFromVal, Val, Vec,
+};
+
+use super::coinjoin_e2e::{MockFactory, MockPair};
+use super::mock_router::MockRouter;
+use super::mock_verifier::{MockProof, MockVerifier};
+use crate::{
+    coinjoin::{CoinJoinMixer, ExecutionMode},
+    helpers, merkle::MerkleTree, BatcherError, DataKey, SoroSwapBatcher, SoroSwapBatcherClient,
+};
+
+const DENOM: i128 = 10_000_000;
+/// Default 10 bps CoinJoin fee retained from each deposit
+const FEE: i128 = DENOM * 10 / 10_000;
+const RESERVE: i128 = 1_000_000_000_000;
+
+struct Setup {
+    env: Env,
+    contract_id: Address,
+    owner: Address,
+    factory: Address,
+    router: Address,
+    token_in: Address,
+    token_out: Address,
+    pool: u32,
+}
+
+fn setup() -> Setup {
+    let env = Env::default();
+    // The router pulls input from the batch contract below the root invocation
+    env.mock_all_auths_allowing_non_root_auth();
+
+    let admin = Address::generate(&env);
+    let token_in = env.register_stellar_asset_contract_v2(admin.clone()).address();
+    let token_out = env.register_stellar_asset_contract_v2(admin).address();
+
+    let pair = env.register(MockPair, ());
+    env.as_contract(&pair, || {
+        MockPair::__init(env.clone(), token_in.clone(), token_out.clone(), RESERVE, RESERVE);
+    });
+    StellarAssetClient::new(&env, &token_out).mint(&pair, &RESERVE);
+
+    let factory = env.register(MockFactory, ());
+    env.as_contract(&factory, || {
+        MockFactory::__init(env.clone(), pair.clone());
+    });
+
+    let router = env.register(MockRouter, ());
+    env.as_contract(&router, || {
+        MockRouter::__init(env.clone(), factory.clone());
+    });
+
+    let owner = Address::generate(&env);
+    let verifier = env.register(MockVerifier, ());
+    let contract_id = env.register(SoroSwapBatcher, ());
+    env.as_contract(&contract_id, || {
+        SoroSwapBatcher::initialize(
+            env.clone(),
+            owner.clone(),
+            factory.clone(),
+            router.clone(),
+        )
+        .unwrap();
+        SoroSwapBatcher::set_verifier(env.clone(), verifier).unwrap();
+    });
+    let pool = env.as_contract(&contract_id, || {
+        SoroSwapBatcher::create_pool(env.clone(), token_in.clone(), token_out.clone(), denom()).unwrap()
+    });
+
+    Setup { env, contract_id, owner, factory, router, token_in, token_out, pool }
+}
+
+/// Deterministic (nullifier, secret) note preimage.
+fn note_preimage(env: &Env, seed: u8) -> (BytesN<32>, BytesN<32>) {
+    (
+        BytesN::from_array(env, &[seed; 32]),
+        BytesN::from_array(env, &[seed.wrapping_add(100); 32]),
+    )
+}
+
+/// Build a (commitment, nullifier_hash) pair from a deterministic note.
+fn note(env: &Env, seed: u8) -> (BytesN<32>, BytesN<32>) {
+    let (nullifier, secret) = note_preimage(env, seed);
+    (
+        CoinJoinMixer::compute_commitment(env, &nullifier, &secret),
+        CoinJoinMixer::compute_nullifier_hash(env, &nullifier),
+    )
+}
+
+fn denom() -> Symbol {
+    soroban_sdk::symbol_short!("10")
+}
+
+fn pool_size(s: &Setup) -> u32 {
+    s.env.as_contract(&s.contract_id, || {
+        let (pool_size, _, _) = SoroSwapBatcher::get_coinjoin_stats(
+            s.env.clone(),
+            s.token_in.clone(),
+            s.token_out.clone(),
+            denom(),
+        )
+        .unwrap();
+        pool_size
+    })
+}
+
+fn swap(s: &Setup, user: &Address, commitment: BytesN<32>) -> Result<u64, BatcherError> {
+    swap_along(s, user, vec![&s.env, s.token_in.clone(), s.token_out.clone()], 9_000_000, commitment)
+}
+
+fn swap_along(
+    s: &Setup,
+    user: &Address,
+    path: Vec<Address>,
+    min_amount_out: i128,
+    commitment: BytesN<32>,
+) -> Result<u64, BatcherError> {
+    StellarAssetClient::new(&s.env, &s.token_in).mint(user, &DENOM);
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::private_swap(
+            s.env.clone(),
+            path,
+            DENOM,
+            min_amount_out,
+            100,
+            user.clone(),
+            commitment,
+        )
+    })
+}
+
+/// Build a mock proof for the note `seed` in settled batch `batch_id`.
+fn withdrawal_proof(s: &Setup, batch_id: u64, seed: u8) -> soroban_sdk::Bytes {
+    let (nullifier, secret) = note_preimage(&s.env, seed);
+    let (commitment, _) = note(&s.env, seed);
+
+    s.env.as_contract(&s.contract_id, || {
+        let batch = SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, batch_id).unwrap();
+        let leaf_index = batch.commitments.first_index_of(&commitment).unwrap();
+        let path = SoroSwapBatcher::get_batch_merkle_path(s.env.clone(), s.pool, batch_id, leaf_index).unwrap();
+
+        MockProof { nullifier, secret, leaf_index, siblings: path.siblings }.to_xdr(&s.env)
+    })
+}
+
+fn withdraw(
+    s: &Setup,
+    batch_id: u64,
+    nullifier_hash: BytesN<32>,
+    recipient: &Address,
+    proof: soroban_sdk::Bytes,
+) -> Result<i128, BatcherError> {
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::withdraw(s.env.clone(), s.pool, batch_id, nullifier_hash, recipient.clone(), proof)
+    })
+}
+
+#[test]
+fn same_ledger_deposits_with_distinct_notes_do_not_collide() {
+    let s = setup();
+
+    for seed in 1..=2u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    assert_eq!(pool_size(&s), 2);
+}
+
+#[test]
+fn duplicate_commitment_is_rejected() {
+    let s = setup();
+    let (commitment, _) = note(&s.env, 1);
+
+    swap(&s, &Address::generate(&s.env), commitment.clone()).unwrap();
+
+    let err = swap(&s, &Address::generate(&s.env), commitment)
+        .expect_err("replayed commitment must fail");
+    assert_eq!(err, BatcherError::CommitmentAlreadyUsed);
+}
+
+#[test]
+fn zero_commitment_is_rejected() {
+    let s = setup();
+    let zero = BytesN::from_array(&s.env, &[0u8; 32]);
+
+    let err = swap(&s, &Address::generate(&s.env), zero)
+        .expect_err("zero commitment must fail");
+    assert_eq!(err, BatcherError::InvalidCommitment);
+}
+
+#[test]
+fn settled_batch_pays_out_through_verified_withdrawals() {
+    let s = setup();
+
+    for seed in 1..=3u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    let batch = s.env.as_contract(&s.contract_id, || {
+        assert_eq!(SoroSwapBatcher::get_settled_batch_count(s.env.clone(), s.pool).unwrap(), 1);
+        SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, 0).unwrap()
+    });
+    assert_eq!(batch.commitments.len(), 3);
+    assert_eq!(batch.remaining_claims, 3);
+    assert!(batch.payout_amount > 0);
+
+    let token_out = TokenClient::new(&s.env, &s.token_out);
+    for seed in 1..=3u8 {
+        let recipient = Address::generate(&s.env);
+        let (_, nullifier_hash) = note(&s.env, seed);
+        let proof = withdrawal_proof(&s, 0, seed);
+
+        let paid = withdraw(&s, 0, nullifier_hash.clone(), &recipient, proof).unwrap();
+        assert_eq!(paid, batch.payout_amount);
+        assert_eq!(token_out.balance(&recipient), batch.payout_amount);
+
+        s.env.as_contract(&s.contract_id, || {
+            assert!(SoroSwapBatcher::is_nullifier_used(s.env.clone(), nullifier_hash.clone()));
+            // Spent nullifiers are persistent entries kept at the maximum TTL
+            let ttl = s.env.storage().persistent().get_ttl(&DataKey::NullifierUsed(nullifier_hash));
+            assert_eq!(ttl, s.env.storage().max_ttl());
+        });
+    }
+
+    s.env.as_contract(&s.contract_id, || {
+        let batch = SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, 0).unwrap();
+        assert_eq!(batch.remaining_claims, 0);
+    });
+}
+
+#[test]
+fn withdrawal_rejects_spent_nullifier_and_bad_proof() {
+    let s = setup();
+
+    for seed in 1..=3u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    let (_, nullifier_hash) = note(&s.env, 1);
+    let proof = withdrawal_proof(&s, 0, 1);
+    withdraw(&s, 0, nullifier_hash.clone(), &Address::generate(&s.env), proof.clone()).unwrap();
+
+    let err = withdraw(&s, 0, nullifier_hash, &Address::generate(&s.env), proof)
+        .expect_err("spent nullifier must fail");
+    assert_eq!(err, BatcherError::NullifierAlreadyUsed);
+
+    // Proof for note 2 presented with note 3's nullifier hash
+    let (_, other_nullifier_hash) = note(&s.env, 3);
+    let err = withdraw(&s, 0, other_nullifier_hash, &Address::generate(&s.env), withdrawal_proof(&s, 0, 2))
+        .expect_err("mismatched proof must fail");
+    assert_eq!(err, BatcherError::InvalidProof);
+
+    // Notes that never joined the batch cannot withdraw
+    let (outsider_commitment, outsider_nullifier_hash) = note(&s.env, 9);
+    let (nullifier, secret) = note_preimage(&s.env, 9);
+    let forged = MockProof {
+        nullifier,
+        secret,
+        leaf_index: 0,
+        siblings: s.env.as_contract(&s.contract_id, || {
+            SoroSwapBatcher::get_batch_merkle_path(s.env.clone(), s.pool, 0, 0).unwrap().siblings
+        }),
+    }
+    .to_xdr(&s.env);
+    assert!(!s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, 0).unwrap().commitments.contains(&outsider_commitment)
+    }));
+    let err = withdraw(&s, 0, outsider_nullifier_hash, &Address::generate(&s.env), forged)
+        .expect_err("non-member must fail");
+    assert_eq!(err, BatcherError::InvalidProof);
+}
+
+#[test]
+fn only_owner_sets_verifier() {
+    let s = setup();
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_verifier(s.env.clone(), Address::generate(&s.env)).unwrap();
+    });
+
+    let auths = s.env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, s.owner);
+}
+
+#[test]
+fn verify_note_checks_both_preimages() {
+    let s = setup();
+    let nullifier = BytesN::from_array(&s.env, &[7u8; 32]);
+    let secret = BytesN::from_array(&s.env, &[107u8; 32]);
+    let (commitment, nullifier_hash) = note(&s.env, 7);
+
+    s.env.as_contract(&s.contract_id, || {
+        assert!(SoroSwapBatcher::verify_note(
+            s.env.clone(),
+            commitment.clone(),
+            nullifier_hash.clone(),
+            nullifier.clone(),
+            secret,
+        ));
+        assert!(!SoroSwapBatcher::verify_note(
+            s.env.clone(),
+            commitment,
+            nullifier_hash,
+            nullifier.clone(),
+            nullifier,
+        ));
+    });
+}
+
+#[test]
+fn deposits_update_merkle_root_and_expose_paths() {
+    let s = setup();
+
+    let empty_root = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_merkle_root(s.env.clone(), s.pool).unwrap()
+    });
+
+    let mut commitments = Vec::new(&s.env);
+    for seed in 1..=2u8 {
+        let (commitment, _) = note(&s.env, seed);
+        commitments.push_back(commitment.clone());
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    s.env.as_contract(&s.contract_id, || {
+        let root = SoroSwapBatcher::get_merkle_root(s.env.clone(), s.pool).unwrap();
+        assert_ne!(root, empty_root);
+
+        let roots = SoroSwapBatcher::get_merkle_roots(s.env.clone(), s.pool).unwrap();
+        assert_eq!(roots.len(), 3);
+        assert_eq!(roots.get(0).unwrap(), root);
+        assert!(SoroSwapBatcher::is_known_root(s.env.clone(), s.pool, empty_root.clone()).unwrap());
+
+        for (i, commitment) in commitments.iter().enumerate() {
+            let leaf_index = SoroSwapBatcher::get_leaf_index(s.env.clone(), commitment.clone()).unwrap();
+            assert_eq!(leaf_index, i as u32);
+
+            let path = SoroSwapBatcher::get_merkle_path(s.env.clone(), s.pool, leaf_index).unwrap();
+            assert_eq!(path.leaf, commitment);
+            assert_eq!(path.root, root);
+            assert_eq!(MerkleTree::compute_root(&s.env, &path.leaf, leaf_index, &path.siblings), root);
+        }
+    });
+}
+
+#[test]
+fn multi_hop_batch_settles_through_every_pair() {
+    let s = setup();
+
+    // Second hop: token_out -> token_final at a 1:2 price
+    let token_final = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+    let second_pair = s.env.register(MockPair, ());
+    s.env.as_contract(&second_pair, || {
+        MockPair::__init(s.env.clone(), s.token_out.clone(), token_final.clone(), RESERVE, 2 * RESERVE);
+    });
+    StellarAssetClient::new(&s.env, &token_final).mint(&second_pair, &(2 * RESERVE));
+    s.env.as_contract(&s.factory, || {
+        MockFactory::add_pair(s.env.clone(), s.token_out.clone(), token_final.clone(), second_pair.clone());
+    });
+    let pool = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::create_pool(s.env.clone(), s.token_in.clone(), token_final.clone(), denom()).unwrap()
+    });
+
+    let path = vec![&s.env, s.token_in.clone(), s.token_out.clone(), token_final.clone()];
+    for seed in 1..=3u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap_along(&s, &Address::generate(&s.env), path.clone(), 18_000_000, commitment).unwrap();
+    }
+
+    // Equal payout uses the chained constant product quote
+    let first_hop = helpers::get_amount_out(3 * (DENOM - FEE), RESERVE, RESERVE);
+    let second_hop = helpers::get_amount_out(first_hop, RESERVE, 2 * RESERVE);
+    // The route ends in a different token, so the deposits went to its own pool
+    assert_ne!(pool, s.pool);
+    let s = Setup { pool, ..s };
+
+    let batch = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, 0).unwrap()
+    });
+    assert_eq!(batch.token_out, token_final);
+    assert_eq!(batch.payout_amount, second_hop / 3);
+
+    // The intermediate token went through the second pair, and only the final token is held
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&second_pair), first_hop);
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&s.contract_id), 0);
+    assert_eq!(TokenClient::new(&s.env, &token_final).balance(&s.contract_id), second_hop);
+
+    let recipient = Address::generate(&s.env);
+    let (_, nullifier_hash) = note(&s.env, 1);
+    withdraw(&s, 0, nullifier_hash, &recipient, withdrawal_proof(&s, 0, 1)).unwrap();
+    assert_eq!(TokenClient::new(&s.env, &token_final).balance(&recipient), batch.payout_amount);
+}
+
+#[test]
+fn unswappable_later_hop_fails_before_funds_move() {
+    let s = setup();
+
+    // Second hop pays nothing out: its pair holds no token_final
+    let token_final = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+    let second_pair = s.env.register(MockPair, ());
+    s.env.as_contract(&second_pair, || {
+        MockPair::__init(s.env.clone(), s.token_out.clone(), token_final.clone(), RESERVE, 0);
+    });
+    s.env.as_contract(&s.factory, || {
+        MockFactory::add_pair(s.env.clone(), s.token_out.clone(), token_final.clone(), second_pair);
+    });
+    StellarAssetClient::new(&s.env, &s.token_in).mint(&s.contract_id, &DENOM);
+
+    let path = vec![&s.env, s.token_in.clone(), s.token_out.clone(), token_final];
+    let result = s.env.as_contract(&s.contract_id, || helpers::swap_exact_in_path(&s.env, &path, DENOM));
+    assert_eq!(result, Err(BatcherError::InsufficientBalance));
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&s.contract_id), DENOM);
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&s.contract_id), 0);
+}
+
+#[test]
+fn malformed_paths_are_rejected() {
+    let s = setup();
+    let user = Address::generate(&s.env);
+
+    for (seed, path) in [
+        (1u8, vec![&s.env, s.token_in.clone()]),
+        (2u8, vec![&s.env, s.token_in.clone(), s.token_in.clone()]),
+        // Revisits a pair, so the chained quote of the second hop would be stale
+        (3u8, vec![&s.env, s.token_in.clone(), s.token_out.clone(), s.token_in.clone()]),
+        (4u8, vec![
+            &s.env,
+            s.token_in.clone(),
+            s.token_out.clone(),
+            s.token_in.clone(),
+            s.token_out.clone(),
+            s.token_in.clone(),
+        ]),
+    ] {
+        let (commitment, _) = note(&s.env, seed);
+        let err = swap_along(&s, &user, path, 9_000_000, commitment).expect_err("malformed path must fail");
+        assert_eq!(err, BatcherError::InvalidInput);
+    }
+}
+
+fn use_router(s: &Setup) {
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_execution_mode(s.env.clone(), s.pool, ExecutionMode::Router).unwrap();
+        assert_eq!(SoroSwapBatcher::get_execution_mode(s.env.clone(), s.pool).unwrap(), ExecutionMode::Router);
+    });
+}
+
+fn router_swaps(s: &Setup) -> u32 {
+    s.env.as_contract(&s.router, || MockRouter::swap_count(s.env.clone()))
+}
+
+fn execute_pool(s: &Setup, keeper: &Address, deadline: u64) -> Result<u64, BatcherError> {
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::execute_pool(s.env.clone(), s.pool, keeper.clone(), deadline)
+    })
+}
+
+#[test]
+fn router_mode_executes_batch_through_router() {
+    let s = setup();
+    use_router(&s);
+
+    // Router pools wait for a keeper, whose transaction carries the deadline
+    for seed in 1..=3u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+    assert_eq!(router_swaps(&s), 0);
+    assert_eq!(pool_size(&s), 3);
+
+    let keeper = Address::generate(&s.env);
+    assert_eq!(execute_pool(&s, &keeper, s.env.ledger().timestamp() + 60), Ok(0));
+
+    assert_eq!(router_swaps(&s), 1);
+    let batch = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, 0).unwrap()
+    });
+    let total_out = helpers::get_amount_out(3 * (DENOM - FEE), RESERVE, RESERVE);
+    assert_eq!(batch.payout_amount, total_out / 3);
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&s.contract_id), total_out);
+    let fees = TokenClient::new(&s.env, &s.token_in).balance(&s.contract_id)
+        + TokenClient::new(&s.env, &s.token_in).balance(&keeper);
+    assert_eq!(fees, 3 * FEE);
+}
+
+#[test]
+fn router_swap_respects_the_keepers_deadline() {
+    let s = setup();
+    use_router(&s);
+    for seed in 1..=3u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    // A transaction that lands after its deadline settles nothing
+    s.env.ledger().with_mut(|li| li.timestamp = 1_000);
+    let keeper = Address::generate(&s.env);
+    assert_eq!(execute_pool(&s, &keeper, 1_000), Err(BatcherError::OrderTooLate));
+    assert_eq!(router_swaps(&s), 0);
+    assert_eq!(pool_size(&s), 3);
+
+    assert_eq!(execute_pool(&s, &keeper, 1_001), Ok(0));
+    assert_eq!(router_swaps(&s), 1);
+}
+
+#[test]
+fn router_rejection_leaves_deposits_queued() {
+    let s = setup();
+    use_router(&s);
+
+    // Drain the pair so the router's swap cannot pay out
+    let pair = s.env.as_contract(&s.factory, || {
+        MockFactory::get_pair(s.env.clone(), s.token_in.clone(), s.token_out.clone())
+    });
+    TokenClient::new(&s.env, &s.token_out).transfer(&pair, Address::generate(&s.env), &RESERVE);
+
+    for seed in 1..=3u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    let keeper = Address::generate(&s.env);
+    assert_eq!(execute_pool(&s, &keeper, u64::MAX), Err(BatcherError::SwapFailed));
+    assert_eq!(router_swaps(&s), 0);
+    s.env.as_contract(&s.contract_id, || {
+        assert_eq!(SoroSwapBatcher::get_settled_batch_count(s.env.clone(), s.pool).unwrap(), 0);
+    });
+    assert_eq!(pool_size(&s), 3);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&s.contract_id), 3 * DENOM);
+}
+
+fn set_pair_shortfall(s: &Setup, shortfall: i128) {
+    let pair = s.env.as_contract(&s.factory, || {
+        MockFactory::get_pair(s.env.clone(), s.token_in.clone(), s.token_out.clone())
+    });
+    s.env.as_contract(&pair, || MockPair::set_shortfall(s.env.clone(), shortfall));
+}
+
+#[test]
+fn batch_payout_uses_measured_output() {
+    let s = setup();
+    set_pair_shortfall(&s, 3);
+
+    for seed in 1..=3u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    let received = helpers::get_amount_out(3 * (DENOM - FEE), RESERVE, RESERVE) - 3;
+    let batch = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_settled_batch(s.env.clone(), s.pool, 0).unwrap()
+    });
+    assert_eq!(batch.payout_amount, received / 3);
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&s.contract_id), received);
+}
+
+#[test]
+fn output_below_participant_minimum_reverts_batch() {
+    let s = setup();
+    // Quote covers each participant's 9M minimum, the delivered amount does not
+    set_pair_shortfall(&s, 3_000_000);
+
+    for seed in 1..=2u8 {
+        let (commitment, _) = note(&s.env, seed);
+        swap(&s, &Address::generate(&s.env), commitment).unwrap();
+    }
+
+    let user = Address::generate(&s.env);
+    let (commitment, _) = note(&s.env, 3);
+    StellarAssetClient::new(&s.env, &s.token_in).mint(&user, &DENOM);
+    let client = SoroSwapBatcherClient::new(&s.env, &s.contract_id);
+    let path = vec![&s.env, s.token_in.clone(), s.token_out.clone()];
+    let result = client.try_private_swap(&path, &DENOM, &9_000_000, &100, &user, &commitment);
+    assert_eq!(result, Err(Ok(BatcherError::SlippageExceeded)));
+
+    s.env.as_contract(&s.contract_id, || {
+        assert_eq!(SoroSwapBatcher::get_settled_batch_count(s.env.clone(), s.pool).unwrap(), 0);
+    });
+    assert_eq!(pool_size(&s), 2);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&s.contract_id), 2 * DENOM);
+    assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&s.contract_id), 0);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&user), DENOM);
+}
+
+#[test]
+fn measured_share_must_cover_the_strictest_minimum() {
+    let quoted = helpers::get_amount_out(3 * (DENOM - FEE), RESERVE, RESERVE);
+    let share = quoted / 3;
+    // Three stroops short: the summed minimums still fit, the strictest participant's does not
+    for mode in [ExecutionMode::Pair, ExecutionMode::Router] {
+        let s = setup();
+        set_pair_shortfall(&s, quoted - 3 * share + 3);
+        if mode == ExecutionMode::Router {
+            use_router(&s);
+        }
+        let path = vec![&s.env, s.token_in.clone(), s.token_out.clone()];
+        for seed in 1..=2u8 {
+            swap_along(&s, &Address::generate(&s.env), path.clone(), 1_000_000, note(&s.env, seed).0).unwrap();
+        }
+
+        let user = Address::generate(&s.env);
+        StellarAssetClient::new(&s.env, &s.token_in).mint(&user, &DENOM);
+        let client = SoroSwapBatcherClient::new(&s.env, &s.contract_id);
+        let deposit = client.try_private_swap(&path, &DENOM, &share, &100, &user, &note(&s.env, 3).0);
+        let result = match mode {
+            ExecutionMode::Pair => deposit,
+            ExecutionMode::Router => {
+                assert!(deposit.is_ok());
+                client.try_execute_pool(&s.pool, &Address::generate(&s.env), &u64::MAX)
+            }
+        };
+        assert_eq!(result, Err(Ok(BatcherError::SlippageExceeded)));
+        s.env.as_contract(&s.contract_id, || {
+            assert_eq!(SoroSwapBatcher::get_settled_batch_count(s.env.clone(), s.pool).unwrap(), 0);
+        });
+        assert_eq!(TokenClient::new(&s.env, &s.token_out).balance(&s.contract_id), 0);
+    }
+}
+
+const DEPOSIT_EXPIRY: u64 = 48 * 60 * 60;
+
+#[test]
+fn expired_deposits_are_refunded_by_anyone() {
+    let s = setup();
+    let early = Address::generate(&s.env);
+    swap(&s, &early, note(&s.env, 1).0).unwrap();
+
+    s.env.ledger().with_mut(|li| li.timestamp = 1_000);
+    let late = Address::generate(&s.env);
+    swap(&s, &late, note(&s.env, 2).0).unwrap();
+
+    let refund = || s.env.as_contract(&s.contract_id, || SoroSwapBatcher::refund_expired(s.env.clone(), s.pool));
+    assert_eq!(refund(), Ok(0));
+
+    s.env.ledger().with_mut(|li| li.timestamp = DEPOSIT_EXPIRY);
+    assert_eq!(refund(), Ok(1));
+
+    let token_in = TokenClient::new(&s.env, &s.token_in);
+    assert_eq!(token_in.balance(&early), DENOM);
+    assert_eq!(token_in.balance(&late), 0);
+    assert_eq!(token_in.balance(&s.contract_id), DENOM);
+    assert_eq!(pool_size(&s), 1);
+}
+
+#[test]
+fn sender_cancels_own_deposit_only_after_expiry() {
+    let s = setup();
+    let user = Address::generate(&s.env);
+    let (commitment, _) = note(&s.env, 1);
+    swap(&s, &user, commitment.clone()).unwrap();
+
+    let cancel = |commitment: &BytesN<32>| {
+        s.env.as_contract(&s.contract_id, || {
+            SoroSwapBatcher::cancel_deposit(s.env.clone(), s.pool, commitment.clone())
+        })
+    };
+    assert_eq!(cancel(&commitment), Err(BatcherError::DepositNotExpired));
+    assert_eq!(cancel(&note(&s.env, 9).0), Err(BatcherError::DepositNotFound));
+
+    s.env.ledger().with_mut(|li| li.timestamp = DEPOSIT_EXPIRY);
+    assert_eq!(cancel(&commitment), Ok(DENOM));
+    assert_eq!(s.env.auths()[0].0, user);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&user), DENOM);
+    assert_eq!(pool_size(&s), 0);
+
+    assert_eq!(cancel(&commitment), Err(BatcherError::DepositNotFound));
+}
+
+#[test]
+fn long_queues_are_paged_and_refunded_in_windows() {
+    let s = setup();
+    s.env.cost_estimate().budget().reset_unlimited();
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_pool_size_limits(s.env.clone(), s.pool, 50, 50).unwrap();
+    });
+
+    // Minimums no swap can meet keep every deposit queued past the pool size
+    let path = vec![&s.env, s.token_in.clone(), s.token_out.clone()];
+    let mut users = Vec::new(&s.env);
+    for seed in 1..=70u8 {
+        let user = Address::generate(&s.env);
+        swap_along(&s, &user, path.clone(), 2 * DENOM, note(&s.env, seed).0).unwrap();
+        users.push_back(user);
+    }
+    assert_eq!(pool_size(&s), 70);
+
+    let page = |start: u64, limit: u32| {
+        s.env.as_contract(&s.contract_id, || {
+            SoroSwapBatcher::get_pending_deposits(s.env.clone(), s.pool, start, limit).unwrap()
+        })
+    };
+    let first = page(0, 100);
+    assert_eq!(first.len(), 64);
+    assert_eq!(first.last().unwrap().seq, 63);
+    let rest = page(64, 64);
+    assert_eq!(rest.len(), 6);
+    assert_eq!(rest.first().unwrap().seq, 64);
+
+    // Each pending deposit is its own persistent entry with a refreshed TTL
+    s.env.as_contract(&s.contract_id, || {
+        let ttl = s.env.storage().persistent().get_ttl(&DataKey::Deposit(s.pool, 69));
+        assert!(ttl >= 1_000_000);
+    });
+
+    s.env.ledger().with_mut(|li| li.timestamp = DEPOSIT_EXPIRY);
+    let cancelled = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::cancel_deposit(s.env.clone(), s.pool, note(&s.env, 1).0)
+    });
+    assert_eq!(cancelled, Ok(DENOM));
+    let range = || s.env.as_contract(&s.contract_id, || SoroSwapBatcher::get_deposit_range(s.env.clone(), s.pool));
+    assert_eq!(range(), Ok((1, 70)));
+    let details = s.env.as_contract(&s.contract_id, || SoroSwapBatcher::get_deposit_details(s.env.clone(), s.pool, 0));
+    assert_eq!(details, Err(BatcherError::DepositNotFound));
+
+    // Refunds walk the queue one window at a time
+    let refund = || s.env.as_contract(&s.contract_id, || SoroSwapBatcher::refund_expired(s.env.clone(), s.pool));
+    assert_eq!(refund(), Ok(64));
+    assert_eq!(range(), Ok((65, 70)));
+    assert_eq!(refund(), Ok(5));
+    assert_eq!(range(), Ok((70, 70)));
+    assert_eq!(pool_size(&s), 0);
+    for user in users.iter() {
+        assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&user), DENOM);
+    }
+}
+
+#[test]
+fn pause_blocks_deposits_and_execution_but_not_reclaims() {
+    let s = setup();
+    let users = [Address::generate(&s.env), Address::generate(&s.env)];
+    swap(&s, &users[0], note(&s.env, 1).0).unwrap();
+    swap(&s, &users[1], note(&s.env, 2).0).unwrap();
+
+    s.env.as_contract(&s.contract_id, || SoroSwapBatcher::pause(s.env.clone()).unwrap());
+    assert_eq!(s.env.auths()[0].0, s.owner);
+    assert!(s.env.as_contract(&s.contract_id, || SoroSwapBatcher::is_paused(s.env.clone())));
+
+    assert_eq!(swap(&s, &Address::generate(&s.env), note(&s.env, 3).0), Err(BatcherError::ContractPaused));
+    let executed = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::execute_pool(s.env.clone(), s.pool, Address::generate(&s.env), u64::MAX)
+    });
+    assert_eq!(executed, Err(BatcherError::ContractPaused));
+    assert_eq!(pool_size(&s), 2);
+
+    s.env.ledger().with_mut(|li| li.timestamp = DEPOSIT_EXPIRY);
+    let cancelled = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::cancel_deposit(s.env.clone(), s.pool, note(&s.env, 1).0)
+    });
+    assert_eq!(cancelled, Ok(DENOM));
+    let refunded = s.env.as_contract(&s.contract_id, || SoroSwapBatcher::refund_expired(s.env.clone(), s.pool));
+    assert_eq!(refunded, Ok(1));
+    for user in users.iter() {
+        assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(user), DENOM);
+    }
+
+    s.env.as_contract(&s.contract_id, || SoroSwapBatcher::unpause(s.env.clone()).unwrap());
+    assert!(!s.env.as_contract(&s.contract_id, || SoroSwapBatcher::is_paused(s.env.clone())));
+    swap(&s, &Address::generate(&s.env), note(&s.env, 3).0).unwrap();
+    assert_eq!(pool_size(&s), 1);
+}
+
+#[test]
+fn deposits_for_different_pairs_fill_separate_pools() {
+    let s = setup();
+
+    let other_out = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+    let other_pair = s.env.register(MockPair, ());
+    s.env.as_contract(&other_pair, || {
+        MockPair::__init(s.env.clone(), s.token_in.clone(), other_out.clone(), RESERVE, RESERVE);
+    });
+    StellarAssetClient::new(&s.env, &other_out).mint(&other_pair, &RESERVE);
+    s.env.as_contract(&s.factory, || {
+        MockFactory::add_pair(s.env.clone(), s.token_in.clone(), other_out.clone(), other_pair);
+    });
+
+    // Deposits for a pair without a pool are refused rather than opening one
+    let other_path = vec![&s.env, s.token_in.clone(), other_out.clone()];
+    let user = Address::generate(&s.env);
+    let err = swap_along(&s, &user, other_path.clone(), 9_000_000, note(&s.env, 20).0).unwrap_err();
+    assert_eq!(err, BatcherError::PoolNotFound);
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&user), DENOM);
+    let other_pool = s.env.as_contract(&s.contract_id, || {
+        assert_eq!(SoroSwapBatcher::get_pool_count(s.env.clone()), 1);
+        SoroSwapBatcher::create_pool(s.env.clone(), s.token_in.clone(), other_out.clone(), denom()).unwrap()
+    });
+
+    // Interleaved deposits for both pairs; neither pool blocks the other
+    for seed in 1..=3u8 {
+        swap(&s, &Address::generate(&s.env), note(&s.env, seed).0).unwrap();
+        swap_along(&s, &Address::generate(&s.env), other_path.clone(), 9_000_000, note(&s.env, seed + 10).0).unwrap();
+    }
+    let total_out = helpers::get_amount_out(3 * (DENOM - FEE), RESERVE, RESERVE);
+    for (pool, token_out) in [(s.pool, &s.token_out), (other_pool, &other_out)] {
+        let (batch, stats) = s.env.as_contract(&s.contract_id, || {
+            (
+                SoroSwapBatcher::get_settled_batch(s.env.clone(), pool, 0).unwrap(),
+                SoroSwapBatcher::get_pool_stats(s.env.clone(), pool).unwrap(),
+            )
+        });
+        assert_eq!(batch.token_out, *token_out);
+        assert_eq!(batch.payout_amount, total_out / 3);
+        assert_eq!(stats.token_out, *token_out);
+        assert_eq!(stats.total_deposits, 3);
+        assert_eq!(stats.current_pool_size, 0);
+    }
+    assert_eq!(TokenClient::new(&s.env, &other_out).balance(&s.contract_id), total_out);
+}
+
+#[test]
+fn fees_are_retained_and_withdrawn_by_fee_recipient() {
+    let s = setup();
+    for seed in 1..=3u8 {
+        swap(&s, &Address::generate(&s.env), note(&s.env, seed).0).unwrap();
+    }
+
+    let token_in = TokenClient::new(&s.env, &s.token_in);
+    let collected = || s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_collected_fees(s.env.clone(), s.token_in.clone())
+    });
+    assert_eq!(collected(), 3 * FEE);
+    assert_eq!(token_in.balance(&s.contract_id), 3 * FEE);
+
+    let fee_recipient = Address::generate(&s.env);
+    s.env.as_contract(&s.contract_id, || {
+        assert_eq!(SoroSwapBatcher::get_fee_recipient(s.env.clone()).unwrap(), s.owner);
+        SoroSwapBatcher::set_fee_recipient(s.env.clone(), fee_recipient.clone()).unwrap();
+    });
+
+    let treasury = Address::generate(&s.env);
+    let withdraw_fees = |amount: i128| s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::withdraw_fees(s.env.clone(), s.token_in.clone(), treasury.clone(), amount)
+    });
+    assert_eq!(withdraw_fees(3 * FEE + 1), Err(BatcherError::InsufficientBalance));
+    assert_eq!(withdraw_fees(0), Err(BatcherError::InvalidInput));
+
+    withdraw_fees(2 * FEE).unwrap();
+    assert_eq!(s.env.auths()[0].0, fee_recipient);
+    assert_eq!(token_in.balance(&treasury), 2 * FEE);
+    assert_eq!(collected(), FEE);
+}
+
+/// Data of the most recent contract event named `name` in the last invocation
+fn last_event(env: &Env, name: &str) -> Option<Map<Symbol, Val>> {
+    let name = Symbol::new(env, name);
+    env.events()
+        .all()
+        .iter()
+        .rev()
+        .find(|(_, topics, _)| Symbol::try_from_val(env, &topics.get(0).unwrap()).as_ref() == Ok(&name))
+        .map(|(_, _, data)| Map::try_from_val(env, &data).unwrap())
+}
+
+fn field<T: TryFromVal<Env, Val>>(env: &Env, data: &Map<Symbol, Val>, key: &str) -> T {
+    T::try_from_val(env, &data.get(Symbol::new(env, key)).unwrap()).ok().unwrap()
+}
+
+#[test]
+fn lifecycle_steps_publish_events() {
+    let s = setup();
+
+    swap(&s, &Address::generate(&s.env), note(&s.env, 1).0).unwrap();
+    let deposit = last_event(&s.env, "coin_join_deposit_event").expect("deposit event");
+    assert_eq!(field::<BytesN<32>>(&s.env, &deposit, "commitment"), note(&s.env, 1).0);
+    assert_eq!(field::<u32>(&s.env, &deposit, "leaf_index"), 0);
+    assert_eq!(field::<u32>(&s.env, &deposit, "pool_size"), 1);
+    assert!(last_event(&s.env, "coin_join_batch_executed_event").is_none());
+
+    for seed in 2..=3u8 {
+        swap(&s, &Address::generate(&s.env), note(&s.env, seed).0).unwrap();
+    }
+    let executed = last_event(&s.env, "coin_join_batch_executed_event").expect("batch event");
+    let total_out = helpers::get_amount_out(3 * (DENOM - FEE), RESERVE, RESERVE);
+    assert_eq!(field::<u32>(&s.env, &executed, "participant_count"), 3);
+    assert_eq!(field::<i128>(&s.env, &executed, "total_input"), 3 * (DENOM - FEE));
+    assert_eq!(field::<i128>(&s.env, &executed, "total_output"), total_out);
+    assert_eq!(field::<i128>(&s.env, &executed, "payout_amount"), total_out / 3);
+    assert_eq!(field::<i128>(&s.env, &executed, "fees"), 3 * FEE);
+    assert_eq!(field::<u32>(&s.env, &executed, "slippage_bps"), 0);
+
+    let recipient = Address::generate(&s.env);
+    let (_, nullifier_hash) = note(&s.env, 2);
+    withdraw(&s, 0, nullifier_hash.clone(), &recipient, withdrawal_proof(&s, 0, 2)).unwrap();
+    let payout = last_event(&s.env, "coin_join_payout_event").expect("payout event");
+    assert_eq!(field::<BytesN<32>>(&s.env, &payout, "nullifier_hash"), nullifier_hash);
+    assert_eq!(field::<Address>(&s.env, &payout, "recipient"), recipient);
+    assert_eq!(field::<u32>(&s.env, &payout, "remaining_claims"), 2);
+
+    s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_fee_recipient(s.env.clone(), recipient.clone()).unwrap();
+    });
+    let updated = last_event(&s.env, "fee_recipient_updated_event").expect("config event");
+    assert_eq!(field::<Address>(&s.env, &updated, "fee_recipient"), recipient);
+}
+
+#[test]
+fn keeper_executes_ready_pool_for_fee_share() {
+    let s = setup();
+    let keeper = Address::generate(&s.env);
+    let execute_pool = || s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::execute_pool(s.env.clone(), s.pool, keeper.clone(), u64::MAX)
+    });
+    let set_limits = |min: u32| s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_pool_size_limits(s.env.clone(), s.pool, min, 5).unwrap();
+    });
+
+    // Raise the minimum so deposits queue instead of executing on arrival
+    set_limits(4);
+    for seed in 1..=3u8 {
+        swap(&s, &Address::generate(&s.env), note(&s.env, seed).0).unwrap();
+    }
+    assert_eq!(execute_pool(), Err(BatcherError::PoolNotReady));
+
+    set_limits(3);
+    assert_eq!(execute_pool(), Ok(0));
+    assert_eq!(s.env.auths()[0].0, keeper);
+    let reward = 3 * FEE * 1_000 / 10_000;
+    let rewarded = last_event(&s.env, "keeper_rewarded_event").expect("reward event");
+    assert_eq!(field::<Address>(&s.env, &rewarded, "keeper"), keeper);
+    assert_eq!(field::<i128>(&s.env, &rewarded, "amount"), reward);
+    assert_eq!(pool_size(&s), 0);
+
+    assert_eq!(TokenClient::new(&s.env, &s.token_in).balance(&keeper), reward);
+    let collected = s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::get_collected_fees(s.env.clone(), s.token_in.clone())
+    });
+    assert_eq!(collected, 3 * FEE - reward);
+
+    assert_eq!(execute_pool(), Err(BatcherError::PoolNotReady));
+}
+
+#[test]
+fn keeper_reward_is_capped_and_set_by_fee_manager() {
+    let s = setup();
+    let set_reward = |bps: u32| s.env.as_contract(&s.contract_id, || {
+        SoroSwapBatcher::set_keeper_reward_bps(s.env.clone(), bps)
+    });
+    let get_reward = || s.env.as_contract(&s.contract_id, || SoroSwapBatcher::get_keeper_reward_bps(s.env.clone()));
+    assert_eq!(get_reward(), 1_000);
+
+    assert_eq!(set_reward(5_001), Err(BatcherError::InvalidConfig));
+    assert_eq!(set_reward(2_500), Ok(()));
+    assert_eq!(s.env.auths()[0].0, s.owner);
+    let updated = last_event(&s.env, "keeper_reward_updated_event").expect("config event");
+    assert_eq!(field::<u32>(&s.env, &updated, "reward_bps"), 2_500);
+    assert_eq!(get_reward(), 2_500);
+}
@@ -0,0 +1,310 @@
+//! Owner-gated upgrades and schema migration.
+
+use soroban_sdk::{
+    symbol_short,
+    testutils::{storage::Persistent as _, Address as _, Ledger, MockAuth, MockAuthInvoke},
+    token::{Client as TokenClient, StellarAssetClient},
+    vec, Address, BytesN, Env, IntoVal, Vec,
+};
+
+use crate::{
+    coinjoin::{CoinJoinMixer, CoinJoinPool, Denomination},
+    upgrade::{CoinJoinPoolV0, CoinJoinPoolV1, DataKeyV0, DepositV0, DepositV1, MerkleTreeV1, SCHEMA_VERSION},
+    BatcherError, DataKey, SoroSwapBatcher, SoroSwapBatcherClient,
+};
+
+struct Setup {
+    env: Env,
+    contract_id: Address,
+    client: SoroSwapBatcherClient<'static>,
+    owner: Address,
+}
+
+fn setup() -> Setup {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let contract_id = env.register(SoroSwapBatcher, ());
+    let client = SoroSwapBatcherClient::new(&env, &contract_id);
+    client.initialize(&owner, &Address::generate(&env), &Address::generate(&env));
+
+    Setup { env, contract_id, client, owner }
+}
+
+#[test]
+fn initialize_records_current_schema() {
+    let s = setup();
+    assert_eq!(s.client.get_schema_version(), SCHEMA_VERSION);
+
+    assert_eq!(s.client.migrate(), SCHEMA_VERSION);
+    assert_eq!(s.env.auths()[0].0, s.owner);
+    assert_eq!(s.client.get_schema_version(), SCHEMA_VERSION);
+}
+
+#[test]
+fn unversioned_state_is_migrated_once() {
+    let s = setup();
+    s.env.as_contract(&s.contract_id, || {
+        s.env.storage().instance().remove(&DataKey::SchemaVersion);
+    });
+    assert_eq!(s.client.get_schema_version(), 0);
+
+    assert_eq!(s.client.migrate(), SCHEMA_VERSION);
+    assert_eq!(s.client.get_schema_version(), SCHEMA_VERSION);
+}
+
+#[test]
+fn newer_schema_is_rejected() {
+    let s = setup();
+    s.env.as_contract(&s.contract_id, || {
+        s.env.storage().instance().set(&DataKey::SchemaVersion, &(SCHEMA_VERSION + 1));
+    });
+
+    assert_eq!(s.client.try_migrate(), Err(Ok(BatcherError::UnsupportedSchemaVersion)));
+}
+
+#[test]
+fn only_owner_upgrades() {
+    let s = setup();
+    let intruder = Address::generate(&s.env);
+    let new_wasm_hash = BytesN::from_array(&s.env, &[7; 32]);
+
+    s.env.mock_auths(&[MockAuth {
+        address: &intruder,
+        invoke: &MockAuthInvoke {
+            contract: &s.contract_id,
+            fn_name: "upgrade",
+            args: (new_wasm_hash.clone(),).into_val(&s.env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert!(s.client.try_upgrade(&new_wasm_hash).is_err());
+
+    s.env.mock_auths(&[MockAuth {
+        address: &intruder,
+        invoke: &MockAuthInvoke {
+            contract: &s.contract_id,
+            fn_name: "migrate",
+            args: ().into_val(&s.env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert!(s.client.try_migrate().is_err());
+}
+
+#[test]
+fn instance_markers_move_to_persistent_entries_when_read() {
+    let s = setup();
+    let nullifier_hash = BytesN::from_array(&s.env, &[3; 32]);
+    let commitment = BytesN::from_array(&s.env, &[4; 32]);
+    let nullifier_key = DataKey::NullifierUsed(nullifier_hash.clone());
+    let commitment_key = DataKey::CommitmentUsed(commitment.clone());
+
+    // Earlier versions recorded spent nullifiers and used commitments in instance storage
+    s.env.as_contract(&s.contract_id, || {
+        s.env.storage().instance().set(&nullifier_key, &true);
+        s.env.storage().instance().set(&commitment_key, &true);
+    });
+
+    assert!(s.client.is_nullifier_used(&nullifier_hash));
+    s.env.as_contract(&s.contract_id, || {
+        assert!(CoinJoinMixer::is_commitment_used(&s.env, &commitment));
+        for key in [&nullifier_key, &commitment_key] {
+            assert!(!s.env.storage().instance().has(key));
+            assert_eq!(s.env.storage().persistent().get_ttl(key), s.env.storage().max_ttl());
+        }
+    });
+    assert!(s.client.is_nullifier_used(&nullifier_hash));
+    assert!(!s.client.is_nullifier_used(&BytesN::from_array(&s.env, &[5; 32])));
+}
+
+#[test]
+fn version_0_pools_refund_pending_deposits() {
+    let s = setup();
+    let token_in = s.env.register_stellar_asset_contract_v2(Address::generate(&s.env)).address();
+    let token_out = Address::generate(&s.env);
+    let senders = [Address::generate(&s.env), Address::generate(&s.env)];
+    StellarAssetClient::new(&s.env, &token_in).mint(&s.contract_id, &20_000_000);
+
+    // Seed the unversioned layout: one pool per denomination symbol with inline deposits
+    s.env.as_contract(&s.contract_id, || {
+        let mut deposits = Vec::new(&s.env);
+        for (seed, sender) in senders.iter().enumerate() {
+            deposits.push_back(DepositV0 {
+                commitment: BytesN::from_array(&s.env, &[seed as u8 + 1; 32]),
+                timestamp: 0,
+                nullifier: BytesN::from_array(&s.env, &[seed as u8 + 10; 32]),
+                fee_paid: 10_000,
+                sender_address: sender.clone(),
+                recipient_address: Address::generate(&s.env),
+                max_slippage_bps: 100,
+                expiry_timestamp: 48 * 60 * 60,
+                token_in: token_in.clone(),
+                token_out: token_out.clone(),
+                min_amount_out: 9_000_000,
+            });
+        }
+        let legacy = CoinJoinPoolV0 {
+            denomination: Denomination::Small,
+            deposits,
+            withdrawals: Vec::new(&s.env),
+            merkle_root: BytesN::from_array(&s.env, &[0; 32]),
+            minimum_pool_size: 3,
+            maximum_pool_size: 10,
+            fee_basis_points: 10,
+        };
+        let storage = s.env.storage().instance();
+        storage.set(&DataKeyV0::CoinJoinPool(symbol_short!("10")), &legacy);
+        storage.remove(&DataKey::BatchEnabled);
+        storage.remove(&DataKey::MulticallEnabled);
+        storage.remove(&DataKey::SchemaVersion);
+    });
+
+    assert_eq!(s.client.migrate(), SCHEMA_VERSION);
+    let token = TokenClient::new(&s.env, &token_in);
+    for sender in senders.iter() {
+        assert_eq!(token.balance(sender), 10_000_000);
+    }
+    assert_eq!(token.balance(&s.contract_id), 0);
+    s.env.as_contract(&s.contract_id, || {
+        let storage = s.env.storage().instance();
+        assert!(!storage.has(&DataKeyV0::CoinJoinPool(symbol_short!("10"))));
+        assert!(storage.has(&DataKey::BatchEnabled));
+        assert!(storage.has(&DataKey::MulticallEnabled));
+    });
+    assert_eq!(
+        s.client.try_get_pool_id(&token_in, &token_out, &symbol_short!("10")),
+        Err(Ok(BatcherError::InvalidInput))
+    );
+}
+
+#[test]
+fn version_1_pools_move_deposits_to_entries() {
+    let s = setup();
+    let admin = Address::generate(&s.env);
+    let token_in = s.env.register_stellar_asset_contract_v2(admin.clone()).address();
+    let token_out = s.env.register_stellar_asset_contract_v2(admin).address();
+    let pool_id = s.client.create_pool(&token_in, &token_out, &symbol_short!("10"));
+    let users = [Address::generate(&s.env), Address::generate(&s.env)];
+    for (seed, user) in users.iter().enumerate() {
+        StellarAssetClient::new(&s.env, &token_in).mint(user, &10_000_000);
+        s.client.private_swap(
+            &vec![&s.env, token_in.clone(), token_out.clone()],
+            &10_000_000,
+            &9_000_000,
+            &100,
+            user,
+            &BytesN::from_array(&s.env, &[seed as u8 + 1; 32]),
+        );
+    }
+
+    // Rewrite the pool in the version 1 layout, with its deposits held inline
+    let tree = s.env.as_contract(&s.contract_id, || {
+        let pool = CoinJoinMixer::get_pool(&s.env, pool_id).unwrap();
+        let mut deposits = Vec::new(&s.env);
+        for seq in 0..2 {
+            let deposit = CoinJoinMixer::get_deposit(&s.env, pool_id, seq).unwrap();
+            s.env.storage().persistent().remove(&DataKey::Deposit(pool_id, seq));
+            deposits.push_back(DepositV1 {
+                commitment: deposit.commitment,
+                timestamp: deposit.timestamp,
+                fee_paid: deposit.fee_paid,
+                sender_address: deposit.sender_address,
+                max_slippage_bps: deposit.max_slippage_bps,
+                expiry_timestamp: deposit.expiry_timestamp,
+                path: deposit.path,
+                min_amount_out: deposit.min_amount_out,
+            });
+        }
+        let legacy = CoinJoinPoolV1 {
+            pool_id,
+            token_in: pool.token_in,
+            token_out: pool.token_out,
+            denomination: pool.denomination,
+            deposits,
+            withdrawals: Vec::new(&s.env),
+            merkle_root: pool.merkle_root,
+            merkle_tree: MerkleTreeV1 {
+                next_index: pool.merkle_tree.next_index,
+                current_root_index: pool.merkle_tree.current_root_index,
+                roots: pool.merkle_tree.roots.clone(),
+            },
+            minimum_pool_size: pool.minimum_pool_size,
+            maximum_pool_size: pool.maximum_pool_size,
+            fee_basis_points: pool.fee_basis_points,
+            deposit_expiry_seconds: pool.deposit_expiry_seconds,
+            execution_mode: pool.execution_mode,
+        };
+        // Pools and their id lookups lived in instance storage until version 6
+        let id_key = DataKey::CoinJoinPoolId(token_in.clone(), token_out.clone(), symbol_short!("10"));
+        s.env.storage().persistent().remove(&DataKey::CoinJoinPool(pool_id));
+        s.env.storage().persistent().remove(&id_key);
+        s.env.storage().instance().set(&DataKey::CoinJoinPool(pool_id), &legacy);
+        s.env.storage().instance().set(&id_key, &pool_id);
+        s.env.storage().instance().set(&DataKey::SchemaVersion, &1u32);
+        pool.merkle_tree
+    });
+
+    assert_eq!(s.client.migrate(), SCHEMA_VERSION);
+    s.env.as_contract(&s.contract_id, || {
+        assert!(!s.env.storage().instance().has(&DataKey::CoinJoinPool(pool_id)));
+        let pool: CoinJoinPool = s.env.storage().persistent().get(&DataKey::CoinJoinPool(pool_id)).unwrap();
+        assert_eq!((pool.deposit_head, pool.deposit_tail, pool.pending_deposits), (0, 2, 2));
+        assert_eq!((pool.max_wait_seconds, pool.last_deposit_timestamp, pool.arrival_interval_seconds), (0, 0, 0));
+        assert_eq!(pool.merkle_tree.filled_subtrees, tree.filled_subtrees);
+        assert_eq!(pool.merkle_tree.root(), tree.root());
+    });
+    assert_eq!(s.client.get_pool_id(&token_in, &token_out, &symbol_short!("10")), pool_id);
+    assert_eq!(s.client.get_pending_deposits(&pool_id, &0, &10).len(), 2);
+
+    // Migrated deposits stay refundable
+    s.env.ledger().with_mut(|li| li.timestamp = 48 * 60 * 60);
+    assert_eq!(s.client.refund_expired(&pool_id), 2);
+    assert_eq!(s.client.get_deposit_range(&pool_id), (2, 2));
+}
+
+#[test]
+fn version_6_counters_move_to_persistent_entries() {
+    let s = setup();
+    let admin = Address::generate(&s.env);
+    let token_in = s.env.register_stellar_asset_contract_v2(admin.clone()).address();
+    let token_out = s.env.register_stellar_asset_contract_v2(admin).address();
+    let pools = [
+        s.client.create_pool(&token_in, &token_out, &symbol_short!("10")),
+        s.client.create_pool(&token_in, &token_out, &symbol_short!("100")),
+    ];
+
+    // Counters and collected fees lived in instance storage until version 7
+    let fees_key = DataKey::CollectedFees(token_in.clone());
+    s.env.as_contract(&s.contract_id, || {
+        for pool_id in pools {
+            s.env.storage().instance().set(&DataKey::SettledBatchCount(pool_id), &(pool_id as u64 + 2));
+            s.env.storage().instance().set(&DataKey::CoinJoinTotalDeposits(pool_id), &(pool_id as u64 + 7));
+            s.env.storage().instance().set(&DataKey::CoinJoinTotalWithdrawals(pool_id), &(pool_id as u64 + 5));
+        }
+        s.env.storage().instance().set(&fees_key, &30_000i128);
+        s.env.storage().instance().set(&DataKey::SchemaVersion, &6u32);
+    });
+
+    assert_eq!(s.client.migrate(), SCHEMA_VERSION);
+    for pool_id in pools {
+        assert_eq!(s.client.get_settled_batch_count(&pool_id), pool_id as u64 + 2);
+        let stats = s.client.get_pool_stats(&pool_id);
+        assert_eq!((stats.total_deposits, stats.total_withdrawals), (pool_id as u64 + 7, pool_id as u64 + 5));
+    }
+    assert_eq!(s.client.get_collected_fees(&token_in), 30_000);
+
+    s.env.as_contract(&s.contract_id, || {
+        let mut keys = vec![&s.env, fees_key.clone()];
+        for pool_id in pools {
+            keys.push_back(DataKey::SettledBatchCount(pool_id));
+            keys.push_back(DataKey::CoinJoinTotalDeposits(pool_id));
+            keys.push_back(DataKey::CoinJoinTotalWithdrawals(pool_id));
+        }
+        for key in keys.iter() {
+            assert!(!s.env.storage().instance().has(&key));
+            assert!(s.env.storage().persistent().get_ttl(&key) > 0);
+        }
+    });
+}
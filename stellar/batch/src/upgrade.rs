@@ -0,0 +1,541 @@
+// src/upgrade.rs
+// Contract upgrades and storage schema migration
+//
+// - `upgrade` swaps the contract's Wasm in place; the address, balances and storage are kept
+// - The storage layout is versioned by `SCHEMA_VERSION`; after installing code that changes
+//   the layout of `CoinJoinPool`, `Deposit` or other stored types, the owner calls `migrate`
+//   to rewrite existing entries before users interact with the new code
+
+use soroban_sdk::{
+    contractevent, contracttype, panic_with_error, token::Client as TokenClient, Address, BytesN, Env,
+    IntoVal, Symbol, TryFromVal, Val, Vec,
+};
+use crate::{
+    access::AccessControl,
+    batch_executor::BatchExecutor,
+    coinjoin::{CoinJoinMixer, CoinJoinPool, Denomination, Deposit, ExecutionMode},
+    error::BatcherError,
+    merkle::MerkleTree,
+    multicall::Multicall,
+    DataKey,
+};
+
+/// Storage layout version written by this build of the contract
+/// 0: one pool per denomination, keyed by its symbol, with contract-generated commitments
+/// 1: pools hold their pending deposits inline
+/// 2: pending deposits are persistent `DataKey::Deposit(pool_id, seq)` entries
+/// 3: pools carry a timed release window and their deposit arrival history
+/// 4: Merkle trees keep their filled subtrees instead of reading sibling nodes on insert
+/// 5: pools no longer keep a log of withdrawal requests
+/// 6: pools and pool id lookups are persistent entries instead of instance storage
+/// 7: per-pool counters and collected fees are persistent entries instead of instance storage
+pub const SCHEMA_VERSION: u32 = 7;
+
+/// Storage keys written by schema version 0 that were re-keyed by pool id in version 1
+/// Variant names match `DataKey`, so these encode to the same ledger keys
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKeyV0 {
+    CoinJoinPool(Symbol),
+}
+
+/// `CoinJoinPool` as stored by schema version 0, one per denomination
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct CoinJoinPoolV0 {
+    pub denomination: Denomination,
+    pub deposits: Vec<DepositV0>,
+    pub withdrawals: Vec<WithdrawalRequest>,
+    pub merkle_root: BytesN<32>,
+    pub minimum_pool_size: u32,
+    pub maximum_pool_size: u32,
+    pub fee_basis_points: u32,
+}
+
+/// `Deposit` as stored by schema version 0
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct DepositV0 {
+    pub commitment: BytesN<32>,
+    pub timestamp: u64,
+    pub nullifier: BytesN<32>,
+    pub fee_paid: i128,
+    pub sender_address: Address,
+    pub recipient_address: Address,
+    pub max_slippage_bps: u32,
+    pub expiry_timestamp: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub min_amount_out: i128,
+}
+
+/// `MerkleTree` as stored by schema versions 1 to 3
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct MerkleTreeV1 {
+    pub next_index: u32,
+    pub current_root_index: u32,
+    pub roots: Vec<BytesN<32>>,
+}
+
+/// `CoinJoinPool` as stored by schema version 1
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct CoinJoinPoolV1 {
+    pub pool_id: u32,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub denomination: Denomination,
+    pub deposits: Vec<DepositV1>,
+    pub withdrawals: Vec<WithdrawalRequest>,
+    pub merkle_root: BytesN<32>,
+    pub merkle_tree: MerkleTreeV1,
+    pub minimum_pool_size: u32,
+    pub maximum_pool_size: u32,
+    pub fee_basis_points: u32,
+    pub deposit_expiry_seconds: u64,
+    pub execution_mode: ExecutionMode,
+}
+
+/// `CoinJoinPool` as stored by schema version 2
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct CoinJoinPoolV2 {
+    pub pool_id: u32,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub denomination: Denomination,
+    pub deposit_head: u64,
+    pub deposit_tail: u64,
+    pub pending_deposits: u32,
+    pub withdrawals: Vec<WithdrawalRequest>,
+    pub merkle_root: BytesN<32>,
+    pub merkle_tree: MerkleTreeV1,
+    pub minimum_pool_size: u32,
+    pub maximum_pool_size: u32,
+    pub fee_basis_points: u32,
+    pub deposit_expiry_seconds: u64,
+    pub execution_mode: ExecutionMode,
+}
+
+/// `CoinJoinPool` as stored by schema version 3
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct CoinJoinPoolV3 {
+    pub pool_id: u32,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub denomination: Denomination,
+    pub deposit_head: u64,
+    pub deposit_tail: u64,
+    pub pending_deposits: u32,
+    pub withdrawals: Vec<WithdrawalRequest>,
+    pub merkle_root: BytesN<32>,
+    pub merkle_tree: MerkleTreeV1,
+    pub minimum_pool_size: u32,
+    pub maximum_pool_size: u32,
+    pub fee_basis_points: u32,
+    pub deposit_expiry_seconds: u64,
+    pub execution_mode: ExecutionMode,
+    pub max_wait_seconds: u64,
+    pub last_deposit_timestamp: u64,
+    pub arrival_interval_seconds: u64,
+}
+
+/// `CoinJoinPool` as stored by schema version 4
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct CoinJoinPoolV4 {
+    pub pool_id: u32,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub denomination: Denomination,
+    pub deposit_head: u64,
+    pub deposit_tail: u64,
+    pub pending_deposits: u32,
+    pub withdrawals: Vec<WithdrawalRequest>,
+    pub merkle_root: BytesN<32>,
+    pub merkle_tree: MerkleTree,
+    pub minimum_pool_size: u32,
+    pub maximum_pool_size: u32,
+    pub fee_basis_points: u32,
+    pub deposit_expiry_seconds: u64,
+    pub execution_mode: ExecutionMode,
+    pub max_wait_seconds: u64,
+    pub last_deposit_timestamp: u64,
+    pub arrival_interval_seconds: u64,
+}
+
+/// Withdrawal log entry kept by pools up to schema version 4
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct WithdrawalRequest {
+    pub nullifier_hash: BytesN<32>,
+    pub recipient_hash: BytesN<32>,
+    pub proof_hash: BytesN<32>,
+    pub requested_timestamp: u64,
+}
+
+/// `Deposit` as stored by schema version 1
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct DepositV1 {
+    pub commitment: BytesN<32>,
+    pub timestamp: u64,
+    pub fee_paid: i128,
+    pub sender_address: Address,
+    pub max_slippage_bps: u32,
+    pub expiry_timestamp: u64,
+    pub path: Vec<Address>,
+    pub min_amount_out: i128,
+}
+
+/// Emitted when the contract Wasm is replaced
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct ContractUpgradedEvent {
+    pub new_wasm_hash: BytesN<32>,
+    pub schema_version: u32,
+}
+
+/// Emitted when stored state is migrated to a newer schema
+#[contractevent]
+#[derive(Clone, Debug)]
+pub struct SchemaMigratedEvent {
+    pub from_version: u32,
+    pub to_version: u32,
+}
+
+pub struct Upgrade;
+
+impl Upgrade {
+    /// Record the schema version of a freshly initialized contract
+    pub fn init_schema(env: &Env) {
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+    }
+
+    /// Replace the contract Wasm with an already uploaded `new_wasm_hash` (owner only)
+    /// The new code takes effect once this invocation completes; call `migrate` afterwards
+    pub fn upgrade(env: &Env, new_wasm_hash: BytesN<32>) -> Result<(), BatcherError> {
+        AccessControl::require_owner(env)?;
+
+        ContractUpgradedEvent {
+            new_wasm_hash: new_wasm_hash.clone(),
+            schema_version: Self::get_schema_version(env),
+        }
+        .publish(env);
+        env.deployer().update_current_contract_wasm(new_wasm_hash);
+        Ok(())
+    }
+
+    /// Bring stored state up to `SCHEMA_VERSION` (owner only)
+    /// Runs each pending step in order and returns the resulting version; a no-op when current
+    pub fn migrate(env: &Env) -> Result<u32, BatcherError> {
+        AccessControl::require_owner(env)?;
+
+        let from_version = Self::get_schema_version(env);
+        if from_version > SCHEMA_VERSION {
+            return Err(BatcherError::UnsupportedSchemaVersion);
+        }
+
+        for version in from_version..SCHEMA_VERSION {
+            Self::migrate_step(env, version);
+        }
+
+        if from_version < SCHEMA_VERSION {
+            env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+            SchemaMigratedEvent { from_version, to_version: SCHEMA_VERSION }.publish(env);
+        }
+        Ok(SCHEMA_VERSION)
+    }
+
+    /// Schema version of the stored state; 0 for deployments that predate versioning
+    pub fn get_schema_version(env: &Env) -> u32 {
+        env.storage().instance()
+            .get(&DataKey::SchemaVersion)
+            .unwrap_or(0)
+    }
+
+    /// Rewrite state stored under schema `version` into the layout of `version + 1`
+    /// A step must preserve every pending deposit, settled batch and collected fee so
+    /// that funds held by the contract stay claimable under the new code
+    fn migrate_step(env: &Env, version: u32) {
+        match version {
+            0 => Self::refund_unversioned_deposits(env),
+            1 => Self::move_deposits_to_persistent_entries(env),
+            2 => Self::add_release_timing(env),
+            3 => Self::add_filled_subtrees(env),
+            4 => Self::drop_withdrawal_log(env),
+            5 => Self::move_pools_to_persistent_entries(env),
+            6 => Self::move_counters_to_persistent_entries(env),
+            _ => panic_with_error!(env, BatcherError::UnsupportedSchemaVersion),
+        }
+    }
+
+    /// 0 -> 1: return every pending deposit of the per-denomination pools to its sender
+    /// and drop those pools; their commitments were generated by the contract rather than
+    /// from a note the depositor holds, so they could never be withdrawn from a pool id
+    /// keyed pool. Pools are then created per token pair by an admin. Also initializes the
+    /// batch executor and multicall, which unversioned deployments did not set up
+    fn refund_unversioned_deposits(env: &Env) {
+        let this = env.current_contract_address();
+        let denominations = [
+            Denomination::Small,
+            Denomination::Medium,
+            Denomination::Large,
+            Denomination::ExtraLarge,
+        ];
+
+        for denomination in denominations {
+            let key = DataKeyV0::CoinJoinPool(denomination.symbol());
+            let Some(legacy) = env.storage().instance().get::<_, CoinJoinPoolV0>(&key) else {
+                continue;
+            };
+
+            // Each deposit transferred exactly the denomination amount of its input token
+            for deposit in legacy.deposits.iter() {
+                TokenClient::new(env, &deposit.token_in)
+                    .transfer(&this, &deposit.sender_address, &denomination.value());
+            }
+
+            env.storage().instance().remove(&key);
+        }
+
+        if !env.storage().instance().has(&DataKey::BatchEnabled) {
+            BatchExecutor::init_batch_executor(env, true, 10)
+                .unwrap_or_else(|e| panic_with_error!(env, e));
+        }
+        if !env.storage().instance().has(&DataKey::MulticallEnabled) {
+            Multicall::init_multicall(env, false)
+                .unwrap_or_else(|e| panic_with_error!(env, e));
+        }
+    }
+
+    /// 1 -> 2: move each pool's inline deposits into per-deposit persistent entries,
+    /// numbered by the Merkle leaf index assigned when they were made
+    fn move_deposits_to_persistent_entries(env: &Env) {
+        let pool_count: u32 = env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0);
+
+        for pool_id in 0..pool_count {
+            let key = DataKey::CoinJoinPool(pool_id);
+            let Some(legacy) = env.storage().instance().get::<_, CoinJoinPoolV1>(&key) else {
+                continue;
+            };
+
+            let next_seq = legacy.merkle_tree.next_index as u64;
+            let mut pool = CoinJoinPoolV2 {
+                pool_id,
+                token_in: legacy.token_in,
+                token_out: legacy.token_out,
+                denomination: legacy.denomination,
+                deposit_head: next_seq,
+                deposit_tail: next_seq,
+                pending_deposits: 0,
+                withdrawals: legacy.withdrawals,
+                merkle_root: legacy.merkle_root,
+                merkle_tree: legacy.merkle_tree,
+                minimum_pool_size: legacy.minimum_pool_size,
+                maximum_pool_size: legacy.maximum_pool_size,
+                fee_basis_points: legacy.fee_basis_points,
+                deposit_expiry_seconds: legacy.deposit_expiry_seconds,
+                execution_mode: legacy.execution_mode,
+            };
+
+            // Inline deposits are in insertion order, so the first one becomes the head
+            for deposit in legacy.deposits.iter() {
+                let seq = CoinJoinMixer::get_leaf_index(env, &deposit.commitment)
+                    .unwrap_or_else(|e| panic_with_error!(env, e)) as u64;
+                let deposit = Deposit {
+                    seq,
+                    commitment: deposit.commitment,
+                    timestamp: deposit.timestamp,
+                    fee_paid: deposit.fee_paid,
+                    sender_address: deposit.sender_address,
+                    max_slippage_bps: deposit.max_slippage_bps,
+                    expiry_timestamp: deposit.expiry_timestamp,
+                    path: deposit.path,
+                    min_amount_out: deposit.min_amount_out,
+                };
+                if pool.pending_deposits == 0 {
+                    pool.deposit_head = seq;
+                }
+                pool.pending_deposits += 1;
+                CoinJoinMixer::store_deposit(env, pool_id, &deposit);
+            }
+
+            env.storage().instance().set(&key, &pool);
+        }
+    }
+
+    /// 2 -> 3: add the timed release window, disabled, and seed each pool's arrival history
+    /// from its oldest and newest pending deposits; sequence numbers count every deposit
+    /// taken in between, so their span gives the average arrival interval
+    fn add_release_timing(env: &Env) {
+        let pool_count: u32 = env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0);
+
+        for pool_id in 0..pool_count {
+            let key = DataKey::CoinJoinPool(pool_id);
+            let Some(legacy) = env.storage().instance().get::<_, CoinJoinPoolV2>(&key) else {
+                continue;
+            };
+
+            let oldest = CoinJoinMixer::get_deposit(env, pool_id, legacy.deposit_head);
+            let newest = CoinJoinMixer::get_deposit(env, pool_id, legacy.deposit_tail.saturating_sub(1));
+            let (last_deposit_timestamp, arrival_interval_seconds) = match (oldest, newest) {
+                (Some(oldest), Some(newest)) if newest.seq > oldest.seq => (
+                    newest.timestamp,
+                    (newest.timestamp - oldest.timestamp) / (newest.seq - oldest.seq),
+                ),
+                (_, Some(newest)) => (newest.timestamp, 0),
+                // Without a pending newest deposit, measure the next gap from the migration
+                _ => (env.ledger().timestamp(), 0),
+            };
+
+            let pool = CoinJoinPoolV3 {
+                pool_id,
+                token_in: legacy.token_in,
+                token_out: legacy.token_out,
+                denomination: legacy.denomination,
+                deposit_head: legacy.deposit_head,
+                deposit_tail: legacy.deposit_tail,
+                pending_deposits: legacy.pending_deposits,
+                withdrawals: legacy.withdrawals,
+                merkle_root: legacy.merkle_root,
+                merkle_tree: legacy.merkle_tree,
+                minimum_pool_size: legacy.minimum_pool_size,
+                maximum_pool_size: legacy.maximum_pool_size,
+                fee_basis_points: legacy.fee_basis_points,
+                deposit_expiry_seconds: legacy.deposit_expiry_seconds,
+                execution_mode: legacy.execution_mode,
+                max_wait_seconds: 0,
+                last_deposit_timestamp,
+                arrival_interval_seconds,
+            };
+            env.storage().instance().set(&key, &pool);
+        }
+    }
+
+    /// 3 -> 4: rebuild each pool tree's filled subtrees from its stored path nodes
+    fn add_filled_subtrees(env: &Env) {
+        let pool_count: u32 = env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0);
+
+        for pool_id in 0..pool_count {
+            let key = DataKey::CoinJoinPool(pool_id);
+            let Some(legacy) = env.storage().instance().get::<_, CoinJoinPoolV3>(&key) else {
+                continue;
+            };
+
+            let merkle_tree = MerkleTree::from_stored_nodes(
+                env,
+                pool_id,
+                legacy.merkle_tree.next_index,
+                legacy.merkle_tree.current_root_index,
+                legacy.merkle_tree.roots,
+            );
+            let pool = CoinJoinPoolV4 {
+                pool_id,
+                token_in: legacy.token_in,
+                token_out: legacy.token_out,
+                denomination: legacy.denomination,
+                deposit_head: legacy.deposit_head,
+                deposit_tail: legacy.deposit_tail,
+                pending_deposits: legacy.pending_deposits,
+                withdrawals: legacy.withdrawals,
+                merkle_root: legacy.merkle_root,
+                merkle_tree,
+                minimum_pool_size: legacy.minimum_pool_size,
+                maximum_pool_size: legacy.maximum_pool_size,
+                fee_basis_points: legacy.fee_basis_points,
+                deposit_expiry_seconds: legacy.deposit_expiry_seconds,
+                execution_mode: legacy.execution_mode,
+                max_wait_seconds: legacy.max_wait_seconds,
+                last_deposit_timestamp: legacy.last_deposit_timestamp,
+                arrival_interval_seconds: legacy.arrival_interval_seconds,
+            };
+            env.storage().instance().set(&key, &pool);
+        }
+    }
+
+    /// 4 -> 5: drop each pool's withdrawal log; spent nullifiers are tracked on their own
+    fn drop_withdrawal_log(env: &Env) {
+        let pool_count: u32 = env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0);
+
+        for pool_id in 0..pool_count {
+            let key = DataKey::CoinJoinPool(pool_id);
+            let Some(legacy) = env.storage().instance().get::<_, CoinJoinPoolV4>(&key) else {
+                continue;
+            };
+
+            let pool = CoinJoinPool {
+                pool_id,
+                token_in: legacy.token_in,
+                token_out: legacy.token_out,
+                denomination: legacy.denomination,
+                deposit_head: legacy.deposit_head,
+                deposit_tail: legacy.deposit_tail,
+                pending_deposits: legacy.pending_deposits,
+                merkle_root: legacy.merkle_root,
+                merkle_tree: legacy.merkle_tree,
+                minimum_pool_size: legacy.minimum_pool_size,
+                maximum_pool_size: legacy.maximum_pool_size,
+                fee_basis_points: legacy.fee_basis_points,
+                deposit_expiry_seconds: legacy.deposit_expiry_seconds,
+                execution_mode: legacy.execution_mode,
+                max_wait_seconds: legacy.max_wait_seconds,
+                last_deposit_timestamp: legacy.last_deposit_timestamp,
+                arrival_interval_seconds: legacy.arrival_interval_seconds,
+            };
+            env.storage().instance().set(&key, &pool);
+        }
+    }
+
+    /// 5 -> 6: move each pool and its pool id lookup out of instance storage into
+    /// persistent entries, so the instance no longer grows with the number of pools
+    fn move_pools_to_persistent_entries(env: &Env) {
+        let pool_count: u32 = env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0);
+
+        for pool_id in 0..pool_count {
+            let key = DataKey::CoinJoinPool(pool_id);
+            let Some(pool) = env.storage().instance().get::<_, CoinJoinPool>(&key) else {
+                continue;
+            };
+            env.storage().instance().remove(&key);
+
+            env.storage().instance().remove(&DataKey::CoinJoinPoolId(
+                pool.token_in.clone(),
+                pool.token_out.clone(),
+                pool.denomination.symbol(),
+            ));
+            CoinJoinMixer::set_pool_id(env, &pool.token_in, &pool.token_out, pool.denomination, pool_id);
+            CoinJoinMixer::update_pool(env, pool_id, pool)
+                .unwrap_or_else(|e| panic_with_error!(env, e));
+        }
+    }
+
+    /// 6 -> 7: move each pool's settled batch, deposit and withdrawal counters and the
+    /// fees collected in its input token into persistent entries next to the pool
+    /// Fees are only ever collected in a pool's input token, so the pools cover them all
+    fn move_counters_to_persistent_entries(env: &Env) {
+        let pool_count: u32 = env.storage().instance().get(&DataKey::CoinJoinPoolCount).unwrap_or(0);
+
+        for pool_id in 0..pool_count {
+            let Ok(pool) = CoinJoinMixer::get_pool(env, pool_id) else {
+                continue;
+            };
+            Self::move_counter::<u64>(env, &DataKey::SettledBatchCount(pool_id));
+            Self::move_counter::<u64>(env, &DataKey::CoinJoinTotalDeposits(pool_id));
+            Self::move_counter::<u64>(env, &DataKey::CoinJoinTotalWithdrawals(pool_id));
+            Self::move_counter::<i128>(env, &DataKey::CollectedFees(pool.token_in));
+        }
+    }
+
+    /// Move one instance counter to its persistent entry; pools sharing an input
+    /// token reach the same fee key, which only the first of them moves
+    fn move_counter<V: TryFromVal<Env, Val> + IntoVal<Env, Val>>(env: &Env, key: &DataKey) {
+        let Some(value) = env.storage().instance().get::<_, V>(key) else {
+            return;
+        };
+        env.storage().instance().remove(key);
+        CoinJoinMixer::set_counter(env, key, &value);
+    }
+}